@@ -0,0 +1,167 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Clipboard access. This is only implemented for the X11 engines, where it
+//! uses the X selection mechanism directly.
+
+use std::ptr;
+
+use ffi;
+use Window;
+
+/// Receives the contents of the clipboard requested with
+/// `Window::request_clipboard_text`. This is called at most once.
+pub trait ClipboardCallback {
+    fn call(&self, Option<String>);
+}
+
+/// The clipboard state associated with a window
+pub struct ClipboardState {
+    /// Callbacks waiting for the clipboard contents to arrive
+    pending: Vec<Box<ClipboardCallback>>,
+    /// The most recently set or received clipboard text
+    text: Option<String>,
+    /// The handler for the selection notification event, if registered
+    handler: *mut ffi::Ecore_Event_Handler,
+}
+
+impl ClipboardState {
+    pub fn new() -> ClipboardState {
+        ClipboardState {
+            pending: Vec::new(),
+            text: None,
+            handler: ptr::mut_null(),
+        }
+    }
+}
+
+impl Drop for ClipboardState {
+    fn drop(&mut self) {
+        if !self.handler.is_null() {
+            unsafe { ffi::ecore_event_handler_del(self.handler) };
+        }
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Place text on the clipboard. Fails if the engine has no clipboard
+    /// support, which is currently the case for anything other than X11.
+    pub fn set_clipboard_text(&self, text: &str) -> Result<(), ()> {
+        try!(imp::set_clipboard_text(self, text));
+        self.clipboard.borrow_mut().text = Some(text.to_string());
+        Ok(())
+    }
+
+    /// The clipboard text most recently set with `set_clipboard_text` or
+    /// received by `request_clipboard_text`. Clipboard contents owned by
+    /// other applications have to be requested asynchronously, so this does
+    /// not reflect changes made elsewhere until they are requested.
+    pub fn clipboard_text(&self) -> Option<String> {
+        self.clipboard.borrow().text.clone()
+    }
+
+    /// Request the current clipboard text. The callback is run from the main
+    /// loop once the owner of the clipboard responds, with `None` if the
+    /// clipboard does not contain text. If the engine has no clipboard
+    /// support it is run immediately with `None`.
+    pub fn request_clipboard_text(&mut self, callback: Box<ClipboardCallback>) {
+        if imp::request_clipboard_text(self).is_ok() {
+            self.clipboard.borrow_mut().pending.push(callback);
+        } else {
+            callback.call(None);
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use libc;
+    use std::mem;
+
+    use ffi;
+    use ffi::x11;
+    use Window;
+
+    pub fn set_clipboard_text(window: &Window, text: &str) -> Result<(), ()> {
+        let win = match window.x11_window() {
+            Some(win) => win as x11::Ecore_X_Window,
+            None => return Err(()),
+        };
+        // The selection is served from a copy of the data, so it doesn't need
+        // to outlive this call
+        let ok = text.with_c_str(|text| unsafe {
+            x11::ecore_x_selection_clipboard_set(win, text as *const _, (libc::strlen(text) + 1) as libc::c_int)
+        });
+        if ffi::from_eina_bool(ok) { Ok(()) } else { Err(()) }
+    }
+
+    pub fn request_clipboard_text(window: &mut Window) -> Result<(), ()> {
+        let win = match window.x11_window() {
+            Some(win) => win as x11::Ecore_X_Window,
+            None => return Err(()),
+        };
+        unsafe {
+            let mut clipboard = window.clipboard.borrow_mut();
+            if clipboard.handler.is_null() {
+                clipboard.handler = ffi::ecore_event_handler_add(
+                    x11::ECORE_X_EVENT_SELECTION_NOTIFY, Some(selection_notify), window.data_ptr(),
+                );
+            }
+            x11::ECORE_X_SELECTION_TARGET_UTF8_STRING.with_c_str(|target| {
+                x11::ecore_x_selection_clipboard_request(win, target)
+            });
+        }
+        Ok(())
+    }
+
+    extern "C" fn selection_notify(data: *mut libc::c_void, _type: libc::c_int,
+                                   event: *mut libc::c_void) -> ffi::Eina_Bool {
+        ::guard_callback("selection_notify", ffi::EINA_TRUE, || {
+            unsafe {
+                let window = &Window::from_data_ptr(data as *const _);
+                let event = &*(event as *const x11::Ecore_X_Event_Selection_Notify);
+                if event.selection != x11::ECORE_X_SELECTION_CLIPBOARD ||
+                        Some(event.win as u32) != window.x11_window() {
+                    return ffi::EINA_TRUE;
+                }
+                let selection = event.data as *const x11::Ecore_X_Selection_Data;
+                let text = if !selection.is_null() && (*selection).content == x11::ECORE_X_SELECTION_CONTENT_TEXT {
+                    ffi::string_from_c_ptr((*(selection as *const x11::Ecore_X_Selection_Data_Text)).text as *const _)
+                } else {
+                    None
+                };
+                // Release the borrow before running the callbacks, as they may
+                // make another request
+                let pending = {
+                    let mut clipboard = window.clipboard.borrow_mut();
+                    clipboard.text = text.clone();
+                    mem::replace(&mut clipboard.pending, Vec::new())
+                };
+                for callback in pending.iter() {
+                    callback.call(text.clone());
+                }
+            }
+            // Let other handlers see the event too
+            ffi::EINA_TRUE
+        })
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod imp {
+    use Window;
+
+    pub fn set_clipboard_text(_window: &Window, _text: &str) -> Result<(), ()> {
+        Err(())
+    }
+
+    pub fn request_clipboard_text(_window: &mut Window) -> Result<(), ()> {
+        Err(())
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Coalescing of mouse move events, so that a slow handler only sees the
+//! latest pointer position.
+
+use libc;
+use std::cell::Cell;
+use std::ptr;
+
+use ffi;
+use {bound_window, EventPropagate, InputMouseMove, MouseMove, Window};
+
+/// The mouse move coalescing state associated with a window
+pub struct CoalesceState {
+    enabled: bool,
+    /// The latest mouse move, waiting to be delivered
+    pending: Cell<Option<ffi::Evas_Event_Mouse_Move>>,
+    /// The job that delivers the pending mouse move
+    job: Cell<*mut ffi::Ecore_Job>,
+}
+
+impl CoalesceState {
+    pub fn new() -> CoalesceState {
+        CoalesceState {
+            enabled: false,
+            pending: Cell::new(None),
+            job: Cell::new(ptr::mut_null()),
+        }
+    }
+
+    pub fn cancel_job(&self) {
+        let job = self.job.get();
+        if !job.is_null() {
+            unsafe { ffi::ecore_job_del(job) };
+            self.job.set(ptr::mut_null());
+        }
+    }
+}
+
+impl Drop for CoalesceState {
+    fn drop(&mut self) {
+        self.cancel_job();
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Coalesce mouse move events. While enabled, the mouse moves received
+    /// in one iteration of the main loop are delivered as a single event,
+    /// once the loop has processed all pending events. Its `cur` position is
+    /// that of the latest move, and its `prev` position that of the first,
+    /// so the motion over the whole iteration can still be computed. The
+    /// intermediate positions are dropped.
+    ///
+    /// Any other input event flushes a pending move first, so events are
+    /// still delivered in the order they happened. Disabling coalescing
+    /// delivers a pending move immediately. Disabled by default.
+    pub fn set_mouse_move_coalescing(&mut self, on: bool) {
+        if !on {
+            flush(self);
+        }
+        self.coalesce.enabled = on;
+    }
+
+    pub fn mouse_move_coalescing(&self) -> bool {
+        self.coalesce.enabled
+    }
+}
+
+/// Hold back a mouse move event if coalescing is enabled, or flush the
+/// pending one before any other event is delivered. Returns `true` if the
+/// event was held back.
+pub unsafe fn defer(window: &Window, callback_type: ffi::Evas_Callback_Type, info: *const libc::c_void) -> bool {
+    let state = &window.coalesce;
+    if callback_type != ffi::EVAS_CALLBACK_MOUSE_MOVE {
+        flush(window);
+        return false;
+    }
+    if !state.enabled {
+        return false;
+    }
+    let mut info = *(info as *const ffi::Evas_Event_Mouse_Move);
+    for pending in state.pending.get().iter() {
+        info.prev = pending.prev;
+    }
+    state.pending.set(Some(info));
+    if state.job.get().is_null() {
+        state.job.set(ffi::ecore_job_add(Some(flush_job), window.ee as *const _));
+    }
+    true
+}
+
+/// Deliver the pending mouse move, if there is one.
+pub fn flush(window: &Window) {
+    window.coalesce.cancel_job();
+    let info = match window.coalesce.pending.get() {
+        Some(info) => info,
+        None => return,
+    };
+    window.coalesce.pending.set(None);
+    let callbacks = &window.input_callbacks;
+    // The original event is gone, so there is nothing to mark handled
+    callbacks.mouse_move.with(EventPropagate, |callback| {
+        callback.respond(window, &MouseMove { ptr: &info as *const _ })
+    });
+    callbacks.handler.with((), |handler| {
+        handler.call(window, InputMouseMove(MouseMove { ptr: &info as *const _ }))
+    });
+}
+
+extern "C" fn flush_job(data: *mut libc::c_void) {
+    ::guard_callback("flush_job", (), || {
+        unsafe {
+            for window in bound_window(data as *const _).iter() {
+                // The job is freed once it has run
+                window.coalesce.job.set(ptr::mut_null());
+                flush(window);
+            }
+        }
+    })
+}
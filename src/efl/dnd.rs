@@ -0,0 +1,296 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Drag and drop. This is only implemented for the X11 engines, using the
+//! XDND protocol, and only accepts dropped files.
+
+use std::mem;
+
+use ffi;
+use Window;
+
+/// Called when files are dropped onto a window, with the paths of the files
+/// and the position of the drop relative to the window.
+pub trait DropCallback {
+    fn call(&self, &Window, Vec<Path>, i32, i32);
+}
+
+/// The drag and drop state associated with a window
+pub struct DndState {
+    callback: Option<Box<DropCallback>>,
+    /// Whether the drag over the window offers a list of files
+    offers_files: bool,
+    /// The position of the drop that is waiting for its data to arrive
+    drop_position: Option<(i32, i32)>,
+    /// The handlers registered for the XDND events
+    handlers: Vec<*mut ffi::Ecore_Event_Handler>,
+}
+
+impl DndState {
+    pub fn new() -> DndState {
+        DndState {
+            callback: None,
+            offers_files: false,
+            drop_position: None,
+            handlers: Vec::new(),
+        }
+    }
+
+    fn unregister(&mut self) {
+        for &handler in self.handlers.iter() {
+            unsafe { ffi::ecore_event_handler_del(handler) };
+        }
+        self.handlers.clear();
+    }
+}
+
+impl Drop for DndState {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Set the callback run when files are dropped onto the window. Other
+    /// kinds of drops are refused. Only the X11 engines support drag and
+    /// drop; with other engines the callback is never run.
+    pub fn set_drop_callback(&mut self, callback: Box<DropCallback>) -> Option<Box<DropCallback>> {
+        if self.dnd.handlers.is_empty() {
+            imp::register(self);
+        }
+        mem::replace(&mut self.dnd.callback, Some(callback))
+    }
+
+    pub fn unset_drop_callback(&mut self) -> Option<Box<DropCallback>> {
+        imp::unregister(self);
+        self.dnd.callback.take()
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use libc;
+
+    use ffi;
+    use ffi::x11;
+    use Window;
+
+    pub fn register(window: &mut Window) {
+        let win = match window.x11_window() {
+            Some(win) => win as x11::Ecore_X_Window,
+            None => return,
+        };
+        unsafe {
+            x11::ecore_x_dnd_aware_set(win, ffi::EINA_TRUE);
+            let data = window.data_ptr();
+            window.dnd.handlers = vec![
+                ffi::ecore_event_handler_add(x11::ECORE_X_EVENT_XDND_ENTER, Some(xdnd_enter), data),
+                ffi::ecore_event_handler_add(x11::ECORE_X_EVENT_XDND_POSITION, Some(xdnd_position), data),
+                ffi::ecore_event_handler_add(x11::ECORE_X_EVENT_XDND_DROP, Some(xdnd_drop), data),
+                ffi::ecore_event_handler_add(x11::ECORE_X_EVENT_SELECTION_NOTIFY, Some(selection_notify), data),
+            ];
+        }
+    }
+
+    pub fn unregister(window: &mut Window) {
+        for win in window.x11_window().iter() {
+            unsafe { x11::ecore_x_dnd_aware_set(*win as x11::Ecore_X_Window, ffi::EINA_FALSE) };
+        }
+        window.dnd.unregister();
+    }
+
+    /// Note whether a drag entering the window offers a list of files
+    extern "C" fn xdnd_enter(data: *mut libc::c_void, _type: libc::c_int,
+                             event: *mut libc::c_void) -> ffi::Eina_Bool {
+        ::guard_callback("xdnd_enter", ffi::EINA_TRUE, || {
+            unsafe {
+                let window = &mut Window::from_data_ptr(data as *const _);
+                let event = &*(event as *const x11::Ecore_X_Event_Xdnd_Enter);
+                if Some(event.win as u32) == window.x11_window() {
+                    window.dnd.offers_files = range(0, event.num_types as int).any(|i| {
+                        ffi::string_from_c_ptr(*event.types.offset(i) as *const _).map_or(false, |t| {
+                            t.as_slice() == x11::ECORE_X_SELECTION_TARGET_URI_LIST
+                        })
+                    });
+                }
+            }
+            ffi::EINA_TRUE
+        })
+    }
+
+    /// Accept drags that offer files as they move over the window, and
+    /// refuse the others
+    extern "C" fn xdnd_position(data: *mut libc::c_void, _type: libc::c_int,
+                                event: *mut libc::c_void) -> ffi::Eina_Bool {
+        ::guard_callback("xdnd_position", ffi::EINA_TRUE, || {
+            unsafe {
+                let window = &Window::from_data_ptr(data as *const _);
+                let event = &*(event as *const x11::Ecore_X_Event_Xdnd_Position);
+                if Some(event.win as u32) == window.x11_window() {
+                    let rect = x11::Ecore_X_Rectangle { x: 0, y: 0, width: 0, height: 0 };
+                    x11::ecore_x_dnd_send_status(ffi::to_eina_bool(window.dnd.offers_files), ffi::EINA_FALSE,
+                                                 rect, x11::ECORE_X_ATOM_XDND_ACTION_COPY);
+                }
+            }
+            ffi::EINA_TRUE
+        })
+    }
+
+    /// Ask for the dropped data as a list of URIs
+    extern "C" fn xdnd_drop(data: *mut libc::c_void, _type: libc::c_int,
+                            event: *mut libc::c_void) -> ffi::Eina_Bool {
+        ::guard_callback("xdnd_drop", ffi::EINA_TRUE, || {
+            unsafe {
+                let window = &mut Window::from_data_ptr(data as *const _);
+                let event = &*(event as *const x11::Ecore_X_Event_Xdnd_Drop);
+                if Some(event.win as u32) == window.x11_window() {
+                    // The drop position is relative to the root window
+                    let (x, y) = window.get_position();
+                    window.dnd.drop_position = Some((event.position.x as i32 - x, event.position.y as i32 - y));
+                    x11::ECORE_X_SELECTION_TARGET_URI_LIST.with_c_str(|target| {
+                        x11::ecore_x_selection_xdnd_request(event.win, target)
+                    });
+                }
+            }
+            ffi::EINA_TRUE
+        })
+    }
+
+    /// Deliver the dropped files once they arrive
+    extern "C" fn selection_notify(data: *mut libc::c_void, _type: libc::c_int,
+                                   event: *mut libc::c_void) -> ffi::Eina_Bool {
+        ::guard_callback("selection_notify", ffi::EINA_TRUE, || {
+            unsafe {
+                let window = &mut Window::from_data_ptr(data as *const _);
+                let event = &*(event as *const x11::Ecore_X_Event_Selection_Notify);
+                if event.selection != x11::ECORE_X_SELECTION_XDND ||
+                        Some(event.win as u32) != window.x11_window() {
+                    return ffi::EINA_TRUE;
+                }
+                let (x, y) = match window.dnd.drop_position.take() {
+                    Some(position) => position,
+                    None => return ffi::EINA_TRUE,
+                };
+                let selection = event.data as *const x11::Ecore_X_Selection_Data;
+                if !selection.is_null() && (*selection).content == x11::ECORE_X_SELECTION_CONTENT_FILES {
+                    let files = &*(selection as *const x11::Ecore_X_Selection_Data_Files);
+                    let paths = range(0, files.num_files as int).filter_map(|i| {
+                        ffi::string_from_c_ptr(*files.files.offset(i) as *const _)
+                    }).filter_map(|uri| super::uri_to_path(uri.as_slice())).collect();
+                    match window.dnd.callback {
+                        Some(ref callback) => callback.call(window, paths, x, y),
+                        None => {},
+                    }
+                }
+                x11::ecore_x_dnd_send_finished();
+            }
+            ffi::EINA_TRUE
+        })
+    }
+}
+
+/// The local path named by an entry of a `text/uri-list`. `file` URIs are
+/// percent-decoded, and must be on this host: either with no host, or with
+/// `localhost` or the name of this machine. Anything else is taken to be a
+/// plain path, as some applications drop those.
+fn uri_to_path(uri: &str) -> Option<Path> {
+    if !uri.starts_with("file://") {
+        return Some(Path::new(uri));
+    }
+    let rest = uri.slice_from("file://".len());
+    let (host, path) = match rest.find('/') {
+        Some(i) => (rest.slice_to(i), rest.slice_from(i)),
+        None => return None,
+    };
+    if !host.is_empty() && host != "localhost" && Some(host.to_string()) != hostname() {
+        return None;
+    }
+    percent_decode(path).map(|bytes| Path::new(bytes))
+}
+
+/// Decode the `%XX` escapes in a URI path, or any other percent-encoded
+/// string. Fails on a malformed escape.
+pub fn percent_decode(path: &str) -> Option<Vec<u8>> {
+    let bytes = path.as_bytes();
+    let digit = |b: u8| (b as char).to_digit(16);
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        if i + 2 >= bytes.len() { return None }
+        match (digit(bytes[i + 1]), digit(bytes[i + 2])) {
+            (Some(high), Some(low)) => decoded.push((high * 16 + low) as u8),
+            _ => return None,
+        }
+        i += 3;
+    }
+    Some(decoded)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn hostname() -> Option<String> {
+    let mut name = [0 as ::libc::c_char, ..256];
+    unsafe {
+        if gethostname(name.as_mut_ptr(), name.len() as ::libc::size_t) != 0 { return None }
+        name[name.len() - 1] = 0;
+        ffi::string_from_c_ptr(name.as_ptr())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+extern "C" {
+    fn gethostname(name: *mut ::libc::c_char, len: ::libc::size_t) -> ::libc::c_int;
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn hostname() -> Option<String> {
+    None
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod imp {
+    use Window;
+
+    pub fn register(_window: &mut Window) {}
+
+    pub fn unregister(window: &mut Window) {
+        window.dnd.unregister();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{percent_decode, uri_to_path};
+
+    #[test]
+    fn file_uris_are_decoded() {
+        assert_eq!(uri_to_path("file:///tmp/a%20b.txt"), Some(Path::new("/tmp/a b.txt")));
+        assert_eq!(uri_to_path("file://localhost/tmp/%C3%A9t%C3%A9"), Some(Path::new("/tmp/été")));
+        assert_eq!(uri_to_path("/tmp/plain"), Some(Path::new("/tmp/plain")));
+    }
+
+    #[test]
+    fn remote_and_malformed_uris_are_refused() {
+        assert_eq!(uri_to_path("file://elsewhere.invalid/tmp/a"), None);
+        assert_eq!(uri_to_path("file://"), None);
+        assert_eq!(uri_to_path("file:///tmp/100%"), None);
+        assert_eq!(uri_to_path("file:///tmp/%zz"), None);
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%2Fb%2fc"), Some(b"a/b/c".to_vec()));
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode(""), Some(Vec::new()));
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Wrappers around the ecore main loop facilities.
+
+use libc;
+use std::cell::Cell;
+use std::ptr;
+
+use ffi;
+use Context;
+
+/// The current value of ecore's monotonic clock, in seconds.
+pub fn time() -> f64 {
+    unsafe { ffi::ecore_time_get() as f64 }
+}
+
+/// The value of ecore's monotonic clock at the start of the current main loop
+/// iteration, in seconds. Everything handled during one iteration sees the
+/// same loop time, which makes it the right clock for animations.
+pub fn loop_time() -> f64 {
+    unsafe { ffi::ecore_loop_time_get() as f64 }
+}
+
+/// The interval between ticks of ecore's animators, in seconds.
+pub fn animator_frametime() -> f64 {
+    unsafe { ffi::ecore_animator_frametime_get() as f64 }
+}
+
+pub fn set_animator_frametime(frametime: f64) {
+    unsafe { ffi::ecore_animator_frametime_set(frametime as libc::c_double) };
+}
+
+/// Called by an idle enterer or exiter. Returning `false` removes it.
+pub trait IdleCallback {
+    fn call(&self) -> bool;
+}
+
+struct IdleEntry {
+    callback: Box<IdleCallback>,
+    /// The ecore handle, or null once the callback has removed itself
+    handle: Cell<*mut ffi::Ecore_Idle_Enterer>,
+}
+
+/// A callback run each time the main loop is about to go idle, after it has
+/// handled all pending events and timers. This is the point where ecore_evas
+/// renders, so it is the right hook for deferred layout and rendering. The
+/// callback is removed when this is dropped.
+#[must_use]
+pub struct IdleEnterer {
+    entry: Box<IdleEntry>,
+}
+
+impl IdleEnterer {
+    pub fn add(callback: Box<IdleCallback>) -> IdleEnterer {
+        let entry = box IdleEntry { callback: callback, handle: Cell::new(ptr::mut_null()) };
+        let handle = unsafe {
+            ffi::ecore_idle_enterer_add(Some(idle_enterer_callback), &*entry as *const IdleEntry as *const _)
+        };
+        entry.handle.set(handle);
+        IdleEnterer { entry: entry }
+    }
+
+    /// Whether the callback is still registered, which it stops being once
+    /// it returns `false`.
+    pub fn is_active(&self) -> bool {
+        !self.entry.handle.get().is_null()
+    }
+}
+
+impl Drop for IdleEnterer {
+    fn drop(&mut self) {
+        let handle = self.entry.handle.get();
+        if !handle.is_null() {
+            unsafe { ffi::ecore_idle_enterer_del(handle) };
+        }
+    }
+}
+
+extern "C" fn idle_enterer_callback(data: *mut libc::c_void) -> ffi::Eina_Bool {
+    ::guard_callback("idle_enterer_callback", ffi::EINA_TRUE, || {
+        let entry = unsafe { &*(data as *const IdleEntry) };
+        if entry.callback.call() {
+            ffi::EINA_TRUE
+        } else {
+            // Ecore deletes the enterer when the callback cancels it
+            entry.handle.set(ptr::mut_null());
+            ffi::EINA_FALSE
+        }
+    })
+}
+
+struct IdleExitEntry {
+    callback: Box<IdleCallback>,
+    /// The ecore handle, or null once the callback has removed itself
+    handle: Cell<*mut ffi::Ecore_Idle_Exiter>,
+}
+
+/// A callback run each time the main loop wakes up from being idle, before
+/// it handles the events that woke it. The callback is removed when this is
+/// dropped.
+#[must_use]
+pub struct IdleExiter {
+    entry: Box<IdleExitEntry>,
+}
+
+impl IdleExiter {
+    pub fn add(callback: Box<IdleCallback>) -> IdleExiter {
+        let entry = box IdleExitEntry { callback: callback, handle: Cell::new(ptr::mut_null()) };
+        let handle = unsafe {
+            ffi::ecore_idle_exiter_add(Some(idle_exiter_callback), &*entry as *const IdleExitEntry as *const _)
+        };
+        entry.handle.set(handle);
+        IdleExiter { entry: entry }
+    }
+
+    /// Whether the callback is still registered, which it stops being once
+    /// it returns `false`.
+    pub fn is_active(&self) -> bool {
+        !self.entry.handle.get().is_null()
+    }
+}
+
+impl Drop for IdleExiter {
+    fn drop(&mut self) {
+        let handle = self.entry.handle.get();
+        if !handle.is_null() {
+            unsafe { ffi::ecore_idle_exiter_del(handle) };
+        }
+    }
+}
+
+extern "C" fn idle_exiter_callback(data: *mut libc::c_void) -> ffi::Eina_Bool {
+    ::guard_callback("idle_exiter_callback", ffi::EINA_TRUE, || {
+        let entry = unsafe { &*(data as *const IdleExitEntry) };
+        if entry.callback.call() {
+            ffi::EINA_TRUE
+        } else {
+            // Ecore deletes the exiter when the callback cancels it
+            entry.handle.set(ptr::mut_null());
+            ffi::EINA_FALSE
+        }
+    })
+}
+
+/// Called with the event info of each ecore event a handler is registered
+/// for. Returning `false` stops the event from reaching handlers added
+/// after this one.
+pub trait EventHandlerCallback {
+    fn call(&self, event: *mut libc::c_void) -> bool;
+}
+
+/// A handler for one type of ecore event, such as those emitted by
+/// ecore_con or ecore_file. The event info is passed through as a raw
+/// pointer, since its type depends on the event. The handler is removed
+/// when this is dropped.
+#[must_use]
+pub struct EventHandler {
+    handler: *mut ffi::Ecore_Event_Handler,
+    /// The callback, pointed to by the ecore handler
+    _callback: Box<Box<EventHandlerCallback>>,
+}
+
+impl EventHandler {
+    pub fn add(event_type: libc::c_int, callback: Box<EventHandlerCallback>) -> EventHandler {
+        let callback = box callback;
+        let handler = unsafe {
+            ffi::ecore_event_handler_add(event_type, Some(event_handler_callback),
+                                         &*callback as *const Box<EventHandlerCallback> as *const _)
+        };
+        EventHandler { handler: handler, _callback: callback }
+    }
+}
+
+impl Drop for EventHandler {
+    fn drop(&mut self) {
+        if !self.handler.is_null() {
+            unsafe { ffi::ecore_event_handler_del(self.handler) };
+        }
+    }
+}
+
+extern "C" fn event_handler_callback(data: *mut libc::c_void, _type: libc::c_int,
+                                     event: *mut libc::c_void) -> ffi::Eina_Bool {
+    ::guard_callback("event_handler_callback", ffi::EINA_TRUE, || {
+        let callback = data as *const Box<EventHandlerCallback>;
+        ffi::to_eina_bool(unsafe { (*callback).call(event) })
+    })
+}
+
+/// The signal and system events that ecore emits by itself
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum SystemEvent {
+    /// `SIGUSR1` or `SIGUSR2`, as 1 or 2.
+    SignalUser(int),
+    SignalHup,
+    SignalInterrupt,
+    SignalQuit,
+    SignalTerminate,
+    SignalPower,
+    /// The system's locale settings changed.
+    LocaleChanged,
+    HostnameChanged,
+    /// The system time or time zone changed.
+    TimeChanged,
+}
+
+/// Called with each signal and system event. Returning `false` stops the
+/// event from reaching handlers added after this one.
+pub trait SystemEventCallback {
+    fn call(&self, SystemEvent) -> bool;
+}
+
+/// The system event types, in the order of `SystemEvent`, less the kinds of
+/// exit signal, which ecore reports as a single event type
+fn system_event_types() -> [libc::c_int, ..7] {
+    [
+        ffi::ECORE_EVENT_SIGNAL_USER,
+        ffi::ECORE_EVENT_SIGNAL_HUP,
+        ffi::ECORE_EVENT_SIGNAL_EXIT,
+        ffi::ECORE_EVENT_SIGNAL_POWER,
+        ffi::ECORE_EVENT_LOCALE_CHANGED,
+        ffi::ECORE_EVENT_HOSTNAME_CHANGED,
+        ffi::ECORE_EVENT_SYSTEM_TIMEDATE_CHANGED,
+    ]
+}
+
+/// A handler for all of the signal and system events, decoded into
+/// `SystemEvent`s. The handler is removed when this is dropped.
+#[must_use]
+pub struct SystemEventHandler {
+    handlers: Vec<*mut ffi::Ecore_Event_Handler>,
+    /// The callback, pointed to by the ecore handlers
+    _callback: Box<Box<SystemEventCallback>>,
+}
+
+impl SystemEventHandler {
+    pub fn add(callback: Box<SystemEventCallback>) -> SystemEventHandler {
+        let callback = box callback;
+        let data = &*callback as *const Box<SystemEventCallback> as *const libc::c_void;
+        let handlers = system_event_types().iter().map(|&event_type| unsafe {
+            ffi::ecore_event_handler_add(event_type, Some(system_event_callback), data)
+        }).collect();
+        SystemEventHandler { handlers: handlers, _callback: callback }
+    }
+}
+
+impl Drop for SystemEventHandler {
+    fn drop(&mut self) {
+        for &handler in self.handlers.iter() {
+            if !handler.is_null() {
+                unsafe { ffi::ecore_event_handler_del(handler) };
+            }
+        }
+    }
+}
+
+/// Decode a signal or system event
+unsafe fn system_event(event_type: libc::c_int, event: *mut libc::c_void) -> Option<SystemEvent> {
+    Some(if event_type == ffi::ECORE_EVENT_SIGNAL_USER {
+        SignalUser((*(event as *const ffi::Ecore_Event_Signal_User)).number as int)
+    } else if event_type == ffi::ECORE_EVENT_SIGNAL_HUP {
+        SignalHup
+    } else if event_type == ffi::ECORE_EVENT_SIGNAL_EXIT {
+        // The kind of exit is given by the bitfields `interrupt`, `quit` and
+        // `terminate`, which are the low bits of the first byte
+        let flags = *(event as *const u8);
+        if flags & 1 != 0 {
+            SignalInterrupt
+        } else if flags & 2 != 0 {
+            SignalQuit
+        } else {
+            SignalTerminate
+        }
+    } else if event_type == ffi::ECORE_EVENT_SIGNAL_POWER {
+        SignalPower
+    } else if event_type == ffi::ECORE_EVENT_LOCALE_CHANGED {
+        LocaleChanged
+    } else if event_type == ffi::ECORE_EVENT_HOSTNAME_CHANGED {
+        HostnameChanged
+    } else if event_type == ffi::ECORE_EVENT_SYSTEM_TIMEDATE_CHANGED {
+        TimeChanged
+    } else {
+        return None;
+    })
+}
+
+extern "C" fn system_event_callback(data: *mut libc::c_void, event_type: libc::c_int,
+                                    event: *mut libc::c_void) -> ffi::Eina_Bool {
+    ::guard_callback("system_event_callback", ffi::EINA_TRUE, || {
+        let callback = data as *const Box<SystemEventCallback>;
+        unsafe {
+            match system_event(event_type, event) {
+                Some(event) => ffi::to_eina_bool((*callback).call(event)),
+                None => ffi::EINA_TRUE,
+            }
+        }
+    })
+}
+
+/// The Unix signals that ecore turns into events
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Signal {
+    SigInt,
+    SigTerm,
+    SigQuit,
+    SigHup,
+    SigUsr1,
+    SigUsr2,
+}
+
+impl Signal {
+    fn from_event(event: &SystemEvent) -> Option<Signal> {
+        match *event {
+            SignalInterrupt => Some(SigInt),
+            SignalTerminate => Some(SigTerm),
+            SignalQuit => Some(SigQuit),
+            SignalHup => Some(SigHup),
+            SignalUser(1) => Some(SigUsr1),
+            SignalUser(2) => Some(SigUsr2),
+            _ => None,
+        }
+    }
+}
+
+/// Called from the main loop when a signal is received
+pub trait SignalCallback {
+    fn call(&self, Signal);
+}
+
+/// Passes the system events for one signal on to a signal callback
+struct SignalFilter {
+    signal: Signal,
+    callback: Box<SignalCallback>,
+}
+
+impl SystemEventCallback for SignalFilter {
+    fn call(&self, event: SystemEvent) -> bool {
+        if Signal::from_event(&event) == Some(self.signal.clone()) {
+            self.callback.call(self.signal.clone());
+        }
+        true
+    }
+}
+
+/// A handler for a signal, registered with `Context::on_signal`. The
+/// handler is removed when this is dropped.
+#[must_use]
+pub struct SignalHandler {
+    _handler: SystemEventHandler,
+}
+
+impl Context {
+    /// Run a callback from the main loop whenever `signal` is received.
+    /// Ecore catches the signal and delivers it as an event, so the callback
+    /// runs between iterations of the main loop rather than in a signal
+    /// handler, and can safely do anything, such as calling
+    /// `main_loop_quit` to shut down cleanly on `SigInt` instead of being
+    /// killed mid-render.
+    pub fn on_signal(&self, signal: Signal, callback: Box<SignalCallback>) -> SignalHandler {
+        let filter = box SignalFilter { signal: signal, callback: callback };
+        SignalHandler { _handler: SystemEventHandler::add(filter as Box<SystemEventCallback>) }
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding of rendered frames into image files in memory.
+
+use std::mem;
+
+use {Rect, Window};
+
+/// The image formats a frame can be encoded to
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum ImageCodec {
+    /// PNG, with an alpha channel. The image data is stored uncompressed,
+    /// so the encoding is fast but large.
+    CodecPng,
+    /// Binary PPM (`P6`). PPM has no alpha channel, so the frame is written
+    /// as if composited over black.
+    CodecPpm,
+}
+
+impl<'a> Window<'a> {
+    /// Encode the current frame to an image file in memory. The frame is
+    /// the whole canvas, read back with `read_pixels`. Fails if the window
+    /// is empty, or its pixels can't be read back.
+    pub fn encode_frame(&self, codec: ImageCodec) -> Result<Vec<u8>, ()> {
+        let (w, h) = self.get_size();
+        let bytes = try!(self.read_pixels(Rect::new(0, 0, w, h)).ok_or(()));
+        let pixels: Vec<u32> = bytes.as_slice().chunks(4).map(|p| {
+            unsafe { mem::transmute::<[u8, ..4], u32>([p[0], p[1], p[2], p[3]]) }
+        }).collect();
+        match codec {
+            CodecPng => encode_png(w as uint, h as uint, pixels.as_slice()),
+            CodecPpm => Ok(encode_ppm(w as uint, h as uint, pixels.as_slice())),
+        }
+    }
+}
+
+/// Split a premultiplied ARGB32 pixel into straight RGBA bytes
+fn unpremultiply(pixel: u32) -> [u8, ..4] {
+    let a = (pixel >> 24) as u8;
+    let channel = |shift: uint| {
+        let c = (pixel >> shift) & 0xff;
+        if a == 0 { 0 } else { ((c * 255 + a as u32 / 2) / a as u32).min(255) as u8 }
+    };
+    [channel(16), channel(8), channel(0), a]
+}
+
+fn encode_ppm(w: uint, h: uint, pixels: &[u32]) -> Vec<u8> {
+    let mut out = Vec::from_slice(format!("P6\n{} {}\n255\n", w, h).as_bytes());
+    for &pixel in pixels.iter() {
+        // Premultiplied colors are already composited over black
+        out.push_all([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+    }
+    out
+}
+
+fn push_u32_be(out: &mut Vec<u8>, n: u32) {
+    out.push_all([(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in range(0u, 8) {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data.iter() {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Append a PNG chunk, with its length and checksum
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    push_u32_be(out, data.len() as u32);
+    let start = out.len();
+    out.push_all(kind);
+    out.push_all(data);
+    let crc = crc32(out.slice_from(start));
+    push_u32_be(out, crc);
+}
+
+/// Wrap data in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.is_empty() {
+        out.push_all([1, 0, 0, 0xff, 0xff]);
+    }
+    loop {
+        let block = match blocks.next() {
+            Some(block) => block,
+            None => break,
+        };
+        let last = if blocks.is_empty() { 1 } else { 0 };
+        let len = block.len() as u16;
+        out.push_all([last, len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        out.push_all(block);
+    }
+    push_u32_be(&mut out, adler32(data));
+    out
+}
+
+/// Encode premultiplied ARGB32 pixels as a PNG. PNG has no empty images, so
+/// this fails if either dimension is zero.
+fn encode_png(w: uint, h: uint, pixels: &[u32]) -> Result<Vec<u8>, ()> {
+    if w == 0 || h == 0 { return Err(()) }
+    let mut out = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+    let mut header = Vec::new();
+    push_u32_be(&mut header, w as u32);
+    push_u32_be(&mut header, h as u32);
+    // 8 bits per channel, RGBA, default compression, filter and interlacing
+    header.push_all([8, 6, 0, 0, 0]);
+    push_chunk(&mut out, b"IHDR", header.as_slice());
+
+    let mut scanlines = Vec::with_capacity(h * (1 + w * 4));
+    for row in pixels.chunks(w) {
+        // No filter
+        scanlines.push(0);
+        for &pixel in row.iter() {
+            scanlines.push_all(unpremultiply(pixel));
+        }
+    }
+    push_chunk(&mut out, b"IDAT", zlib_stored(scanlines.as_slice()).as_slice());
+    push_chunk(&mut out, b"IEND", []);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, encode_png, zlib_stored};
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32([]), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32([]), 1);
+    }
+
+    #[test]
+    fn zlib_streams_are_stored_blocks() {
+        assert_eq!(zlib_stored([]), vec![0x78, 0x01, 0x01, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(zlib_stored([0x00, 0xff, 0x00, 0x00, 0xff]),
+                   vec![0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, 0x00, 0xff, 0x00, 0x00, 0xff,
+                        0x05, 0x00, 0x01, 0xff]);
+        // A block holds at most 65535 bytes
+        let stream = zlib_stored(Vec::from_elem(0x10000, 0u8).as_slice());
+        assert_eq!(stream.len(), 2 + 5 + 0xffff + 5 + 1 + 4);
+        assert_eq!(stream.slice(2, 7), [0x00, 0xff, 0xff, 0x00, 0x00].as_slice());
+        assert_eq!(stream.slice(7 + 0xffff, 12 + 0xffff), [0x01, 0x01, 0x00, 0xfe, 0xff].as_slice());
+    }
+
+    #[test]
+    fn one_pixel_png() {
+        // Opaque red
+        assert_eq!(encode_png(1, 1, [0xffff0000]), Ok(vec![
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a,
+            0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01,
+            0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4, 0x89,
+            0x00, 0x00, 0x00, 0x10, 0x49, 0x44, 0x41, 0x54, 0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff,
+            0x00, 0xff, 0x00, 0x00, 0xff, 0x05, 0x00, 0x01, 0xff, 0xfa, 0x5c, 0x88, 0xd1,
+            0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ]));
+    }
+
+    #[test]
+    fn empty_png_is_an_error() {
+        assert_eq!(encode_png(0, 1, []), Err(()));
+        assert_eq!(encode_png(1, 0, []), Err(()));
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Out-of-process rendering with Ecore_Evas_Extn. A socket is a window whose
+//! canvas is shared with other processes, which display it with a plug (see
+//! `Window::new_plug`).
+
+use libc;
+use std::mem;
+
+use ffi;
+use {bound_window, Window};
+
+/// Called when the other end of a socket or plug connects or disconnects
+pub trait ExtnCallback {
+    fn call(&self, &Window);
+}
+
+/// The socket state associated with a window
+pub struct ExtnState {
+    connect_callback: Option<Box<ExtnCallback>>,
+    disconnect_callback: Option<Box<ExtnCallback>>,
+    /// The handlers registered for the client events
+    handlers: Vec<*mut ffi::Ecore_Event_Handler>,
+}
+
+impl ExtnState {
+    pub fn new() -> ExtnState {
+        ExtnState {
+            connect_callback: None,
+            disconnect_callback: None,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Register the client event handlers for the socket `ee`. The events
+    /// are sent for every socket in the process, so the handlers are given
+    /// the ecore_evas to filter on, which leads back to the window.
+    fn register(&mut self, ee: *mut ffi::Ecore_Evas) {
+        if !self.handlers.is_empty() { return }
+        let data = ee as *const libc::c_void;
+        unsafe {
+            self.handlers = vec![
+                ffi::ecore_event_handler_add(ffi::ECORE_EVAS_EXTN_CLIENT_ADD, Some(client_add), data),
+                ffi::ecore_event_handler_add(ffi::ECORE_EVAS_EXTN_CLIENT_DEL, Some(client_del), data),
+            ];
+        }
+    }
+
+    pub fn unregister(&mut self) {
+        for &handler in self.handlers.iter() {
+            unsafe { ffi::ecore_event_handler_del(handler) };
+        }
+        self.handlers.clear();
+    }
+}
+
+impl Drop for ExtnState {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Set the callback run when a plug connects to this window. This only
+    /// applies to windows built with `Context::build_socket`.
+    pub fn set_socket_connect_callback(&mut self, callback: Box<ExtnCallback>) -> Option<Box<ExtnCallback>> {
+        self.extn.register(self.ee);
+        mem::replace(&mut self.extn.connect_callback, Some(callback))
+    }
+
+    pub fn unset_socket_connect_callback(&mut self) -> Option<Box<ExtnCallback>> {
+        if self.extn.disconnect_callback.is_none() {
+            self.extn.unregister();
+        }
+        self.extn.connect_callback.take()
+    }
+
+    /// Set the callback run when a plug disconnects from this window. This
+    /// only applies to windows built with `Context::build_socket`.
+    pub fn set_socket_disconnect_callback(&mut self, callback: Box<ExtnCallback>) -> Option<Box<ExtnCallback>> {
+        self.extn.register(self.ee);
+        mem::replace(&mut self.extn.disconnect_callback, Some(callback))
+    }
+
+    pub fn unset_socket_disconnect_callback(&mut self) -> Option<Box<ExtnCallback>> {
+        if self.extn.connect_callback.is_none() {
+            self.extn.unregister();
+        }
+        self.extn.disconnect_callback.take()
+    }
+}
+
+extern "C" fn client_add(data: *mut libc::c_void, _type: libc::c_int,
+                         event: *mut libc::c_void) -> ffi::Eina_Bool {
+    ::guard_callback("client_add", ffi::EINA_TRUE, || {
+        if event != data { return ffi::EINA_TRUE }
+        unsafe {
+            for window in bound_window(event as *const _).iter() {
+                for callback in window.extn.connect_callback.iter() {
+                    callback.call(window);
+                }
+            }
+        }
+        ffi::EINA_TRUE
+    })
+}
+
+extern "C" fn client_del(data: *mut libc::c_void, _type: libc::c_int,
+                         event: *mut libc::c_void) -> ffi::Eina_Bool {
+    ::guard_callback("client_del", ffi::EINA_TRUE, || {
+        if event != data { return ffi::EINA_TRUE }
+        unsafe {
+            for window in bound_window(event as *const _).iter() {
+                for callback in window.extn.disconnect_callback.iter() {
+                    callback.call(window);
+                }
+            }
+        }
+        ffi::EINA_TRUE
+    })
+}
@@ -13,6 +13,7 @@ pub use self::ffi::*;
 
 use libc;
 use std::ptr;
+use std::str;
 
 pub static EINA_FALSE: Eina_Bool = 0;
 pub static EINA_TRUE: Eina_Bool = 1;
@@ -27,6 +28,14 @@ pub fn from_eina_bool(x: Eina_Bool) -> bool {
     if x == EINA_FALSE { false } else { true }
 }
 
+/// Copy a C string returned by the EFL into an owned string, or return `None`
+/// if the pointer is null. The EFL returns null for strings that are unset,
+/// and the safe wrappers in this crate treat an unset string as empty unless
+/// the difference matters.
+pub unsafe fn string_from_c_ptr(ptr: *const libc::c_char) -> Option<String> {
+    if ptr.is_null() { None } else { Some(str::raw::from_c_str(ptr)) }
+}
+
 pub fn eina_list_iter(list: *const Eina_List) -> EinaListItems {
     EinaListItems {
         iter: unsafe { eina_list_iterator_new(list) },
@@ -54,3 +63,236 @@ impl Drop for EinaListItems {
         }
     }
 }
+
+/// Declarations from `Ecore_Win32.h`. These aren't part of the generated
+/// bindings because Ecore_Win32 is only available on Windows.
+#[cfg(windows)]
+pub mod win32 {
+    use libc;
+
+    pub enum Ecore_Win32_Window {}
+
+    #[link(name = "ecore_win32")]
+    extern "C" {
+        pub fn ecore_win32_window_hwnd_get(window: *mut Ecore_Win32_Window) -> *mut libc::c_void;
+    }
+}
+
+/// Declarations from `Ecore_X.h`. These aren't part of the generated bindings
+/// because Ecore_X is only available on X11 platforms.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub mod x11 {
+    use libc;
+    use super::Eina_Bool;
+
+    pub type Ecore_X_Window = libc::c_uint;
+    pub type Ecore_X_Time = libc::c_uint;
+    pub type Ecore_X_Atom = libc::c_uint;
+    pub type Ecore_X_Selection = libc::c_uint;
+
+    pub static ECORE_X_SELECTION_XDND: Ecore_X_Selection = 2;
+    pub static ECORE_X_SELECTION_CLIPBOARD: Ecore_X_Selection = 3;
+
+    pub static ECORE_X_SELECTION_CONTENT_TEXT: libc::c_uint = 1;
+    pub static ECORE_X_SELECTION_CONTENT_FILES: libc::c_uint = 2;
+
+    pub static ECORE_X_SELECTION_TARGET_UTF8_STRING: &'static str = "UTF8_STRING";
+    pub static ECORE_X_SELECTION_TARGET_URI_LIST: &'static str = "text/uri-list";
+
+    #[repr(C)]
+    pub struct Ecore_X_Rectangle {
+        pub x: libc::c_int,
+        pub y: libc::c_int,
+        pub width: libc::c_uint,
+        pub height: libc::c_uint,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Position {
+        pub x: libc::c_int,
+        pub y: libc::c_int,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Event_Xdnd_Enter {
+        pub win: Ecore_X_Window,
+        pub source: Ecore_X_Window,
+        pub types: *mut *mut libc::c_char,
+        pub num_types: libc::c_int,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Event_Xdnd_Position {
+        pub win: Ecore_X_Window,
+        pub source: Ecore_X_Window,
+        pub position: Ecore_X_Position,
+        pub action: Ecore_X_Atom,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Event_Xdnd_Drop {
+        pub win: Ecore_X_Window,
+        pub source: Ecore_X_Window,
+        pub action: Ecore_X_Atom,
+        pub position: Ecore_X_Position,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Event_Window_Visibility_Change {
+        pub win: Ecore_X_Window,
+        pub fully_obscured: libc::c_int,
+        pub time: Ecore_X_Time,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Event_Selection_Notify {
+        pub win: Ecore_X_Window,
+        pub time: Ecore_X_Time,
+        pub selection: Ecore_X_Selection,
+        pub atom: Ecore_X_Atom,
+        pub target: *mut libc::c_char,
+        pub data: *mut libc::c_void,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Selection_Data {
+        pub content: libc::c_uint,
+        pub data: *mut libc::c_uchar,
+        pub length: libc::c_int,
+        pub format: libc::c_int,
+        pub free: Option<extern "C" fn(*mut libc::c_void) -> libc::c_int>,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Selection_Data_Text {
+        pub data: Ecore_X_Selection_Data,
+        pub text: *mut libc::c_char,
+    }
+
+    #[repr(C)]
+    pub struct Ecore_X_Selection_Data_Files {
+        pub data: Ecore_X_Selection_Data,
+        pub files: *mut *mut libc::c_char,
+        pub num_files: libc::c_int,
+    }
+
+    pub type Ecore_X_Randr_Output = libc::c_uint;
+    pub type Ecore_X_Randr_Crtc = libc::c_uint;
+    pub type Ecore_X_Randr_Mode = libc::c_uint;
+    pub type Ecore_X_Randr_Refresh_Rate = libc::c_short;
+
+    #[link(name = "ecore_x")]
+    extern "C" {
+        pub static ECORE_X_EVENT_SELECTION_NOTIFY: libc::c_int;
+        pub static ECORE_X_EVENT_XDND_ENTER: libc::c_int;
+        pub static ECORE_X_EVENT_XDND_POSITION: libc::c_int;
+        pub static ECORE_X_EVENT_XDND_DROP: libc::c_int;
+        pub static ECORE_X_EVENT_WINDOW_VISIBILITY_CHANGE: libc::c_int;
+        pub static ECORE_X_ATOM_XDND_ACTION_COPY: Ecore_X_Atom;
+
+        pub fn ecore_x_netwm_opacity_set(win: Ecore_X_Window, opacity: libc::c_uint);
+        pub fn ecore_x_netwm_opacity_get(win: Ecore_X_Window, opacity: *mut libc::c_uint) -> Eina_Bool;
+        pub fn ecore_x_selection_clipboard_set(win: Ecore_X_Window, data: *const libc::c_void,
+                                               size: libc::c_int) -> Eina_Bool;
+        pub fn ecore_x_selection_clipboard_request(win: Ecore_X_Window, target: *const libc::c_char);
+        pub fn ecore_x_selection_xdnd_request(win: Ecore_X_Window, target: *const libc::c_char);
+        pub fn ecore_x_dnd_aware_set(win: Ecore_X_Window, on: Eina_Bool);
+        pub fn ecore_x_dnd_send_status(will_accept: Eina_Bool, suppress: Eina_Bool,
+                                       rectangle: Ecore_X_Rectangle, action: Ecore_X_Atom);
+        pub fn ecore_x_dnd_send_finished();
+        pub fn ecore_x_window_shape_rectangles_set(win: Ecore_X_Window, rects: *mut Ecore_X_Rectangle,
+                                                   num: libc::c_int);
+        pub fn ecore_x_window_shape_input_rectangles_set(win: Ecore_X_Window, rects: *mut Ecore_X_Rectangle,
+                                                         num: libc::c_int);
+        pub fn ecore_x_init(name: *const libc::c_char) -> libc::c_int;
+        pub fn ecore_x_shutdown() -> libc::c_int;
+        pub fn ecore_x_window_root_get(win: Ecore_X_Window) -> Ecore_X_Window;
+        pub fn ecore_x_randr_crtcs_get(root: Ecore_X_Window, num: *mut libc::c_int) -> *mut Ecore_X_Randr_Crtc;
+        pub fn ecore_x_randr_crtc_mode_get(root: Ecore_X_Window, crtc: Ecore_X_Randr_Crtc) -> Ecore_X_Randr_Mode;
+        pub fn ecore_x_randr_crtc_refresh_rate_get(root: Ecore_X_Window, crtc: Ecore_X_Randr_Crtc,
+                                                   mode: Ecore_X_Randr_Mode) -> libc::c_double;
+        pub fn ecore_x_randr_crtc_geometry_get(root: Ecore_X_Window, crtc: Ecore_X_Randr_Crtc,
+                                               x: *mut libc::c_int, y: *mut libc::c_int,
+                                               w: *mut libc::c_int, h: *mut libc::c_int);
+        pub fn ecore_x_window_root_first_get() -> Ecore_X_Window;
+        pub fn ecore_x_randr_outputs_get(root: Ecore_X_Window, num: *mut libc::c_int) -> *mut Ecore_X_Randr_Output;
+        pub fn ecore_x_randr_output_crtc_get(root: Ecore_X_Window, output: Ecore_X_Randr_Output) -> Ecore_X_Randr_Crtc;
+        pub fn ecore_x_randr_output_name_get(root: Ecore_X_Window, output: Ecore_X_Randr_Output,
+                                             len: *mut libc::c_int) -> *mut libc::c_char;
+        pub fn ecore_x_randr_output_size_mm_get(root: Ecore_X_Window, output: Ecore_X_Randr_Output,
+                                                w: *mut libc::c_int, h: *mut libc::c_int);
+        pub fn ecore_x_randr_screen_primary_output_current_refresh_rate_get(root: Ecore_X_Window)
+                                                                            -> Ecore_X_Randr_Refresh_Rate;
+    }
+}
+
+/// The layout of an `Evas_Native_Surface` describing a Linux dmabuf. The
+/// dmabuf surface type was added to Evas after the native surface union in
+/// the generated bindings, so it is declared here.
+pub mod dmabuf {
+    use libc;
+
+    /// The version of `Evas_Native_Surface` that has the layout below. The
+    /// scanout handler was added to the dmabuf surface in version 5 (EFL
+    /// 1.20), and Evas refuses surfaces of any other version than its own.
+    pub static EVAS_NATIVE_SURFACE_VERSION: libc::c_int = 5;
+    pub static EVAS_NATIVE_SURFACE_WL_DMABUF: libc::c_uint = 6;
+
+    /// `DRM_FORMAT_ARGB8888` and `DRM_FORMAT_XRGB8888` from `drm_fourcc.h`
+    pub static DRM_FORMAT_ARGB8888: u32 = 0x34325241;
+    pub static DRM_FORMAT_XRGB8888: u32 = 0x34325258;
+
+    pub static MAX_PLANES: uint = 4;
+
+    /// `struct dmabuf_attributes`, as the Evas Wayland engines expect it
+    #[repr(C)]
+    pub struct Dmabuf_Attributes {
+        pub width: i32,
+        pub height: i32,
+        pub format: u32,
+        pub flags: u32,
+        pub n_planes: libc::c_int,
+        pub fd: [libc::c_int, ..MAX_PLANES],
+        pub offset: [u32, ..MAX_PLANES],
+        pub stride: [u32, ..MAX_PLANES],
+        pub modifier: [u64, ..MAX_PLANES],
+    }
+
+    /// The start of an `Evas_Native_Surface` holding a dmabuf. This is
+    /// smaller than the whole union, which Evas copies, so it has to be
+    /// written into an `Evas_Native_Surface` rather than passed by itself.
+    #[repr(C)]
+    pub struct Evas_Native_Surface_Wl_Dmabuf {
+        pub version: libc::c_int,
+        pub type_: libc::c_uint,
+        pub attr: *mut libc::c_void,
+        pub resource: *mut libc::c_void,
+        pub scanout_handler: *mut libc::c_void,
+        pub scanout_data: *mut libc::c_void,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::string_from_c_ptr;
+    use super::Evas_Native_Surface;
+    use super::dmabuf::Evas_Native_Surface_Wl_Dmabuf;
+    use std::mem;
+    use std::ptr;
+
+    #[test]
+    fn null_string_is_none() {
+        assert_eq!(unsafe { string_from_c_ptr(ptr::null()) }, None);
+    }
+
+    #[test]
+    fn string_is_copied() {
+        let string = "title".with_c_str(|ptr| unsafe { string_from_c_ptr(ptr) });
+        assert_eq!(string, Some("title".to_string()));
+    }
+
+    #[test]
+    fn dmabuf_surface_fits_native_surface() {
+        assert!(mem::size_of::<Evas_Native_Surface_Wl_Dmabuf>() <= mem::size_of::<Evas_Native_Surface>());
+    }
+}
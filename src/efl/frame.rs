@@ -0,0 +1,95 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Notification of presented frames, with their timing.
+
+use std::cell::Cell;
+use std::mem;
+
+use ecore;
+use Window;
+
+/// The timing of a presented frame
+#[deriving(Clone, PartialEq, Show)]
+pub struct FrameInfo {
+    /// The number of the frame, counting from 0 when the frame callback
+    /// was set.
+    pub frame: u64,
+    /// When the frame was presented, on ecore's monotonic clock (see
+    /// `ecore::time`), in seconds.
+    pub timestamp: f64,
+    /// The time since the previous frame was presented, in seconds. This is
+    /// `None` for the first frame.
+    pub interval: Option<f64>,
+    /// Whether the interval was long enough that at least one frame was
+    /// missed, taking the window's `frame_time` as the target.
+    pub dropped: bool,
+}
+
+/// Called after each frame of a window is presented
+pub trait FrameCallback {
+    fn call(&self, &Window, FrameInfo);
+}
+
+/// The frame callback and frame timing associated with a window
+pub struct FrameState {
+    callback: Option<Box<FrameCallback>>,
+    /// The number of frames presented since the callback was set
+    count: Cell<u64>,
+    /// When the last frame was presented
+    last: Cell<Option<f64>>,
+}
+
+impl FrameState {
+    pub fn new() -> FrameState {
+        FrameState {
+            callback: None,
+            count: Cell::new(0),
+            last: Cell::new(None),
+        }
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Set a callback that is called after each frame is presented, with
+    /// its timing. Every engine presents at the end of rendering (see
+    /// `swap_buffers`), so frames are timed from the post render event.
+    pub fn set_frame_callback(&mut self, callback: Box<FrameCallback>) -> Option<Box<FrameCallback>> {
+        self.frame.count.set(0);
+        self.frame.last.set(None);
+        mem::replace(&mut self.frame.callback, Some(callback))
+    }
+
+    pub fn unset_frame_callback(&mut self) -> Option<Box<FrameCallback>> {
+        self.frame.callback.take()
+    }
+}
+
+/// Time the frame that has just been presented, and pass it to the frame
+/// callback.
+pub fn dispatch_frame(window: &Window) {
+    let state = &window.frame;
+    if state.callback.is_none() {
+        return;
+    }
+    let timestamp = ecore::time();
+    let interval = state.last.get().map(|last| timestamp - last);
+    let info = FrameInfo {
+        frame: state.count.get(),
+        timestamp: timestamp,
+        interval: interval,
+        // Allow for jitter of half a frame before counting one as missed
+        dropped: interval.map_or(false, |interval| interval > window.frame_time() * 1.5),
+    };
+    state.count.set(info.frame + 1);
+    state.last.set(Some(timestamp));
+    for callback in state.callback.iter() {
+        callback.call(window, info.clone());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Recognizers for common multi-touch gestures. Each is fed the events and
+//! points passed to a `TouchHandler`, and reports its gesture once the
+//! touches have moved past a threshold.
+
+use std::f64::consts::PI;
+
+use {TouchEvent, TouchPoint};
+
+fn distance(a: &TouchPoint, b: &TouchPoint) -> f64 {
+    ((b.x - a.x) * (b.x - a.x) + (b.y - a.y) * (b.y - a.y)).sqrt()
+}
+
+/// The angle of the line from `a` to `b`, in degrees clockwise
+fn angle(a: &TouchPoint, b: &TouchPoint) -> f64 {
+    (b.y - a.y).atan2(b.x - a.x) * 180.0 / PI
+}
+
+fn centroid(points: &[TouchPoint]) -> (f64, f64) {
+    let n = points.len() as f64;
+    let (x, y) = points.iter().fold((0.0, 0.0), |(x, y), p| (x + p.x, y + p.y));
+    (x / n, y / n)
+}
+
+/// Recognizes two fingers moving apart or together.
+pub struct PinchRecognizer {
+    threshold: f64,
+    /// The distance between the fingers when the second one went down
+    start_distance: Option<f64>,
+    active: bool,
+}
+
+impl PinchRecognizer {
+    /// Create a recognizer that starts reporting once the distance between
+    /// the fingers has changed by `threshold`, as a fraction of the starting
+    /// distance.
+    pub fn new(threshold: f64) -> PinchRecognizer {
+        PinchRecognizer { threshold: threshold, start_distance: None, active: false }
+    }
+
+    /// Update the recognizer with a touch event. While a pinch is in
+    /// progress, this returns the distance between the fingers relative to
+    /// when the second finger went down. The pinch ends when the number of
+    /// fingers changes.
+    pub fn update(&mut self, event: &TouchEvent, points: &[TouchPoint]) -> Option<f64> {
+        if points.len() != 2 {
+            self.start_distance = None;
+            self.active = false;
+            return None;
+        }
+        let current = distance(&points[0], &points[1]);
+        let start = match (event, self.start_distance) {
+            (&::TouchMoved(_), Some(start)) => start,
+            _ => {
+                self.start_distance = Some(current);
+                self.active = false;
+                return None;
+            },
+        };
+        if start == 0.0 { return None }
+        let scale = current / start;
+        if !self.active && (scale - 1.0).abs() < self.threshold { return None }
+        self.active = true;
+        Some(scale)
+    }
+}
+
+/// Recognizes two fingers turning around each other.
+pub struct RotateRecognizer {
+    threshold: f64,
+    /// The angle between the fingers when the second one went down
+    start_angle: Option<f64>,
+    active: bool,
+}
+
+impl RotateRecognizer {
+    /// Create a recognizer that starts reporting once the fingers have
+    /// turned by `threshold` degrees.
+    pub fn new(threshold: f64) -> RotateRecognizer {
+        RotateRecognizer { threshold: threshold, start_angle: None, active: false }
+    }
+
+    /// Update the recognizer with a touch event. While a rotation is in
+    /// progress, this returns how far the fingers have turned since the
+    /// second finger went down, in degrees clockwise between -180 and 180.
+    /// The rotation ends when the number of fingers changes.
+    pub fn update(&mut self, event: &TouchEvent, points: &[TouchPoint]) -> Option<f64> {
+        if points.len() != 2 {
+            self.start_angle = None;
+            self.active = false;
+            return None;
+        }
+        let current = angle(&points[0], &points[1]);
+        let start = match (event, self.start_angle) {
+            (&::TouchMoved(_), Some(start)) => start,
+            _ => {
+                self.start_angle = Some(current);
+                self.active = false;
+                return None;
+            },
+        };
+        let mut delta = current - start;
+        if delta > 180.0 { delta -= 360.0 } else if delta < -180.0 { delta += 360.0 }
+        if !self.active && delta.abs() < self.threshold { return None }
+        self.active = true;
+        Some(delta)
+    }
+}
+
+/// Recognizes one or more fingers moving together.
+pub struct PanRecognizer {
+    threshold: f64,
+    /// The centroid of the fingers, less the translation so far
+    origin: Option<(f64, f64)>,
+    translation: (f64, f64),
+    active: bool,
+}
+
+impl PanRecognizer {
+    /// Create a recognizer that starts reporting once the fingers have
+    /// moved `threshold` pixels.
+    pub fn new(threshold: f64) -> PanRecognizer {
+        PanRecognizer { threshold: threshold, origin: None, translation: (0.0, 0.0), active: false }
+    }
+
+    /// Update the recognizer with a touch event. While a pan is in
+    /// progress, this returns how far the centroid of the fingers has moved
+    /// since the first one went down. Fingers can be added and lifted
+    /// during a pan without the translation jumping; the pan ends when the
+    /// last finger is lifted.
+    pub fn update(&mut self, event: &TouchEvent, points: &[TouchPoint]) -> Option<(f64, f64)> {
+        if points.is_empty() {
+            self.origin = None;
+            self.translation = (0.0, 0.0);
+            self.active = false;
+            return None;
+        }
+        let (cx, cy) = centroid(points);
+        let (tx, ty) = self.translation;
+        match (event, self.origin) {
+            (&::TouchMoved(_), Some((ox, oy))) => self.translation = (cx - ox, cy - oy),
+            // The set of fingers changed, so continue from the current
+            // translation around the new centroid
+            _ => {
+                self.origin = Some((cx - tx, cy - ty));
+                return if self.active { Some(self.translation) } else { None };
+            },
+        }
+        let (tx, ty) = self.translation;
+        if !self.active && (tx * tx + ty * ty).sqrt() < self.threshold { return None }
+        self.active = true;
+        Some(self.translation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use {TouchBegan, TouchMoved, TouchEnded, TouchPoint};
+    use super::{PinchRecognizer, RotateRecognizer, PanRecognizer};
+
+    fn point(id: i32, x: f64, y: f64) -> TouchPoint {
+        TouchPoint { id: id, x: x, y: y, pressure: 1.0, radius: 0.0 }
+    }
+
+    fn assert_near(actual: Option<f64>, expected: f64) {
+        match actual {
+            Some(actual) => assert!((actual - expected).abs() < 1e-9, "{} is not {}", actual, expected),
+            None => fail!("expected {}, got None", expected),
+        }
+    }
+
+    #[test]
+    fn pinch_reports_scale() {
+        let mut pinch = PinchRecognizer::new(0.05);
+        let (a, b) = (point(0, 0.0, 0.0), point(1, 100.0, 0.0));
+        assert_eq!(pinch.update(&TouchBegan(a), [a]), None);
+        assert_eq!(pinch.update(&TouchBegan(b), [a, b]), None);
+        // Within the threshold
+        let b = point(1, 102.0, 0.0);
+        assert_eq!(pinch.update(&TouchMoved(b), [a, b]), None);
+        let b = point(1, 150.0, 0.0);
+        assert_near(pinch.update(&TouchMoved(b), [a, b]), 1.5);
+        // Once started, small changes are reported too
+        let b = point(1, 101.0, 0.0);
+        assert_near(pinch.update(&TouchMoved(b), [a, b]), 1.01);
+        let a = point(0, 50.0, 0.0);
+        assert_near(pinch.update(&TouchMoved(a), [a, b]), 0.51);
+        assert_eq!(pinch.update(&TouchEnded(b), [a]), None);
+    }
+
+    #[test]
+    fn rotate_reports_angle() {
+        let mut rotate = RotateRecognizer::new(10.0);
+        let (a, b) = (point(0, 0.0, 0.0), point(1, 100.0, 0.0));
+        assert_eq!(rotate.update(&TouchBegan(a), [a]), None);
+        assert_eq!(rotate.update(&TouchBegan(b), [a, b]), None);
+        let b = point(1, 99.0, 5.0);
+        assert_eq!(rotate.update(&TouchMoved(b), [a, b]), None);
+        let b = point(1, 0.0, 100.0);
+        assert_near(rotate.update(&TouchMoved(b), [a, b]), 90.0);
+        let b = point(1, 0.0, -100.0);
+        assert_near(rotate.update(&TouchMoved(b), [a, b]), -90.0);
+        assert_eq!(rotate.update(&TouchEnded(b), [a]), None);
+    }
+
+    #[test]
+    fn rotate_wraps_around() {
+        let mut rotate = RotateRecognizer::new(10.0);
+        let (a, b) = (point(0, 0.0, 0.0), point(1, -100.0, 1.0));
+        assert_eq!(rotate.update(&TouchBegan(a), [a]), None);
+        assert_eq!(rotate.update(&TouchBegan(b), [a, b]), None);
+        // Crossing from just below 180 degrees to just above -180 is a small
+        // turn, not a full one
+        let b = point(1, -100.0, -1.0);
+        assert_eq!(rotate.update(&TouchMoved(b), [a, b]), None);
+        let b = point(1, -100.0, -20.0);
+        let expected = ((1.0f64 / 100.0).atan() + (20.0f64 / 100.0).atan()) * 180.0 / PI;
+        assert_near(rotate.update(&TouchMoved(b), [a, b]), expected);
+    }
+
+    #[test]
+    fn pan_reports_translation() {
+        let mut pan = PanRecognizer::new(10.0);
+        let (a, b) = (point(0, 0.0, 0.0), point(1, 100.0, 0.0));
+        assert_eq!(pan.update(&TouchBegan(a), [a]), None);
+        assert_eq!(pan.update(&TouchBegan(b), [a, b]), None);
+        let a = point(0, 4.0, 2.0);
+        assert_eq!(pan.update(&TouchMoved(a), [a, b]), None);
+        let a = point(0, 10.0, 20.0);
+        assert_eq!(pan.update(&TouchMoved(a), [a, b]), Some((5.0, 10.0)));
+        let b = point(1, 110.0, 20.0);
+        assert_eq!(pan.update(&TouchMoved(b), [a, b]), Some((10.0, 20.0)));
+        // Lifting a finger moves the centroid, but not the translation
+        assert_eq!(pan.update(&TouchEnded(a), [b]), Some((10.0, 20.0)));
+        let b = point(1, 120.0, 20.0);
+        assert_eq!(pan.update(&TouchMoved(b), [b]), Some((20.0, 20.0)));
+        assert_eq!(pan.update(&TouchEnded(b), []), None);
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A grid of equally sized image tiles.
+
+use {EvasImage, Window};
+
+/// A fixed grid of image objects, one per cell. The objects are created once
+/// and reused, so updating a tile only uploads its pixels and marks that one
+/// cell as changed, instead of creating and destroying an object per tile.
+pub struct ImageGrid<'a> {
+    cells: Vec<EvasImage<'a>>,
+    rows: uint,
+    cols: uint,
+    cell_w: i32,
+    cell_h: i32,
+}
+
+impl<'a> ImageGrid<'a> {
+    /// Create a grid of `rows` by `cols` cells, each `cell_w` by `cell_h`
+    /// pixels, with its top left corner at the origin of the canvas. The
+    /// cells are transparent until they are updated.
+    pub fn new(window: &'a Window, rows: uint, cols: uint, cell_w: i32, cell_h: i32) -> ImageGrid<'a> {
+        let mut cells = Vec::with_capacity(rows * cols);
+        for row in range(0, rows) {
+            for col in range(0, cols) {
+                let image = window.new_image();
+                image.set_alpha(true);
+                image.set_image_size(cell_w, cell_h);
+                image.move_to(col as i32 * cell_w, row as i32 * cell_h);
+                image.resize(cell_w, cell_h);
+                image.show();
+                cells.push(image);
+            }
+        }
+        ImageGrid { cells: cells, rows: rows, cols: cols, cell_w: cell_w, cell_h: cell_h }
+    }
+
+    pub fn rows(&self) -> uint { self.rows }
+
+    pub fn cols(&self) -> uint { self.cols }
+
+    pub fn cell_size(&self) -> (i32, i32) { (self.cell_w, self.cell_h) }
+
+    /// Replace the pixels of a cell, in the format accepted by
+    /// `EvasImage::set_data`. Fails if the cell is out of range or the data
+    /// is not the size of a cell.
+    pub fn update_cell(&self, row: uint, col: uint, data: &[u8]) -> Result<(), ()> {
+        if row >= self.rows || col >= self.cols { return Err(()) }
+        self.cells.get(row * self.cols + col).set_data(data)
+    }
+
+    /// Move the top left corner of the grid, in canvas coordinates.
+    pub fn move_to(&self, x: i32, y: i32) {
+        for (i, image) in self.cells.iter().enumerate() {
+            let (row, col) = (i / self.cols, i % self.cols);
+            image.move_to(x + col as i32 * self.cell_w, y + row as i32 * self.cell_h);
+        }
+    }
+
+    pub fn show(&self) {
+        for image in self.cells.iter() { image.show() }
+    }
+
+    pub fn hide(&self) {
+        for image in self.cells.iter() { image.hide() }
+    }
+}
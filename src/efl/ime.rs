@@ -0,0 +1,216 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Input method support, using the Ecore_IMF input method framework.
+
+use libc;
+use std::mem;
+use std::ptr;
+
+use ffi;
+use {Rect, Window};
+
+/// A change in the state of the input method
+pub enum ImeEvent {
+    /// The text being composed changed. The second value is the position of
+    /// the cursor within the text, in characters. An empty string means that
+    /// composition has ended or been cancelled.
+    ImePreedit(String, uint),
+    /// The input method produced text to be inserted.
+    ImeCommit(String),
+}
+
+pub trait ImeCallback {
+    fn call(&self, &Window, ImeEvent);
+}
+
+/// The input method state associated with a window
+pub struct ImeState {
+    /// The input method context, if input methods are enabled
+    context: *mut ffi::Ecore_IMF_Context,
+    callback: Option<Box<ImeCallback>>,
+}
+
+impl ImeState {
+    pub fn new() -> ImeState {
+        ImeState {
+            context: ptr::mut_null(),
+            callback: None,
+        }
+    }
+
+    /// Delete the input method context, if there is one.
+    pub fn disable(&mut self) {
+        if !self.context.is_null() {
+            unsafe {
+                ffi::ecore_imf_context_focus_out(self.context);
+                ffi::ecore_imf_context_del(self.context);
+                ffi::ecore_imf_shutdown();
+            }
+            self.context = ptr::mut_null();
+        }
+    }
+}
+
+impl Drop for ImeState {
+    fn drop(&mut self) {
+        self.disable();
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Set the callback that receives text from the input method. This has
+    /// no effect until input methods are enabled with `ime_set_enabled`.
+    pub fn set_ime_callback(&mut self, callback: Box<ImeCallback>) -> Option<Box<ImeCallback>> {
+        mem::replace(&mut self.ime.callback, Some(callback))
+    }
+
+    pub fn unset_ime_callback(&mut self) -> Option<Box<ImeCallback>> {
+        self.ime.callback.take()
+    }
+
+    /// Enable or disable input methods for the window. While enabled, key
+    /// events are passed to the input method first, and those it consumes are
+    /// not delivered to the key callbacks. Fails if no input method module is
+    /// available.
+    pub fn ime_set_enabled(&mut self, enabled: bool) -> Result<(), ()> {
+        if !enabled {
+            self.ime.disable();
+            return Ok(());
+        }
+        if !self.ime.context.is_null() {
+            return Ok(());
+        }
+        unsafe {
+            ffi::ecore_imf_init();
+            let id = ffi::ecore_imf_context_default_id_get();
+            let context = if id.is_null() { ptr::mut_null() } else { ffi::ecore_imf_context_add(id) };
+            if context.is_null() {
+                ffi::ecore_imf_shutdown();
+                return Err(());
+            }
+
+            let data = self.data_ptr();
+            let client_window = ffi::ecore_evas_window_get(self.ee as *const _);
+            ffi::ecore_imf_context_client_window_set(context, client_window as *mut libc::c_void);
+            ffi::ecore_imf_context_client_canvas_set(context, self.canvas as *mut libc::c_void);
+            ffi::ecore_imf_context_event_callback_add(
+                context, ffi::ECORE_IMF_CALLBACK_PREEDIT_CHANGED, Some(preedit_changed_callback), data,
+            );
+            ffi::ecore_imf_context_event_callback_add(
+                context, ffi::ECORE_IMF_CALLBACK_COMMIT, Some(commit_callback), data,
+            );
+            ffi::ecore_imf_context_focus_in(context);
+
+            // The input method needs to see key events even if there are no
+            // key callbacks set
+            for &(callback_type, callback) in [
+                (ffi::EVAS_CALLBACK_KEY_DOWN, ::key_down_callback),
+                (ffi::EVAS_CALLBACK_KEY_UP, ::key_up_callback),
+            ].iter() {
+                ffi::evas_object_event_callback_del(self.object, callback_type, Some(callback));
+                ffi::evas_object_event_callback_add(self.object, callback_type, Some(callback), data);
+            }
+
+            self.ime.context = context;
+        }
+        Ok(())
+    }
+
+    pub fn ime_is_enabled(&self) -> bool {
+        !self.ime.context.is_null()
+    }
+
+    /// Tell the input method where the text cursor is, in canvas coordinates,
+    /// so that it can position its candidate window next to it.
+    pub fn ime_set_cursor_location(&self, rect: Rect) {
+        if !self.ime.context.is_null() {
+            unsafe {
+                ffi::ecore_imf_context_cursor_location_set(
+                    self.ime.context, rect.x as libc::c_int, rect.y as libc::c_int,
+                    rect.w as libc::c_int, rect.h as libc::c_int,
+                )
+            };
+        }
+    }
+}
+
+/// Whether input events of this type need to be passed to the window's input
+/// method
+pub fn wants_event(window: &Window, callback_type: ffi::Evas_Callback_Type) -> bool {
+    !window.ime.context.is_null() &&
+        (callback_type == ffi::EVAS_CALLBACK_KEY_DOWN || callback_type == ffi::EVAS_CALLBACK_KEY_UP)
+}
+
+/// Pass an input event to the window's input method. Returns `true` if the
+/// input method consumed the event, in which case it should not be delivered
+/// any further.
+pub unsafe fn filter_event(window: &Window, callback_type: ffi::Evas_Callback_Type,
+                           event_info: *mut libc::c_void) -> bool {
+    if !wants_event(window, callback_type) {
+        return false;
+    }
+    let context = window.ime.context;
+    let filtered = if callback_type == ffi::EVAS_CALLBACK_KEY_DOWN {
+        let mut event: ffi::Ecore_IMF_Event_Key_Down = mem::zeroed();
+        ffi::ecore_imf_evas_event_key_down_wrap(event_info as *mut _, &mut event);
+        ffi::ecore_imf_context_filter_event(
+            context, ffi::ECORE_IMF_EVENT_KEY_DOWN, &mut event as *mut _ as *mut ffi::Ecore_IMF_Event,
+        )
+    } else {
+        let mut event: ffi::Ecore_IMF_Event_Key_Up = mem::zeroed();
+        ffi::ecore_imf_evas_event_key_up_wrap(event_info as *mut _, &mut event);
+        ffi::ecore_imf_context_filter_event(
+            context, ffi::ECORE_IMF_EVENT_KEY_UP, &mut event as *mut _ as *mut ffi::Ecore_IMF_Event,
+        )
+    };
+    ffi::from_eina_bool(filtered)
+}
+
+extern "C" fn preedit_changed_callback(
+    data: *mut libc::c_void,
+    context: *mut ffi::Ecore_IMF_Context,
+    _event_info: *mut libc::c_void,
+) {
+    ::guard_callback("preedit_changed_callback", (), || {
+        unsafe {
+            let window = &Window::from_data_ptr(data as *const _);
+            let mut text = ptr::mut_null();
+            let mut cursor = 0;
+            ffi::ecore_imf_context_preedit_string_get(context, &mut text, &mut cursor);
+            let preedit = ffi::string_from_c_ptr(text as *const _).unwrap_or(String::new());
+            if !text.is_null() {
+                libc::free(text as *mut libc::c_void);
+            }
+            for callback in window.ime.callback.iter() {
+                callback.call(window, ImePreedit(preedit.clone(), cursor as uint));
+            }
+        }
+    })
+}
+
+extern "C" fn commit_callback(
+    data: *mut libc::c_void,
+    _context: *mut ffi::Ecore_IMF_Context,
+    event_info: *mut libc::c_void,
+) {
+    ::guard_callback("commit_callback", (), || {
+        unsafe {
+            let window = &Window::from_data_ptr(data as *const _);
+            let text = match ffi::string_from_c_ptr(event_info as *const _) {
+                Some(text) => text,
+                None => return,
+            };
+            for callback in window.ime.callback.iter() {
+                callback.call(window, ImeCommit(text.clone()));
+            }
+            ::text::deliver(window, text.as_slice());
+        }
+    })
+}
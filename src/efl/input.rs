@@ -0,0 +1,452 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers for processing input events.
+
+use libc;
+use std::comm::Receiver;
+use std::io::{Buffer, IoError, IoResult, Writer};
+
+use ffi;
+use {ButtonFlags, Event, MouseMove, MultiDown, MultiMove, TimeStamp, Window};
+use {EventMouseMove, EventMouseDown, EventMouseUp, EventMouseWheel, EventKeyDown, EventKeyUp};
+
+/// An input event that can be fed back into a window
+#[deriving(Clone, Show)]
+enum Recorded {
+    RecordedMouseMove(i32, i32),
+    RecordedMouseDown(i32, u32),
+    RecordedMouseUp(i32, u32),
+    RecordedMouseWheel(i32, i32),
+    RecordedKeyDown(String, String, String),
+    RecordedKeyUp(String, String, String),
+}
+
+impl Recorded {
+    fn from_event(event: &Event) -> Option<(TimeStamp, Recorded)> {
+        match *event {
+            EventMouseMove(ref e) => Some((e.timestamp.clone(), RecordedMouseMove(e.cur.output.x, e.cur.output.y))),
+            EventMouseDown(ref e) => Some((e.timestamp.clone(), RecordedMouseDown(e.button, e.flags.bits()))),
+            EventMouseUp(ref e) => Some((e.timestamp.clone(), RecordedMouseUp(e.button, e.flags.bits()))),
+            EventMouseWheel(ref e) => Some((e.timestamp.clone(), RecordedMouseWheel(e.direction, e.z))),
+            EventKeyDown(ref e) => Some((e.timestamp.clone(), RecordedKeyDown(e.keyname.clone(), e.key.clone(), e.string.clone()))),
+            EventKeyUp(ref e) => Some((e.timestamp.clone(), RecordedKeyUp(e.keyname.clone(), e.key.clone(), e.string.clone()))),
+            _ => None,
+        }
+    }
+
+    fn feed(&self, window: &Window, timestamp: TimeStamp) {
+        match *self {
+            RecordedMouseMove(x, y) => window.feed_mouse_move(x, y, timestamp),
+            RecordedMouseDown(button, flags) => {
+                window.feed_mouse_down(button, ButtonFlags::from_bits_truncate(flags), timestamp)
+            },
+            RecordedMouseUp(button, flags) => {
+                window.feed_mouse_up(button, ButtonFlags::from_bits_truncate(flags), timestamp)
+            },
+            RecordedMouseWheel(direction, z) => window.feed_mouse_wheel(direction, z, timestamp),
+            RecordedKeyDown(ref keyname, ref key, ref string) => {
+                window.feed_key_down(keyname.as_slice(), key.as_slice(), string.as_slice(), timestamp)
+            },
+            RecordedKeyUp(ref keyname, ref key, ref string) => {
+                window.feed_key_up(keyname.as_slice(), key.as_slice(), string.as_slice(), timestamp)
+            },
+        }
+    }
+
+    /// Write the event as a single line of space separated fields, starting
+    /// with the timestamp and the kind of event.
+    fn write(&self, writer: &mut Writer, timestamp: &TimeStamp) -> IoResult<()> {
+        let ms = timestamp.millis();
+        match *self {
+            RecordedMouseMove(x, y) => writeln!(writer, "{} mouse_move {} {}", ms, x, y),
+            RecordedMouseDown(button, flags) => writeln!(writer, "{} mouse_down {} {}", ms, button, flags),
+            RecordedMouseUp(button, flags) => writeln!(writer, "{} mouse_up {} {}", ms, button, flags),
+            RecordedMouseWheel(direction, z) => writeln!(writer, "{} mouse_wheel {} {}", ms, direction, z),
+            RecordedKeyDown(ref keyname, ref key, ref string) => {
+                writeln!(writer, "{} key_down {} {} {}", ms, escape(keyname.as_slice()),
+                         escape(key.as_slice()), escape(string.as_slice()))
+            },
+            RecordedKeyUp(ref keyname, ref key, ref string) => {
+                writeln!(writer, "{} key_up {} {} {}", ms, escape(keyname.as_slice()),
+                         escape(key.as_slice()), escape(string.as_slice()))
+            },
+        }
+    }
+
+    /// Parse a line written by `write`.
+    fn parse(line: &str) -> Option<(TimeStamp, Recorded)> {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let event = match fields.as_slice() {
+            [_, "mouse_move", x, y] => {
+                match (from_str(x), from_str(y)) {
+                    (Some(x), Some(y)) => RecordedMouseMove(x, y),
+                    _ => return None,
+                }
+            },
+            [_, "mouse_down", button, flags] => {
+                match (from_str(button), from_str(flags)) {
+                    (Some(button), Some(flags)) => RecordedMouseDown(button, flags),
+                    _ => return None,
+                }
+            },
+            [_, "mouse_up", button, flags] => {
+                match (from_str(button), from_str(flags)) {
+                    (Some(button), Some(flags)) => RecordedMouseUp(button, flags),
+                    _ => return None,
+                }
+            },
+            [_, "mouse_wheel", direction, z] => {
+                match (from_str(direction), from_str(z)) {
+                    (Some(direction), Some(z)) => RecordedMouseWheel(direction, z),
+                    _ => return None,
+                }
+            },
+            [_, "key_down", keyname, key, string] => {
+                match (unescape(keyname), unescape(key), unescape(string)) {
+                    (Some(keyname), Some(key), Some(string)) => RecordedKeyDown(keyname, key, string),
+                    _ => return None,
+                }
+            },
+            [_, "key_up", keyname, key, string] => {
+                match (unescape(keyname), unescape(key), unescape(string)) {
+                    (Some(keyname), Some(key), Some(string)) => RecordedKeyUp(keyname, key, string),
+                    _ => return None,
+                }
+            },
+            _ => return None,
+        };
+        from_str(fields[0]).map(|ms| (TimeStamp(ms), event))
+    }
+}
+
+/// Percent-encode the bytes of a string that would break up a line of the
+/// recording format. Empty strings are written as `""`, which can't come
+/// from a non-empty string as quotes are always encoded.
+fn escape(s: &str) -> String {
+    if s.is_empty() { return "\"\"".to_string() }
+    let mut escaped = String::new();
+    for &byte in s.as_bytes().iter() {
+        if byte <= b' ' || byte == b'%' || byte == b'"' || byte >= 0x7f {
+            escaped.push_str(format!("%{:02X}", byte).as_slice());
+        } else {
+            escaped.push_char(byte as char);
+        }
+    }
+    escaped
+}
+
+/// Decode a string written by `escape`. Fails unless every `%` is followed
+/// by exactly two hex digits.
+fn unescape(s: &str) -> Option<String> {
+    if s == "\"\"" { return Some(String::new()) }
+    ::dnd::percent_decode(s).and_then(|bytes| String::from_utf8(bytes).ok())
+}
+
+/// Errors that can occur while loading a recording
+#[deriving(Show)]
+pub enum ReplayError {
+    ReplayIoError(IoError),
+    /// The recording could not be parsed at the given line number, counting
+    /// from 1.
+    ReplayParseError(uint),
+}
+
+/// Records the input events delivered to a window so that they can be saved
+/// and replayed with a `Player`. Only mouse movement, mouse buttons, the
+/// mouse wheel, and keys are recorded.
+pub struct Recorder {
+    receiver: Receiver<Event>,
+    events: Vec<(TimeStamp, Recorded)>,
+}
+
+impl Recorder {
+    /// Start recording the input events delivered to a window. This
+    /// replaces the window's input handler.
+    pub fn new(window: &mut Window) -> Recorder {
+        Recorder {
+            receiver: window.event_channel(),
+            events: Vec::new(),
+        }
+    }
+
+    fn collect(&mut self) {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => {
+                    for recorded in Recorded::from_event(&event).move_iter() {
+                        self.events.push(recorded);
+                    }
+                },
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// The number of events recorded so far.
+    pub fn len(&mut self) -> uint {
+        self.collect();
+        self.events.len()
+    }
+
+    /// Write the events recorded so far, one per line.
+    pub fn write(&mut self, writer: &mut Writer) -> IoResult<()> {
+        self.collect();
+        for &(ref timestamp, ref event) in self.events.iter() {
+            try!(event.write(writer, timestamp));
+        }
+        Ok(())
+    }
+
+    /// Stop recording, returning a player for the events recorded so far.
+    pub fn finish(mut self) -> Player {
+        self.collect();
+        Player { events: self.events }
+    }
+}
+
+/// Replays recorded input events into a window.
+pub struct Player {
+    events: Vec<(TimeStamp, Recorded)>,
+}
+
+impl Player {
+    /// Load a recording written by `Recorder::write`. Blank lines are
+    /// ignored.
+    pub fn read(reader: &mut Buffer) -> Result<Player, ReplayError> {
+        let mut events = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => return Err(ReplayIoError(err)),
+            };
+            let line = line.as_slice().trim();
+            if line.is_empty() { continue }
+            match Recorded::parse(line) {
+                Some(event) => events.push(event),
+                None => return Err(ReplayParseError(i + 1)),
+            }
+        }
+        Ok(Player { events: events })
+    }
+
+    pub fn len(&self) -> uint {
+        self.events.len()
+    }
+
+    /// Feed every event into the window in order, with their original
+    /// timestamps. This happens immediately rather than in real time, so
+    /// the result doesn't depend on how fast the events are handled.
+    pub fn play(&self, window: &Window) {
+        for &(ref timestamp, ref event) in self.events.iter() {
+            event.feed(window, timestamp.clone());
+        }
+    }
+}
+
+/// How a `MotionSmoother` turns raw pointer positions into points
+#[deriving(Clone, PartialEq, Show)]
+pub enum SmoothingMode {
+    /// Pull each point towards the new position by the given factor,
+    /// between 0 and 1. Lower factors give smoother but laggier strokes,
+    /// and 1 disables smoothing.
+    SmoothLowPass(f64),
+    /// Interpolate a Catmull-Rom spline through the positions, split into
+    /// the given number of segments between each pair of positions. Points
+    /// are produced one position behind the pointer, as each segment
+    /// depends on the position after it.
+    SmoothCatmullRom(uint),
+}
+
+/// Turns the pointer positions of a stroke into smoothed points, for
+/// drawing. Positions are kept at sub-pixel precision where the events
+/// provide it, which for multi-touch events is the `xsub` and `ysub` of
+/// their canvas position.
+pub struct MotionSmoother {
+    mode: SmoothingMode,
+    /// The last positions pushed, up to four, oldest first
+    history: Vec<(f64, f64)>,
+    /// The last point produced by the low pass filter
+    filtered: Option<(f64, f64)>,
+}
+
+impl MotionSmoother {
+    pub fn new(mode: SmoothingMode) -> MotionSmoother {
+        match mode {
+            SmoothLowPass(factor) => assert!(factor > 0.0 && factor <= 1.0),
+            SmoothCatmullRom(segments) => assert!(segments > 0),
+        }
+        MotionSmoother { mode: mode, history: Vec::new(), filtered: None }
+    }
+
+    /// Forget the current stroke, so the next position starts a new one.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.filtered = None;
+    }
+
+    /// Add the next position of the stroke, returning the points to draw up
+    /// to it. The first position of a stroke is always returned as is.
+    pub fn push(&mut self, x: f64, y: f64) -> Vec<(f64, f64)> {
+        match self.mode {
+            SmoothLowPass(factor) => {
+                let point = match self.filtered {
+                    Some((fx, fy)) => (fx + (x - fx) * factor, fy + (y - fy) * factor),
+                    None => (x, y),
+                };
+                self.filtered = Some(point);
+                vec![point]
+            },
+            SmoothCatmullRom(segments) => {
+                if self.history.is_empty() {
+                    // Repeat the first position, so the first segment has a
+                    // position before it
+                    self.history.push((x, y));
+                    self.history.push((x, y));
+                    return vec![(x, y)];
+                }
+                if self.history.len() == 4 {
+                    self.history.remove(0);
+                }
+                self.history.push((x, y));
+                if self.history.len() < 4 { return Vec::new() }
+                self.segment(segments)
+            },
+        }
+    }
+
+    /// End the stroke, returning the points between the last two positions
+    /// that have not been produced yet.
+    pub fn finish(&mut self) -> Vec<(f64, f64)> {
+        let points = match self.mode {
+            SmoothCatmullRom(segments) if self.history.len() >= 3 => {
+                // Repeat the last position, so the last segment has a
+                // position after it
+                let last = *self.history.last().unwrap();
+                if self.history.len() == 4 {
+                    self.history.remove(0);
+                }
+                self.history.push(last);
+                self.segment(segments)
+            },
+            _ => Vec::new(),
+        };
+        self.reset();
+        points
+    }
+
+    /// The points along the Catmull-Rom segment between the middle two of
+    /// the four positions in the history, excluding its start.
+    fn segment(&self, segments: uint) -> Vec<(f64, f64)> {
+        let ((x0, y0), (x1, y1)) = (*self.history.get(0), *self.history.get(1));
+        let ((x2, y2), (x3, y3)) = (*self.history.get(2), *self.history.get(3));
+        range(1, segments + 1).map(|i| {
+            let t = i as f64 / segments as f64;
+            (catmull_rom(x0, x1, x2, x3, t), catmull_rom(y0, y1, y2, y3, t))
+        }).collect()
+    }
+
+    /// Add the current canvas position of a mouse move.
+    pub fn push_mouse_move(&mut self, event: &MouseMove) -> Vec<(f64, f64)> {
+        let canvas = event.cur().canvas;
+        self.push(canvas.x as f64, canvas.y as f64)
+    }
+
+    /// Add the current canvas position of a multi-touch move, at sub-pixel
+    /// precision.
+    pub fn push_multi_move(&mut self, event: &MultiMove) -> Vec<(f64, f64)> {
+        let canvas = event.cur().canvas;
+        self.push(canvas.xsub as f64, canvas.ysub as f64)
+    }
+}
+
+/// A uniform Catmull-Rom spline through `p1` and `p2`, at `t` between 0 and 1.
+fn catmull_rom(p0: f64, p1: f64, p2: f64, p3: f64, t: f64) -> f64 {
+    let (t2, t3) = (t * t, t * t * t);
+    0.5 * (2.0 * p1 + (p2 - p0) * t + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2 +
+           (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// The state of a pen touching the window
+#[deriving(Clone, PartialEq, Show)]
+pub struct StylusState {
+    /// The pressure of the pen, between 0 and 1.
+    pub pressure: f64,
+    /// The tilt of the pen away from the vertical, in degrees along the x
+    /// and y axes. Evas does not pass tilt through its multi-touch events,
+    /// so these are `None` unless a future Evas reports them.
+    pub tilt_x: Option<f64>,
+    pub tilt_y: Option<f64>,
+    /// The rotation of the pen around its own axis, in degrees.
+    pub angle: f64,
+    /// The size of the contact area, for pens that report one.
+    pub radius_x: f64,
+    pub radius_y: f64,
+}
+
+/// Whether the device that generated an event is a pen
+fn is_pen(dev: *mut ffi::Evas_Device) -> bool {
+    !dev.is_null() && unsafe { ffi::evas_device_class_get(dev) } == ffi::EVAS_DEVICE_CLASS_PEN
+}
+
+fn stylus_state(pressure: libc::c_double, angle: libc::c_double,
+                radius_x: libc::c_double, radius_y: libc::c_double) -> StylusState {
+    StylusState {
+        pressure: pressure as f64,
+        tilt_x: None,
+        tilt_y: None,
+        angle: angle as f64,
+        radius_x: radius_x as f64,
+        radius_y: radius_y as f64,
+    }
+}
+
+impl MultiDown {
+    /// The state of the pen, if the event came from one.
+    pub fn stylus(&self) -> Option<StylusState> {
+        let info = unsafe { &*self.ptr };
+        if !is_pen(info.dev) { return None }
+        Some(stylus_state(info.pressure, info.angle, info.radius_x, info.radius_y))
+    }
+}
+
+impl MultiMove {
+    /// The state of the pen, if the event came from one.
+    pub fn stylus(&self) -> Option<StylusState> {
+        let info = unsafe { &*self.ptr };
+        if !is_pen(info.dev) { return None }
+        Some(stylus_state(info.pressure, info.angle, info.radius_x, info.radius_y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, unescape};
+
+    #[test]
+    fn strings_round_trip() {
+        for s in ["", "a", "Return", "%", "\"", "a b", "é\t\n"].iter() {
+            assert_eq!(unescape(escape(*s).as_slice()), Some(s.to_string()));
+        }
+        assert_eq!(escape(""), "\"\"".to_string());
+        assert_eq!(escape("a b%"), "a%20b%25".to_string());
+    }
+
+    #[test]
+    fn malformed_escapes_are_refused() {
+        assert_eq!(unescape("%"), None);
+        assert_eq!(unescape("a%"), None);
+        assert_eq!(unescape("%4"), None);
+        assert_eq!(unescape("%zz"), None);
+        assert_eq!(unescape("%4g1"), None);
+        assert_eq!(unescape("%41"), Some("A".to_string()));
+        // Escaped bytes have to make up valid UTF-8
+        assert_eq!(unescape("%FF"), None);
+    }
+}
@@ -20,13 +20,52 @@
 extern crate libc;
 extern crate sync;
 
+use std::any::{Any, AnyRefExt};
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::comm::{channel, Receiver, Sender};
 use std::mem;
 use std::ptr;
-use std::str;
-
+use std::rc::Rc;
+use std::time::Duration;
+use sync::mutex::{StaticMutex, MUTEX_INIT};
+
+pub use clipboard::ClipboardCallback;
+pub use dnd::DropCallback;
+pub use encode::{ImageCodec, CodecPng, CodecPpm};
+pub use frame::{FrameCallback, FrameInfo};
+pub use extn::ExtnCallback;
+pub use grid::ImageGrid;
+pub use ime::{ImeCallback, ImeEvent, ImePreedit, ImeCommit};
+pub use object::{EvasObject, EvasRectangle, EvasLine, EvasPolygon, EvasText, EvasImage, EvasPlug, EvasMap};
+pub use object::FocusKeyCallback;
+pub use object::{ImagePreloadedCallback, ImageOrient, OrientNone, Orient90, Orient180, Orient270};
+pub use object::{OrientFlipHorizontal, OrientFlipVertical, OrientTranspose, OrientTransverse};
+pub use repeat::{RepeatMode, RepeatDeliver, RepeatSuppress, RepeatMark};
+pub use text::TextInputCallback;
+pub use touch::{TouchHandler, TouchEvent, TouchBegan, TouchMoved, TouchEnded, TouchPoint};
+pub use transform::CoordinateTransform;
+
+mod clipboard;
+mod coalesce;
+mod dnd;
+pub mod ecore;
+mod encode;
+mod frame;
+mod extn;
 pub mod ffi;
+pub mod gesture;
+mod grid;
+mod ime;
+pub mod input;
+mod object;
+mod repeat;
+mod text;
+mod throttle;
+mod touch;
+mod transform;
+mod visibility;
 
-#[deriving(Clone)]
 pub struct Context {
     supported_engines: Vec<Engine>,
 }
@@ -35,49 +74,76 @@ pub struct Context {
 pub enum InitError {
     EcoreInitError,
     EcoreEvasInitError,
-    AlreadyInitialized,
+    /// ecore_evas initialised, but reported no rendering engines, so no
+    /// window could ever be created.
+    NoEnginesAvailable,
 }
 
-pub fn init() -> Result<Context, InitError> {
-    use sync::one::{Once, ONCE_INIT};
+static INIT_LOCK: StaticMutex = MUTEX_INIT;
+static mut INIT_COUNT: uint = 0;
 
-    static mut INIT: Once = ONCE_INIT;
-    let mut result = Err(AlreadyInitialized);
+/// Initialise ecore and ecore_evas. Initialisation is reference counted: every
+/// `Context` (including clones) holds a reference, and the libraries are shut
+/// down when the last one is dropped. `init` may be called again afterwards.
+pub fn init() -> Result<Context, InitError> {
     unsafe {
-        INIT.doit(|| {
-            result = if ffi::ecore_init() == 0 {
-                Err(EcoreInitError)
+        let _guard = INIT_LOCK.lock();
+        if INIT_COUNT == 0 {
+            if ffi::ecore_init() == 0 {
+                return Err(EcoreInitError);
             } else if ffi::ecore_evas_init() == 0 {
-                // Evas was not initialised, but we still need to shut down
-                // ecore on exiting
-                std::rt::at_exit(proc() {
-                    ffi::ecore_shutdown();
-                });
-                Err(EcoreEvasInitError)
-            } else {
-                // Get a list of the supported engines
-                let engines_ptr = ffi::ecore_evas_engines_get();
-                let engines = ffi::eina_list_iter(engines_ptr as *const _).map(|data| {
-                    Engine::parse(str::raw::from_c_str(data as *const _))
-                }).collect();
-                ffi::ecore_evas_engines_free(engines_ptr);
-
-                // We will need to shut down evas on exiting
-                std::rt::at_exit(proc() {
-                    ffi::ecore_evas_shutdown();
-                    ffi::ecore_shutdown();
-                });
+                // Evas was not initialised, but ecore was, so undo that
+                ffi::ecore_shutdown();
+                return Err(EcoreEvasInitError);
+            }
+        }
+        INIT_COUNT += 1;
+    }
 
-                Ok(Context { supported_engines: engines, })
-            };
-        });
+    // Get a list of the supported engines
+    let engines = unsafe {
+        let engines_ptr = ffi::ecore_evas_engines_get();
+        let engines = ffi::eina_list_iter(engines_ptr as *const _).map(|data| {
+            Engine::parse(ffi::string_from_c_ptr(data as *const _).unwrap_or(String::new()))
+        }).collect();
+        ffi::ecore_evas_engines_free(engines_ptr);
+        engines
+    };
+    // Dropping the context releases our reference on failure
+    let context = Context { supported_engines: engines, };
+    try!(check_engines(context.supported_engines.as_slice()));
+    Ok(context)
+}
+
+/// Fail if ecore_evas reported no engines to create windows with.
+fn check_engines(engines: &[Engine]) -> Result<(), InitError> {
+    if engines.is_empty() { Err(NoEnginesAvailable) } else { Ok(()) }
+}
+
+impl Clone for Context {
+    fn clone(&self) -> Context {
+        let _guard = INIT_LOCK.lock();
+        unsafe { INIT_COUNT += 1 };
+        Context { supported_engines: self.supported_engines.clone(), }
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        let _guard = INIT_LOCK.lock();
+        unsafe {
+            INIT_COUNT -= 1;
+            if INIT_COUNT == 0 {
+                ffi::ecore_evas_shutdown();
+                ffi::ecore_shutdown();
+            }
+        }
     }
-    result
 }
 
 // Generates an enum that specifies the possible engines that EFL can use.
 macro_rules! engines {
-    ($($Engine:ident => $name:pat),+) => {
+    ($($Engine:ident => $name:expr),+) => {
         /// A rendering engine identifier
         #[deriving(Clone, Show, PartialEq, Eq)]
         pub enum Engine {
@@ -87,18 +153,26 @@ macro_rules! engines {
 
         impl Engine {
             fn parse(src: String) -> Engine {
-                match src.as_slice() {
-                    $($name => $Engine,)+
-                    _ => Unknown(src),
-                }
+                $(if src.as_slice() == $name { return $Engine })+
+                Unknown(src)
             }
 
             fn get_efl_name<'a>(&'a self) -> &'a str {
                 match *self {
-                    $($Engine => stringify!($name),)+
+                    $($Engine => $name,)+
                     Unknown(ref src) => src.as_slice(),
                 }
             }
+
+            /// Whether the engine is hardware accelerated, rendering with
+            /// OpenGL or Direct3D. Only these engines use the GL settings of
+            /// a `WindowBuilder`; software and buffer engines ignore them.
+            pub fn is_gl(&self) -> bool {
+                match *self {
+                    OpenGlX11 | OpenglCocoa | OpenglSdl | WaylandEgl | Direct3d | OpenGlGlew => true,
+                    _ => false,
+                }
+            }
         }
     }
 }
@@ -128,10 +202,133 @@ impl Context {
         WindowBuilder {
             context: self,
             engine: None,
+            engine_preference: Vec::new(),
+            extra_options: None,
+            display: None,
             x: x, y: y,
             w: w, h: h,
-            gl_config: GlConfig::new(),
+            gl_settings: GlSettings::new(),
+            target: NewTarget,
+            visible: true,
+            title: None,
+            name_class: None,
+            role: None,
+        }
+    }
+
+    /// Build a window that renders into an in-memory buffer using the
+    /// `Buffer` engine. This does not need a display, so it can be used for
+    /// headless rendering, and the result can be read back with
+    /// `Window::buffer_pixels`.
+    pub fn build_buffer_window<'a>(&'a self, w: i32, h: i32) -> WindowBuilder<'a> {
+        self.build_window(0, 0, w, h).with_engine(Buffer)
+    }
+
+    /// Build a window like `build_window`, unless there is no display to
+    /// show it on, in which case it is built like `build_buffer_window` so
+    /// that it can still be rendered, such as in CI. See `has_display` for
+    /// how a display is detected. Setting `ECORE_EVAS_ENGINE` overrides the
+    /// detection, and the engine it names is used either way.
+    pub fn build_window_auto<'a>(&'a self, x: i32, y: i32, w: i32, h: i32) -> WindowBuilder<'a> {
+        if self.has_display() || std::os::getenv("ECORE_EVAS_ENGINE").is_some() {
+            self.build_window(x, y, w, h)
+        } else {
+            self.build_buffer_window(w, h)
+        }
+    }
+
+    /// Whether there appears to be a display for windows to be shown on.
+    /// On Unix other than Mac OS this checks that `DISPLAY` or
+    /// `WAYLAND_DISPLAY` is set and not empty, without connecting to it. Elsewhere there
+    /// is always assumed to be a display.
+    pub fn has_display(&self) -> bool {
+        has_display()
+    }
+
+    /// Build a window that renders into an offscreen X pixmap using the
+    /// `SoftwareX11` engine, for embedders that composite the result
+    /// themselves. Ecore_Evas always allocates the pixmap itself, with the
+    /// depth and visual of `parent` (or of the root window if `parent` is
+    /// 0); its ID can be read back with `Window::get_x11_pixmap`. Creating
+    /// the window fails if the `SoftwareX11` engine is unavailable. The
+    /// engine set with `with_engine` is ignored.
+    pub fn build_pixmap_window<'a>(&'a self, parent: u64, w: i32, h: i32) -> WindowBuilder<'a> {
+        let mut builder = self.build_window(0, 0, w, h).with_engine(SoftwareX11);
+        builder.target = PixmapTarget(parent);
+        builder
+    }
+
+    /// Build an offscreen window whose canvas is shared with other
+    /// processes, which can display it with `Window::new_plug`. The socket
+    /// listens on the service with the given name and number; a `system`
+    /// service is visible to every user rather than only this one. The
+    /// position and engine of the builder are ignored.
+    pub fn build_socket<'a>(&'a self, name: &str, number: i32, system: bool, w: i32, h: i32) -> WindowBuilder<'a> {
+        let mut builder = self.build_window(0, 0, w, h);
+        builder.target = SocketTarget(name.to_string(), number, system);
+        builder
+    }
+
+    /// Non-owning references to every ecore_evas currently alive in the
+    /// process, including those owned by a `Window`.
+    pub fn windows<'a>(&'a self) -> std::vec::MoveItems<WindowRef<'a>> {
+        unsafe {
+            let list = ffi::ecore_evas_ecore_evas_list_get();
+            let windows: Vec<WindowRef<'a>> = ffi::eina_list_iter(list as *const _).map(|data| {
+                WindowRef { context: self, ee: data as *mut _ }
+            }).collect();
+            ffi::eina_list_free(list);
+            windows.move_iter()
+        }
+    }
+
+    /// The screens attached to the display. If a window is using an X11
+    /// engine, these are the active RandR outputs of its X screen.
+    /// Otherwise, or if RandR is unavailable, a single screen is synthesized
+    /// from the screen of the first window, and the list is empty if there
+    /// are no windows.
+    pub fn screens(&self) -> Vec<ScreenInfo> {
+        // Only ask the X server if a window is already using it, so that
+        // other engines never connect to X
+        for &win in self.windows().filter_map(|window| window.x11_window()).next().iter() {
+            match x11_screens(win) {
+                Some(screens) => return screens,
+                None => {},
+            }
         }
+        self.windows().next().map_or(Vec::new(), |window| {
+            let (mut x, mut y, mut w, mut h) = (0, 0, 0, 0);
+            let (mut xdpi, mut ydpi) = (0, 0);
+            unsafe {
+                ffi::ecore_evas_screen_geometry_get(window.ee as *const _, &mut x, &mut y, &mut w, &mut h);
+                ffi::ecore_evas_screen_dpi_get(window.ee as *const _, &mut xdpi, &mut ydpi);
+            }
+            vec![ScreenInfo {
+                name: "default".to_string(),
+                geometry: Rect::new(x, y, w, h),
+                dpi: (xdpi as i32, ydpi as i32),
+            }]
+        })
+    }
+
+    /// Set the engine used by the `Ews` engine. EWS (Ecore Evas Window
+    /// System) renders every `Ews` window as an object inside a single real
+    /// window, which is created with this engine. This must be called before
+    /// the first `Ews` window is created.
+    pub fn ews_backing_engine_set(&self, engine: &Engine) -> Result<(), ()> {
+        let ok = unsafe {
+            engine.get_efl_name().with_c_str(|name| ffi::ecore_evas_ews_engine_set(name, ptr::null()))
+        };
+        if ffi::from_eina_bool(ok) { Ok(()) } else { Err(()) }
+    }
+
+    /// Set the geometry of the real window that `Ews` windows are rendered
+    /// into.
+    pub fn ews_setup(&self, x: i32, y: i32, w: i32, h: i32) -> Result<(), ()> {
+        let ok = unsafe {
+            ffi::ecore_evas_ews_setup(x as libc::c_int, y as libc::c_int, w as libc::c_int, h as libc::c_int)
+        };
+        if ffi::from_eina_bool(ok) { Ok(()) } else { Err(()) }
     }
 
     pub fn main_loop_begin(&self) {
@@ -146,17 +343,298 @@ impl Context {
     pub fn get_supported_engines<'a>(&'a self) -> &'a [Engine] {
         self.supported_engines.as_slice()
     }
+
+    /// Release this context. This is the same as dropping it: ecore and
+    /// ecore_evas are only shut down once every context has been released.
+    pub fn shutdown(self) {}
+}
+
+/// A screen attached to the display.
+#[deriving(Clone, PartialEq, Show)]
+pub struct ScreenInfo {
+    pub name: String,
+    /// The area of the screen, in the coordinate space shared by all
+    /// screens.
+    pub geometry: Rect,
+    /// The horizontal and vertical resolution, in dots per inch, or zero if
+    /// the physical size of the screen is unknown.
+    pub dpi: (i32, i32),
+}
+
+/// The RandR outputs of the X screen that the given window is on.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn x11_screens(win: u32) -> Option<Vec<ScreenInfo>> {
+    unsafe {
+        if ffi::x11::ecore_x_init(ptr::null()) == 0 { return None }
+        let root = ffi::x11::ecore_x_window_root_get(win as ffi::x11::Ecore_X_Window);
+        let mut num = 0;
+        let outputs = ffi::x11::ecore_x_randr_outputs_get(root, &mut num);
+        let mut screens = Vec::new();
+        if !outputs.is_null() {
+            std::slice::raw::buf_as_slice(outputs as *const _, num as uint, |outputs| {
+                for &output in outputs.iter() {
+                    // Outputs without a CRTC are disconnected or disabled
+                    let crtc = ffi::x11::ecore_x_randr_output_crtc_get(root, output);
+                    if crtc == 0 { continue }
+                    let (mut x, mut y, mut w, mut h) = (0, 0, 0, 0);
+                    ffi::x11::ecore_x_randr_crtc_geometry_get(root, crtc, &mut x, &mut y, &mut w, &mut h);
+                    let (mut w_mm, mut h_mm) = (0, 0);
+                    ffi::x11::ecore_x_randr_output_size_mm_get(root, output, &mut w_mm, &mut h_mm);
+                    let dpi = |px: libc::c_int, mm: libc::c_int| {
+                        if mm > 0 { (px as f64 * 25.4 / mm as f64).round() as i32 } else { 0 }
+                    };
+                    let mut len = 0;
+                    let name = ffi::x11::ecore_x_randr_output_name_get(root, output, &mut len);
+                    screens.push(ScreenInfo {
+                        name: ffi::string_from_c_ptr(name as *const _).unwrap_or(String::new()),
+                        geometry: Rect::new(x, y, w, h),
+                        dpi: (dpi(w, w_mm), dpi(h, h_mm)),
+                    });
+                    if !name.is_null() { libc::free(name as *mut libc::c_void) }
+                }
+            });
+            libc::free(outputs as *mut libc::c_void);
+        }
+        ffi::x11::ecore_x_shutdown();
+        if screens.is_empty() { None } else { Some(screens) }
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn x11_screens(_win: u32) -> Option<Vec<ScreenInfo>> {
+    None
+}
+
+/// The options passed to `ecore_evas_new`: the extra options, followed by the
+/// display if there is one. Engines use the last `display` option they find,
+/// so the display has to come after any given in the extra options.
+fn engine_options(extra_options: Option<String>, display: Option<&str>) -> Option<String> {
+    let display = match display {
+        Some(display) => display,
+        None => return extra_options,
+    };
+    let mut options = extra_options.unwrap_or(String::new());
+    if !options.is_empty() && !options.as_slice().ends_with(";") {
+        options.push_char(';');
+    }
+    options.push_str(format!("display={};", display).as_slice());
+    Some(options)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn has_display() -> bool {
+    display_in_env(|name| std::os::getenv(name))
+}
+
+/// Whether `getenv` finds a display variable set to something other than an
+/// empty string
+#[cfg(all(unix, not(target_os = "macos")))]
+fn display_in_env(getenv: |&str| -> Option<String>) -> bool {
+    for name in ["DISPLAY", "WAYLAND_DISPLAY"].iter() {
+        if getenv(*name).map_or(false, |value| !value.is_empty()) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn has_display() -> bool {
+    true
 }
 
 /// Surface color format.
 #[repr(u32)]
+#[deriving(Clone, PartialEq, Eq, Show)]
 pub enum ColorFormat {
     Rgb888 = ffi::EVAS_GL_RGB_888,
     Rgba8888 = ffi::EVAS_GL_RGBA_8888,
 }
 
+/// A single color channel of a pixel.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Channel {
+    ChannelRed,
+    ChannelGreen,
+    ChannelBlue,
+    ChannelAlpha,
+}
+
+static RGB_CHANNELS: &'static [Channel] = &[ChannelRed, ChannelGreen, ChannelBlue];
+static RGBA_CHANNELS: &'static [Channel] = &[ChannelRed, ChannelGreen, ChannelBlue, ChannelAlpha];
+
+impl ColorFormat {
+    /// The size of a single pixel in bytes.
+    pub fn bytes_per_pixel(&self) -> uint {
+        self.channel_order().len()
+    }
+
+    /// The channels of a pixel, in the order they are laid out in memory.
+    /// Each channel is one byte.
+    pub fn channel_order(&self) -> &'static [Channel] {
+        match *self {
+            Rgb888 => RGB_CHANNELS,
+            Rgba8888 => RGBA_CHANNELS,
+        }
+    }
+}
+
+/// The pixel layout of image data.
+#[repr(u32)]
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Colorspace {
+    /// Premultiplied ARGB, one native-endian `u32` per pixel.
+    Argb8888 = ffi::EVAS_COLORSPACE_ARGB8888,
+    /// A plane of native-endian RGB565 `u16` pixels, followed by a plane of
+    /// one byte alpha values in the range 0 to 32.
+    Rgb565A5p = ffi::EVAS_COLORSPACE_RGB565_A5P,
+    /// One byte of gray per pixel.
+    Gry8 = ffi::EVAS_COLORSPACE_GRY8,
+    /// Planar YCbCr 4:2:2 in ITU-R BT.601: a Y plane, followed by Cb and Cr
+    /// planes of half the width.
+    YCbCr422P601 = ffi::EVAS_COLORSPACE_YCBCR422P601_PL,
+    /// Planar YCbCr 4:2:2 in ITU-R BT.709, laid out like `YCbCr422P601`.
+    YCbCr422P709 = ffi::EVAS_COLORSPACE_YCBCR422P709_PL,
+    /// Packed YCbCr 4:2:2 in ITU-R BT.601, as YUYV.
+    YCbCr422601 = ffi::EVAS_COLORSPACE_YCBCR422601_PL,
+    /// YCbCr 4:2:0 in ITU-R BT.601 as NV12: a Y plane, followed by a plane
+    /// of interleaved Cb and Cr at half the width and height.
+    YCbCr420Nv12601 = ffi::EVAS_COLORSPACE_YCBCR420NV12601_PL,
+}
+
+impl Colorspace {
+    /// The number of bytes needed for an image of the given size.
+    pub fn data_size(&self, w: i32, h: i32) -> uint {
+        let (w, h) = (w as uint, h as uint);
+        let (half_w, half_h) = ((w + 1) / 2, (h + 1) / 2);
+        match *self {
+            Argb8888 => w * h * 4,
+            Rgb565A5p => w * h * 3,
+            Gry8 => w * h,
+            YCbCr422P601 | YCbCr422P709 => w * h + half_w * h * 2,
+            YCbCr422601 => half_w * 4 * h,
+            YCbCr420Nv12601 => w * h + half_w * 2 * half_h,
+        }
+    }
+
+    /// Evas takes YCbCr data as a table of pointers to the start of each
+    /// row, rather than as a contiguous buffer.
+    fn is_ycbcr(&self) -> bool {
+        match *self {
+            Argb8888 | Rgb565A5p | Gry8 => false,
+            _ => true,
+        }
+    }
+
+    /// The offset of the start of each row in the data of an image of the
+    /// given size, for the colorspaces that Evas needs a row table for.
+    fn row_offsets(&self, w: i32, h: i32) -> Vec<uint> {
+        let (w, h) = (w as uint, h as uint);
+        let (half_w, half_h) = ((w + 1) / 2, (h + 1) / 2);
+        let mut offsets = Vec::new();
+        match *self {
+            YCbCr422P601 | YCbCr422P709 => {
+                offsets.extend(range(0, h).map(|row| row * w));
+                offsets.extend(range(0, h * 2).map(|row| w * h + row * half_w));
+            },
+            YCbCr422601 => {
+                offsets.extend(range(0, h).map(|row| row * half_w * 4));
+            },
+            YCbCr420Nv12601 => {
+                offsets.extend(range(0, h).map(|row| row * w));
+                offsets.extend(range(0, half_h).map(|row| w * h + row * half_w * 2));
+            },
+            Argb8888 | Rgb565A5p | Gry8 => {},
+        }
+        offsets
+    }
+}
+
+/// Pixel data that the window's image is drawing from directly, along with
+/// the row table pointing into it for YCbCr colorspaces. These are only held
+/// to keep them alive.
+#[allow(dead_code)]
+struct ImageData {
+    data: Vec<u8>,
+    rows: Vec<*const u8>,
+}
+
+/// An 8-bit per channel RGBA color. Whether the color channels are
+/// premultiplied by alpha is up to the user: Evas expects premultiplied
+/// colors, which can be obtained with `premultiply`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r: r, g: g, b: b, a: a }
+    }
+
+    /// Scale the color channels by alpha.
+    pub fn premultiply(&self) -> Color {
+        let mul = |c: u8| ((c as u32 * self.a as u32 + 127) / 255) as u8;
+        Color { r: mul(self.r), g: mul(self.g), b: mul(self.b), a: self.a }
+    }
+
+    /// Undo `premultiply`. Fully transparent colors become transparent black,
+    /// as their color information has been lost.
+    pub fn unpremultiply(&self) -> Color {
+        if self.a == 0 { return Color::new(0, 0, 0, 0) }
+        let div = |c: u8| {
+            cmp::min(255, (c as u32 * 255 + self.a as u32 / 2) / self.a as u32) as u8
+        };
+        Color { r: div(self.r), g: div(self.g), b: div(self.b), a: self.a }
+    }
+
+    /// Pack the color into the 32-bit ARGB layout used for Evas image data.
+    pub fn to_argb32(&self) -> u32 {
+        (self.a as u32 << 24) | (self.r as u32 << 16) | (self.g as u32 << 8) | self.b as u32
+    }
+
+    pub fn from_argb32(pixel: u32) -> Color {
+        Color {
+            a: (pixel >> 24) as u8,
+            r: (pixel >> 16) as u8,
+            g: (pixel >> 8) as u8,
+            b: pixel as u8,
+        }
+    }
+
+    /// The bytes of a single pixel of this color in the given format.
+    pub fn to_bytes(&self, format: ColorFormat) -> Vec<u8> {
+        format.channel_order().iter().map(|channel| match *channel {
+            ChannelRed => self.r,
+            ChannelGreen => self.g,
+            ChannelBlue => self.b,
+            ChannelAlpha => self.a,
+        }).collect()
+    }
+
+    /// Read a single pixel in the given format. Returns `None` if `bytes` is
+    /// not exactly one pixel long. Formats without alpha are opaque.
+    pub fn from_bytes(format: ColorFormat, bytes: &[u8]) -> Option<Color> {
+        if bytes.len() != format.bytes_per_pixel() { return None }
+        let mut color = Color::new(0, 0, 0, 255);
+        for (channel, &byte) in format.channel_order().iter().zip(bytes.iter()) {
+            match *channel {
+                ChannelRed => color.r = byte,
+                ChannelGreen => color.g = byte,
+                ChannelBlue => color.b = byte,
+                ChannelAlpha => color.a = byte,
+            }
+        }
+        Some(color)
+    }
+}
+
 /// Surface depth format.
 #[repr(u32)]
+#[deriving(Clone, PartialEq, Eq, Show)]
 pub enum DepthBits {
     DepthBits8 = ffi::EVAS_GL_DEPTH_BIT_8,
     DepthBits16 = ffi::EVAS_GL_DEPTH_BIT_16,
@@ -165,6 +643,7 @@ pub enum DepthBits {
 }
 /// Surface stencil format.
 #[repr(u32)]
+#[deriving(Clone, PartialEq, Eq, Show)]
 pub enum StencilBits {
     StencilBits1 = ffi::EVAS_GL_STENCIL_BIT_1,
     StencilBits2 = ffi::EVAS_GL_STENCIL_BIT_2,
@@ -187,6 +666,7 @@ bitflags! {
 ///
 /// Only works for supported devices.
 #[repr(u32)]
+#[deriving(Clone, PartialEq, Eq, Show)]
 pub enum MultisampleBits {
     /// MSAA with a minimum number of samples.
     MultisampleLow = ffi::EVAS_GL_MULTISAMPLE_LOW,
@@ -196,6 +676,56 @@ pub enum MultisampleBits {
     MultisampleHigh = ffi::EVAS_GL_MULTISAMPLE_HIGH,
 }
 
+impl MultisampleBits {
+    /// The number of samples Evas_GL uses for this setting on a device
+    /// supporting at most `max_samples`. `MultisampleLow` is the smallest
+    /// useful sample count, 2. Devices supporting fewer than 2 samples get
+    /// no multisampling at all, which is reported as 0.
+    pub fn sample_count(&self, max_samples: u32) -> u32 {
+        if max_samples < 2 { return 0 }
+        match *self {
+            MultisampleLow => 2,
+            MultisampleMedium => cmp::max(2, max_samples / 2),
+            MultisampleHigh => max_samples,
+        }
+    }
+}
+
+/// A set of OpenGL surface parameters to test for support with
+/// `Window::gl_config_supported`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct GlConfigQuery {
+    pub color_format: ColorFormat,
+    pub depth: Option<DepthBits>,
+    pub stencil: Option<StencilBits>,
+    pub multisample: Option<MultisampleBits>,
+}
+
+/// The OpenGL configuration chosen for a window, kept as plain values so that
+/// a builder can be duplicated, and only turned into an `Evas_GL_Config` if
+/// the window's engine uses OpenGL. The defaults match those of a freshly
+/// allocated `Evas_GL_Config`.
+#[deriving(Clone, PartialEq)]
+struct GlSettings {
+    color_format: ColorFormat,
+    depth: Option<DepthBits>,
+    stencil: Option<StencilBits>,
+    options: GlOptions,
+    multisample: Option<MultisampleBits>,
+}
+
+impl GlSettings {
+    fn new() -> GlSettings {
+        GlSettings {
+            color_format: Rgb888,
+            depth: None,
+            stencil: None,
+            options: GlOptionsNone,
+            multisample: None,
+        }
+    }
+}
+
 /// A struct that holds the OpenGL configuration. EFL requires us to allocate
 /// and deallocate the configuration instead of instantiating the struct
 /// ourselves for backwards compatibility reasons.
@@ -210,6 +740,17 @@ impl GlConfig {
         GlConfig { ptr: ptr }
     }
 
+    /// Allocate a configuration populated with the given settings
+    fn from_settings(settings: &GlSettings) -> GlConfig {
+        let mut config = GlConfig::new();
+        config.set_color_format(settings.color_format.clone());
+        config.set_depth(settings.depth.clone());
+        config.set_stencil(settings.stencil.clone());
+        config.set_options(settings.options);
+        config.set_multisample(settings.multisample.clone());
+        config
+    }
+
     fn set_color_format(&mut self, format: ColorFormat) {
         unsafe { (*self.ptr).color_format = format as libc::c_uint };
     }
@@ -237,116 +778,549 @@ impl Drop for GlConfig {
     }
 }
 
+#[deriving(Show)]
+pub enum WindowError {
+    /// `ecore_evas_new` failed with the given engine, or with automatic
+    /// engine selection if no engine was specified.
+    EngineCreateError(Option<Engine>),
+    /// `ecore_evas_new` failed with an engine that is not in the context's
+    /// list of supported engines.
+    UnsupportedEngine(Engine),
+    /// A socket could not listen on the service with the given name, which
+    /// may already be in use.
+    SocketListenError(String),
+    /// The window could not be created on the display given with
+    /// `WindowBuilder::with_display`, which may not exist or refuse the
+    /// connection.
+    DisplayOpenError(String),
+    /// The display given with `WindowBuilder::with_display` contains a `;`,
+    /// which would pass other options to the engine.
+    InvalidDisplay(String),
+}
+
+/// What the ecore_evas of a window is created as
+#[deriving(Clone)]
+enum WindowTarget {
+    /// A regular window, created with `ecore_evas_new`
+    NewTarget,
+    /// An offscreen X pixmap, with the visual of the given parent window
+    PixmapTarget(u64),
+    /// An Ecore_Evas_Extn socket, listening on the given service
+    SocketTarget(String, i32, bool),
+}
+
+#[deriving(Clone)]
 pub struct WindowBuilder<'a> {
     context: &'a Context,
     engine: Option<Engine>,
+    /// The engines to try in order, if no single engine was specified
+    engine_preference: Vec<Engine>,
+    /// The engine specific options passed to `ecore_evas_new`
+    extra_options: Option<String>,
+    /// The X display or Wayland socket to open the window on
+    display: Option<String>,
     x: i32, y: i32,
     w: i32, h: i32,
-    gl_config: GlConfig,
+    gl_settings: GlSettings,
+    target: WindowTarget,
+    visible: bool,
+    title: Option<String>,
+    name_class: Option<(String, String)>,
+    role: Option<String>,
 }
 
 impl<'a> WindowBuilder<'a> {
     /// Specify the rendering engine to use with the window. If this is not
     /// specified the first working rendering engine will be used.
     pub fn with_engine(mut self, engine: Engine) -> WindowBuilder<'a> {
-        self.engine = Some(engine); self
+        self.engine = Some(engine);
+        self.engine_preference.clear();
+        self
+    }
+
+    /// Specify the rendering engines to try, in order of preference. Engines
+    /// that are not in the context's list of supported engines are skipped,
+    /// and if none of the others can create the window the first working
+    /// engine is used, as if no engine was specified. This replaces an
+    /// engine set with `with_engine`. The engine that was used can be found
+    /// with `Window::get_engine`.
+    pub fn with_engine_preference(mut self, engines: &[Engine]) -> WindowBuilder<'a> {
+        self.engine = None;
+        self.engine_preference = engines.to_vec();
+        self
     }
 
+    /// Pass engine specific options to `ecore_evas_new`, as a list of
+    /// `key=value;` pairs that the engine parses itself. Unknown keys are
+    /// ignored. The options known to the engines are:
+    ///
+    /// - `SoftwareX11` and `OpenGlX11`: `display` (the X display to connect
+    ///   to, such as `:1`) and `parent` (the ID of a window to embed in).
+    ///   `OpenGlX11` also takes `vsync` (`1` to sync to the vertical
+    ///   blank).
+    /// - `WaylandShm` and `WaylandEgl`: `display` (the Wayland socket name,
+    ///   such as `wayland-1`), `parent` and `frame` (`0` for no client side
+    ///   decorations).
+    /// - `Fb`: `display` (the number of the framebuffer device).
+    /// - `Sdl` and `OpenglSdl`: `name` (the window title) and `swrender`.
+    ///
+    /// The options are ignored by windows built with `build_pixmap_window`
+    /// and `build_socket`.
+    pub fn with_extra_options(mut self, options: &str) -> WindowBuilder<'a> {
+        self.extra_options = Some(options.to_string()); self
+    }
+
+    /// Open the window on a specific display: an X display such as `:1` for
+    /// the X11 engines, or a Wayland socket name such as `wayland-1` for
+    /// the Wayland engines. This is passed to the engine as its `display`
+    /// option (see `with_extra_options`), after the extra options, so that
+    /// it takes precedence over one given there. If the window can't be created, `create` fails with
+    /// `DisplayOpenError`, and with `InvalidDisplay` if the display contains
+    /// a `;`. Like the other options, this is ignored by windows built with
+    /// `build_pixmap_window` and `build_socket`.
+    pub fn with_display(mut self, display: &str) -> WindowBuilder<'a> {
+        self.display = Some(display.to_string()); self
+    }
+
+    /// The GL settings below only apply to engines for which `Engine::is_gl`
+    /// is true. If any of them are changed from their defaults and the
+    /// window ends up with a software engine, a warning naming the engine is
+    /// printed to stderr when it is created.
     pub fn with_color_format(mut self, format: ColorFormat) -> WindowBuilder<'a> {
-        self.gl_config.set_color_format(format); self
+        self.gl_settings.color_format = format; self
     }
 
     pub fn with_gl_depth(mut self, depth: Option<DepthBits>) -> WindowBuilder<'a> {
-        self.gl_config.set_depth(depth); self
+        self.gl_settings.depth = depth; self
     }
 
     pub fn with_gl_stencil(mut self, stencil: Option<StencilBits>) -> WindowBuilder<'a> {
-        self.gl_config.set_stencil(stencil); self
+        self.gl_settings.stencil = stencil; self
     }
 
     pub fn with_gl_options(mut self, options: GlOptions) -> WindowBuilder<'a> {
-        self.gl_config.set_options(options); self
+        self.gl_settings.options = options; self
     }
 
     pub fn with_gl_multisample(mut self, multisample: Option<MultisampleBits>) -> WindowBuilder<'a> {
-        self.gl_config.set_multisample(multisample); self
+        self.gl_settings.multisample = multisample; self
+    }
+
+    /// A copy of this builder, with the same engine, geometry and settings,
+    /// so that several similar windows can be created from one
+    /// configuration. Each window created gets its own GL configuration.
+    pub fn duplicate(&self) -> WindowBuilder<'a> {
+        self.clone()
+    }
+
+    /// Whether the window is shown as soon as it is created. Defaults to
+    /// true. A window created hidden can be fully set up, callbacks
+    /// included, before it is presented with `Window::show`.
+    pub fn with_visible(mut self, visible: bool) -> WindowBuilder<'a> {
+        self.visible = visible; self
     }
 
-    pub fn create(self) -> Result<Window<'a>, ()> {
+    /// Set the title of the window before it is shown.
+    pub fn with_title(mut self, title: &str) -> WindowBuilder<'a> {
+        self.title = Some(title.to_string()); self
+    }
+
+    /// Set the name and class of the window, which window managers use to
+    /// match their rules against it (`WM_CLASS` on X11).
+    pub fn with_name_class(mut self, name: &str, class: &str) -> WindowBuilder<'a> {
+        self.name_class = Some((name.to_string(), class.to_string())); self
+    }
+
+    /// Set the role of the window, which distinguishes between the windows
+    /// of an application with the same name and class (`WM_WINDOW_ROLE` on
+    /// X11).
+    pub fn with_role(mut self, role: &str) -> WindowBuilder<'a> {
+        self.role = Some(role.to_string()); self
+    }
+
+    pub fn create(self) -> Result<Window<'a>, WindowError> {
         let WindowBuilder {
             context,
             engine,
+            engine_preference,
+            extra_options,
+            display,
             x, y, w, h,
-            gl_config,
+            gl_settings,
+            target,
+            visible,
+            title,
+            name_class,
+            role,
         } = self;
 
+        for display in display.iter() {
+            if display.as_slice().contains_char(';') {
+                return Err(InvalidDisplay(display.clone()));
+            }
+        }
+        let is_new = match target { NewTarget => true, _ => false };
+        let extra_options = engine_options(extra_options, display.as_ref().map(|d| d.as_slice()));
+        let options = extra_options.as_ref().map(|options| options.as_slice());
         let ee = unsafe {
-            match engine {
-                Some(ref engine) => engine.get_efl_name().with_c_str(|name| {
-                    ffi::ecore_evas_new(name, x, y, w, h, ptr::null())
-                }),
-                None => {
-                    ffi::ecore_evas_new(ptr::null(), x, y, w, h, ptr::null())
+            match (target, &engine) {
+                (PixmapTarget(parent), _) => {
+                    ffi::ecore_evas_software_x11_pixmap_new(ptr::null(), parent as ffi::Ecore_X_Window, x, y, w, h)
+                },
+                (SocketTarget(name, number, system), _) => {
+                    let ee = ffi::ecore_evas_extn_socket_new(w, h);
+                    let listening = !ee.is_null() && name.with_c_str(|name| {
+                        ffi::from_eina_bool(ffi::ecore_evas_extn_socket_listen(
+                            ee, name, number as libc::c_int, ffi::to_eina_bool(system),
+                        ))
+                    });
+                    if !listening {
+                        if !ee.is_null() { ffi::ecore_evas_free(ee) }
+                        return Err(SocketListenError(name));
+                    }
+                    ee
+                },
+                (NewTarget, &Some(ref engine)) => {
+                    new_ecore_evas(Some(engine), x, y, w, h, options)
+                },
+                (NewTarget, &None) => {
+                    let mut ee = ptr::mut_null();
+                    for engine in engine_preference.iter().filter(|e| context.supported_engines.contains(*e)) {
+                        ee = new_ecore_evas(Some(engine), x, y, w, h, options);
+                        if !ee.is_null() { break }
+                    }
+                    if ee.is_null() {
+                        ee = new_ecore_evas(None, x, y, w, h, options);
+                    }
+                    ee
                 },
             }
         };
         if !ee.is_null() {
+            // These need to be set before the window is mapped for window
+            // manager rules to apply to it
+            for title in title.iter() {
+                unsafe { title.with_c_str(|title| ffi::ecore_evas_title_set(ee, title)) };
+            }
+            for &(ref name, ref class) in name_class.iter() {
+                unsafe {
+                    name.with_c_str(|name| class.with_c_str(|class| {
+                        ffi::ecore_evas_name_class_set(ee, name, class)
+                    }))
+                };
+            }
+            for role in role.iter() {
+                unsafe { role.with_c_str(|role| ffi::ecore_evas_role_set(ee, role)) };
+            }
             let canvas = unsafe { ffi::ecore_evas_get(ee as *const _) };
             let object = unsafe { ffi::evas_object_image_add(canvas) };
-            let window = Window {
+            let engine_name = unsafe {
+                ffi::string_from_c_ptr(ffi::ecore_evas_engine_name_get(ee as *const _)).unwrap_or(String::new())
+            };
+            // Software engines have no use for the GL configuration
+            let gl_config = if Engine::parse(engine_name.clone()).is_gl() {
+                Some(GlConfig::from_settings(&gl_settings))
+            } else {
+                if gl_settings != GlSettings::new() {
+                    let _ = writeln!(&mut std::io::stderr(),
+                                     "efl: GL settings are ignored by the {} engine", engine_name);
+                }
+                None
+            };
+            let data = box WindowData {
                 context: context,
                 ee: ee,
+                engine_name: engine_name,
                 canvas: canvas,
                 object: object,
-                _gl_config: gl_config,
+                gl_config: gl_config,
                 event_callbacks: EventCallbacks::new(),
                 input_callbacks: InputCallbacks::new(),
+                damage: RefCell::new(DamageRegion::new()),
+                user_data: None,
+                image_data: None,
+                image_colorspace: Argb8888,
+                content_scale: Cell::new(1.0),
+                logical_coords: Cell::new(false),
+                coordinate_transform: None,
+                letterbox: Cell::new(None),
+                background: Cell::new(ptr::mut_null()),
+                background_color: Cell::new(None),
+                sticky_change_callback: None,
+                profile: RefCell::new(None),
+                profile_change_callback: None,
+                rotation: Cell::new(0),
+                device_rotation_callback: None,
+                state: Cell::new(WindowState::new()),
+                state_changed_callback: CallbackSlot::new(),
+                clipboard: RefCell::new(clipboard::ClipboardState::new()),
+                dnd: dnd::DndState::new(),
+                ime: ime::ImeState::new(),
+                extn: extn::ExtnState::new(),
+                coalesce: coalesce::CoalesceState::new(),
+                touch: touch::TouchState::new(),
+                key_repeat: repeat::RepeatState::new(),
+                text_input_callback: None,
+                focus: object::FocusState::new(),
+                frame: frame::FrameState::new(),
+                throttle: throttle::ThrottleState::new(),
+                visibility: visibility::VisibilityState::new(),
             };
+            let window = Window { data: unsafe { mem::transmute(data) }, owned: true };
             unsafe {
                 ffi::evas_object_resize(window.object, w, h);
                 ffi::evas_object_focus_set(window.object, ffi::EINA_TRUE);
-                ffi::evas_object_show(window.object);
-                // We store a pointer back to the window so that the
-                // `extern "C"` event callbacks can access their corresponding
-                // Rust callbacks in the `EventCallbacks` vtable.
-                let window_ptr: *const Window = &window;
-                Window::data_ptr_key().with_c_str(|key| {
-                    ffi::ecore_evas_data_set(window.ee, key, window_ptr as *const _)
-                });
+                if visible {
+                    ffi::evas_object_show(window.object);
+                    ffi::ecore_evas_show(window.ee);
+                }
             }
+            window.bind_data_ptr();
+            window.state.set(window.get_state());
+            window.register_internal_callbacks();
+            visibility::register(&window);
             Ok(window)
+        } else if engine.as_ref().map_or(false, |engine| !context.supported_engines.contains(engine)) {
+            Err(UnsupportedEngine(engine.unwrap()))
         } else {
-            Err(())
+            match display {
+                // Only regular windows are opened on the display
+                Some(ref display) if is_new => Err(DisplayOpenError(display.clone())),
+                _ => Err(EngineCreateError(engine)),
+            }
         }
     }
 }
 
+/// Create a regular window with `ecore_evas_new`, using the given engine or
+/// automatic engine selection.
+unsafe fn new_ecore_evas(engine: Option<&Engine>, x: i32, y: i32, w: i32, h: i32,
+                         options: Option<&str>) -> *mut ffi::Ecore_Evas {
+    let new = |name: *const libc::c_char| match options {
+        Some(options) => options.with_c_str(|options| ffi::ecore_evas_new(name, x, y, w, h, options)),
+        None => ffi::ecore_evas_new(name, x, y, w, h, ptr::null()),
+    };
+    match engine {
+        Some(engine) => engine.get_efl_name().with_c_str(|name| new(name)),
+        None => new(ptr::null()),
+    }
+}
+
+/// Window sizing hints for the window manager. Sizes are `(width, height)`
+/// pairs.
+#[deriving(Clone, PartialEq, Show, Default)]
+pub struct SizeHints {
+    pub min: Option<(i32, i32)>,
+    pub max: Option<(i32, i32)>,
+    pub base: Option<(i32, i32)>,
+    pub step: Option<(i32, i32)>,
+    pub aspect: Option<f64>,
+}
+
+/// A point on the edge or center of a window, used to keep that point fixed
+/// when resizing.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Anchor {
+    AnchorTopLeft,
+    AnchorTop,
+    AnchorTopRight,
+    AnchorLeft,
+    AnchorCenter,
+    AnchorRight,
+    AnchorBottomLeft,
+    AnchorBottom,
+    AnchorBottomRight,
+}
+
+/// A window and the canvas drawn in it. The window's state is kept on the
+/// heap, where it stays for the life of the window, and that is what the EFL
+/// callbacks are registered with, so a `Window` can be moved freely.
 pub struct Window<'a> {
+    data: *mut WindowData<'a>,
+    /// Whether the window is freed when this is dropped. The windows passed
+    /// to callbacks only borrow the state of the window they are for.
+    owned: bool,
+}
+
+#[allow(visible_private_types)]
+impl<'a> Deref<WindowData<'a>> for Window<'a> {
+    fn deref<'b>(&'b self) -> &'b WindowData<'a> {
+        unsafe { &*self.data }
+    }
+}
+
+#[allow(visible_private_types)]
+impl<'a> DerefMut<WindowData<'a>> for Window<'a> {
+    fn deref_mut<'b>(&'b mut self) -> &'b mut WindowData<'a> {
+        unsafe { &mut *self.data }
+    }
+}
+
+/// The state of a window, behind its `Window`
+struct WindowData<'a> {
     context: &'a Context,
     ee: *mut ffi::Ecore_Evas,
-    #[allow(dead_code)]
+    /// The name of the engine, which is fixed for the life of the window
+    engine_name: String,
     canvas: *mut ffi::Evas,
     object: *mut ffi::Evas_Object,
-    /// Carry these parameters for the lifetime of the window, and destroy them
-    /// at afterwards
-    _gl_config: GlConfig,
+    /// Carry these parameters for the lifetime of the window, if its engine
+    /// uses them. They are only freed once the ecore_evas is, as any GL
+    /// surface on the canvas may have been created from them (see the `Drop`
+    /// implementation).
+    gl_config: Option<GlConfig>,
     /// The vtable of event callbacks associated with the window
     event_callbacks: EventCallbacks,
     /// The vtable of input callbacks associated with the window
     input_callbacks: InputCallbacks,
+    /// The regions damaged since the last manual render
+    damage: RefCell<DamageRegion>,
+    /// Arbitrary state attached by the user
+    user_data: Option<Box<Any>>,
+    /// A pixel buffer that the window's image is drawing from directly
+    image_data: Option<ImageData>,
+    /// The layout of the window's image data
+    image_colorspace: Colorspace,
+    /// The number of device pixels per logical pixel
+    content_scale: Cell<f64>,
+    /// Whether input events are reported in logical pixels
+    logical_coords: Cell<bool>,
+    /// A user supplied mapping applied to input event coordinates
+    coordinate_transform: Option<Box<transform::CoordinateTransform>>,
+    /// The size of the content to letterbox the image to, if enabled
+    letterbox: Cell<Option<(i32, i32)>>,
+    /// The rectangle filling the canvas behind everything else, if there is
+    /// a background color or the image is letterboxed
+    background: Cell<*mut ffi::Evas_Object>,
+    /// The color of the background, if one was set
+    background_color: Cell<Option<Color>>,
+    /// Called when the window becomes sticky or stops being sticky
+    sticky_change_callback: Option<Box<StickyChangeCallback>>,
+    /// The profile last reported to the profile change callback
+    profile: RefCell<Option<String>>,
+    /// Called when the window manager switches the window's profile
+    profile_change_callback: Option<Box<ProfileChangeCallback>>,
+    /// The rotation last reported to the device rotation callback
+    rotation: Cell<i32>,
+    /// Called when the window manager asks the window to rotate
+    device_rotation_callback: Option<Box<DeviceRotationCallback>>,
+    /// The window state as of the last state change, to find what changed
+    state: Cell<WindowState>,
+    /// Called with the window states toggled by a state change
+    state_changed_callback: CallbackSlot<Box<StateChangedCallback>>,
+    /// Clipboard contents and pending clipboard requests
+    clipboard: RefCell<clipboard::ClipboardState>,
+    /// The file drop callback and its event handlers
+    dnd: dnd::DndState,
+    /// The input method context and its callback
+    ime: ime::ImeState,
+    /// The socket connection callbacks and their event handlers
+    extn: extn::ExtnState,
+    /// The mouse move waiting to be delivered, if moves are coalesced
+    coalesce: coalesce::CoalesceState,
+    /// The touch handler and the points currently touching the window
+    touch: touch::TouchState,
+    /// The keys held down, for detecting key repeats
+    key_repeat: repeat::RepeatState,
+    /// Called with the text typed into the window
+    text_input_callback: Option<Box<text::TextInputCallback>>,
+    /// The callback for the key events of the object with keyboard focus
+    focus: object::FocusState,
+    /// The frame callback and the timing of the last frame
+    frame: frame::FrameState,
+    /// The frame rate cap and the animator that enforces it
+    throttle: throttle::ThrottleState,
+    /// Whether the window is covered by other windows
+    visibility: visibility::VisibilityState,
+}
+
+/// Run the body of an extern callback, catching a failure so that it doesn't
+/// unwind into the EFL, which is undefined behaviour. The failure is reported
+/// and the callback returns `default`, so the main loop keeps running.
+fn guard_callback<T>(name: &str, default: T, body: || -> T) -> T {
+    let mut result = None;
+    let caught = unsafe { std::rt::unwind::try(|| result = Some(body())) };
+    match (caught, result) {
+        (Ok(()), Some(result)) => result,
+        (Err(cause), _) => {
+            let message = match cause.as_ref::<&'static str>() {
+                Some(message) => message.to_string(),
+                None => match cause.as_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => "Box<Any>".to_string(),
+                },
+            };
+            let _ = writeln!(&mut std::io::stderr(), "efl: {} failed: {}", name, message);
+            default
+        },
+        (Ok(()), None) => default,
+    }
+}
+
+/// Borrow the window bound to an ecore_evas (see `Window::bind_data_ptr`),
+/// if it has not been dropped.
+unsafe fn bound_window(ee: *const ffi::Ecore_Evas) -> Option<Window<'static>> {
+    let data = Window::data_ptr_key().with_c_str(|key| ffi::ecore_evas_data_get(ee, key));
+    if data.is_null() { None } else { Some(Window::from_data_ptr(data)) }
 }
 
 impl<'a> std::fmt::Show for Window<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Window({}, {}, {}, {})", self.ee, self.canvas, self.object, self.event_callbacks.resize.is_some())
+        write!(f, "Window({}, {}, {}, {})", self.ee, self.canvas, self.object, self.has_resize_callback())
     }
 }
 
 impl<'a> Window<'a> {
     fn data_ptr_key() -> &'static str { "WINDOW_PTR" }
 
+    /// Store a pointer to the window's state on the ecore_evas, so that the
+    /// `extern "C"` event callbacks can access their corresponding Rust
+    /// callbacks in the `EventCallbacks` vtable. The state never moves, so
+    /// this is only done once, when the window is created.
+    fn bind_data_ptr(&self) {
+        unsafe {
+            Window::data_ptr_key().with_c_str(|key| {
+                ffi::ecore_evas_data_set(self.ee, key, self.data_ptr())
+            });
+        }
+    }
+
+    fn unbind_data_ptr(&self) {
+        unsafe {
+            Window::data_ptr_key().with_c_str(|key| {
+                ffi::ecore_evas_data_set(self.ee, key, ptr::null())
+            });
+        }
+    }
+
+    /// The pointer to the window's state that callbacks are registered with
+    fn data_ptr(&self) -> *const libc::c_void {
+        self.data as *const libc::c_void
+    }
+
+    /// Borrow the window whose `data_ptr` a callback was registered with.
+    /// Dropping the result leaves the window alone.
+    unsafe fn from_data_ptr(data: *const libc::c_void) -> Window<'static> {
+        Window { data: data as *mut WindowData<'static>, owned: false }
+    }
+
     pub fn get_context<'a>(&'a self) -> &'a Context { self.context }
 
+    /// Attach a value to the window, replacing any previously attached
+    /// value. Callbacks can retrieve it with `user_data`.
+    pub fn set_user_data<T: 'static>(&mut self, data: T) {
+        self.user_data = Some(box data as Box<Any>);
+    }
+
+    /// The value attached with `set_user_data`, or `None` if nothing has been
+    /// attached or the attached value is not a `T`.
+    pub fn user_data<'a, T: 'static>(&'a self) -> Option<&'a T> {
+        match self.user_data {
+            Some(ref data) => data.downcast_ref::<T>(),
+            None => None,
+        }
+    }
+
     pub fn set_iconified(&self, on: bool) {
         unsafe { ffi::ecore_evas_iconified_set(self.ee, ffi::to_eina_bool(on)) };
     }
@@ -367,6 +1341,21 @@ impl<'a> Window<'a> {
         unsafe { ffi::ecore_evas_fullscreen_set(self.ee, ffi::to_eina_bool(on)) };
     }
 
+    /// Make the window fullscreen on a particular screen, as indexed in
+    /// `Context::screens`. The window is moved onto the screen first, since
+    /// window managers fullscreen windows on the screen they are on. Fails if
+    /// there is no screen with that index.
+    pub fn set_fullscreen_on(&self, screen_index: uint) -> Result<(), ()> {
+        let screens = self.context.screens();
+        if screen_index >= screens.len() { return Err(()) }
+        let geometry = screens.get(screen_index).geometry;
+        unsafe {
+            ffi::ecore_evas_move_resize(self.ee, geometry.x, geometry.y, geometry.w, geometry.h);
+            ffi::ecore_evas_fullscreen_set(self.ee, ffi::EINA_TRUE);
+        }
+        Ok(())
+    }
+
     pub fn is_fullscreen(&self) -> bool {
         unsafe { ffi::from_eina_bool(ffi::ecore_evas_fullscreen_get(self.ee as *const _)) }
     }
@@ -407,47 +1396,322 @@ impl<'a> Window<'a> {
         unsafe { title.with_c_str(|title| ffi::ecore_evas_title_set(self.ee, title)) };
     }
 
+    /// The current title of the window, which is empty if no title has been
+    /// set. Unlike the engine name this can change at any time, so it is read
+    /// from ecore_evas into a new string on every call.
     pub fn get_title(&self) -> String {
-        unsafe { str::raw::from_c_str(ffi::ecore_evas_title_get(self.ee as *const _)) }
+        unsafe { ffi::string_from_c_ptr(ffi::ecore_evas_title_get(self.ee as *const _)).unwrap_or(String::new()) }
     }
 
-    pub fn get_engine_name(&self) -> String {
-        unsafe { str::raw::from_c_str(ffi::ecore_evas_engine_name_get(self.ee as *const _)) }
+    /// Run `f` with the current title, borrowed from ecore_evas instead of
+    /// being copied into a new string. The title is empty if none has been
+    /// set, or if it is not valid UTF-8.
+    pub fn borrow_title<T>(&self, f: |&str| -> T) -> T {
+        unsafe {
+            let title = ffi::ecore_evas_title_get(self.ee as *const _);
+            if title.is_null() { return f("") }
+            let len = libc::strlen(title) as uint;
+            std::slice::raw::buf_as_slice(title as *const u8, len, |bytes| {
+                f(std::str::from_utf8(bytes).unwrap_or(""))
+            })
+        }
     }
 
-    pub fn show(&self) {
-        unsafe { ffi::ecore_evas_show(self.ee) };
+    /// The name of the engine the window is using. This never changes, so
+    /// it is read once when the window is created.
+    pub fn get_engine_name<'b>(&'b self) -> &'b str {
+        self.engine_name.as_slice()
     }
 
-    pub fn hide(&self) {
-        unsafe { ffi::ecore_evas_hide(self.ee) };
+    pub fn get_engine(&self) -> Engine {
+        Engine::parse(self.engine_name.clone())
     }
 
-    pub fn activate(&self) {
-        unsafe { ffi::ecore_evas_activate(self.ee) };
+    /// The X11 window id, or `None` if the window is not using an X11
+    /// engine.
+    pub fn x11_window(&self) -> Option<u32> {
+        match self.get_engine() {
+            OpenGlX11 | SoftwareX11 => Some(unsafe { ffi::ecore_evas_window_get(self.ee as *const _) as u32 }),
+            _ => None,
+        }
     }
 
-    pub fn get_position(&self) -> (i32, i32) {
-        let (mut x, mut y) = (0, 0);
-        unsafe { ffi::ecore_evas_geometry_get(self.ee as *const _, &mut x, &mut y, ptr::mut_null(), ptr::mut_null()) }
-        (x as i32, y as i32)
+    /// The engine specific native window handle, or `None` if the engine has
+    /// no native window.
+    fn native_window_ptr(&self) -> Option<*mut libc::c_void> {
+        let window = unsafe { ffi::ecore_evas_window_get(self.ee as *const _) };
+        if window == 0 { None } else { Some(window as *mut libc::c_void) }
     }
 
-    pub fn get_size(&self) -> (i32, i32) {
-        let (mut w, mut h) = (0, 0);
-        unsafe { ffi::ecore_evas_geometry_get(self.ee as *const _, ptr::mut_null(), ptr::mut_null(), &mut w, &mut h) }
-        (w as i32, h as i32)
+    /// The `Ecore_Cocoa_Window` holding the window's `NSWindow`, or `None` if
+    /// the window is not using the Cocoa engine. The handle is owned by
+    /// ecore_evas and is only valid until the window is dropped. It is not
+    /// retained on behalf of the caller, so it must not be released, and
+    /// must be retained explicitly if it is kept beyond that.
+    pub fn cocoa_window(&self) -> Option<*mut libc::c_void> {
+        match self.get_engine() {
+            OpenglCocoa => self.native_window_ptr(),
+            _ => None,
+        }
     }
 
-    pub fn get_size_min(&self) -> (i32, i32) {
-        let (mut w, mut h) = (0, 0);
-        unsafe { ffi::ecore_evas_size_min_get(self.ee as *const _, &mut w, &mut h) }
-        (w as i32, h as i32)
+    /// The native window handle ecore_evas holds for the SDL engines, or
+    /// `None` for any other engine. SDL is initialised by ecore_evas when
+    /// an SDL window is created, so it is always initialised when this
+    /// returns a handle. The handle is only valid until the window is
+    /// dropped.
+    pub fn sdl_window(&self) -> Option<*mut libc::c_void> {
+        match self.get_engine() {
+            Sdl | OpenglSdl => self.native_window_ptr(),
+            _ => None,
+        }
     }
 
-    pub fn get_size_max(&self) -> (i32, i32) {
-        let (mut w, mut h) = (0, 0);
-        unsafe { ffi::ecore_evas_size_max_get(self.ee as *const _, &mut w, &mut h) }
+    /// The `HWND` of the window, or `None` if the window is not using one of
+    /// the Win32 engines. The handle is only valid until the window is
+    /// dropped.
+    #[cfg(windows)]
+    pub fn win32_hwnd(&self) -> Option<*mut libc::c_void> {
+        match self.get_engine() {
+            SoftwareGdi | SoftwareDdraw | Direct3d => unsafe {
+                let window = ffi::ecore_evas_win32_window_get(self.ee as *const _);
+                if window.is_null() { return None }
+                let hwnd = ffi::win32::ecore_win32_window_hwnd_get(window as *mut _);
+                if hwnd.is_null() { None } else { Some(hwnd) }
+            },
+            _ => None,
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn win32_hwnd(&self) -> Option<*mut libc::c_void> {
+        None
+    }
+
+    /// Set the region of the window that receives input, as rectangles in
+    /// window coordinates. Input outside of the region passes through to
+    /// whatever is below the window, so an empty slice makes the whole
+    /// window click-through. Fails if the engine has no way of setting it,
+    /// which is currently the case for anything other than X11.
+    pub fn set_shape_input(&self, rects: &[Rect]) -> Result<(), ()> {
+        self.set_x11_shape(rects, true)
+    }
+
+    /// Set the visible region of the window, as rectangles in window
+    /// coordinates. Fails under the same conditions as `set_shape_input`.
+    /// This is overridden by ecore_evas if the window is shaped from its
+    /// alpha channel instead.
+    pub fn set_shape(&self, rects: &[Rect]) -> Result<(), ()> {
+        self.set_x11_shape(rects, false)
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn set_x11_shape(&self, rects: &[Rect], input: bool) -> Result<(), ()> {
+        let win = match self.x11_window() {
+            Some(win) => win as ffi::x11::Ecore_X_Window,
+            None => return Err(()),
+        };
+        let mut rects: Vec<ffi::x11::Ecore_X_Rectangle> = rects.iter().map(|rect| {
+            ffi::x11::Ecore_X_Rectangle {
+                x: rect.x as libc::c_int,
+                y: rect.y as libc::c_int,
+                width: cmp::max(rect.w, 0) as libc::c_uint,
+                height: cmp::max(rect.h, 0) as libc::c_uint,
+            }
+        }).collect();
+        let num = rects.len() as libc::c_int;
+        unsafe {
+            if input {
+                ffi::x11::ecore_x_window_shape_input_rectangles_set(win, rects.as_mut_ptr(), num);
+            } else {
+                ffi::x11::ecore_x_window_shape_rectangles_set(win, rects.as_mut_ptr(), num);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn set_x11_shape(&self, _rects: &[Rect], _input: bool) -> Result<(), ()> {
+        Err(())
+    }
+
+    /// Set the opacity of the whole window as applied by the compositor,
+    /// from 0.0 (transparent) to 1.0 (opaque). This is independent of the
+    /// window's alpha channel. Fails if the engine has no way of setting it,
+    /// which is currently the case for anything other than X11.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn set_opacity(&self, opacity: f64) -> Result<(), ()> {
+        let opacity = opacity.max(0.0).min(1.0);
+        match self.x11_window() {
+            Some(win) => {
+                let opacity = (opacity * std::u32::MAX as f64) as libc::c_uint;
+                unsafe { ffi::x11::ecore_x_netwm_opacity_set(win as ffi::x11::Ecore_X_Window, opacity) };
+                Ok(())
+            },
+            None => Err(()),
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    pub fn set_opacity(&self, _opacity: f64) -> Result<(), ()> {
+        Err(())
+    }
+
+    /// The opacity of the window as applied by the compositor. Fails under
+    /// the same conditions as `set_opacity`.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn get_opacity(&self) -> Result<f64, ()> {
+        match self.x11_window() {
+            Some(win) => {
+                let mut opacity = 0;
+                let is_set = unsafe {
+                    ffi::x11::ecore_x_netwm_opacity_get(win as ffi::x11::Ecore_X_Window, &mut opacity)
+                };
+                if ffi::from_eina_bool(is_set) {
+                    Ok(opacity as f64 / std::u32::MAX as f64)
+                } else {
+                    // Windows without the hint are fully opaque
+                    Ok(1.0)
+                }
+            },
+            None => Err(()),
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    pub fn get_opacity(&self) -> Result<f64, ()> {
+        Err(())
+    }
+
+    /// The refresh rate of the screen the window is on, in Hz, or `None` if
+    /// it is unknown. This is currently only known for the X11 engines,
+    /// using RandR: the rate is that of the CRTC containing the center of
+    /// the window, or of the primary output if no CRTC contains it.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn refresh_rate(&self) -> Option<f64> {
+        let win = match self.x11_window() {
+            Some(win) => win as ffi::x11::Ecore_X_Window,
+            None => return None,
+        };
+        let (x, y) = self.get_position();
+        let (w, h) = self.get_size();
+        let (center_x, center_y) = (x + w / 2, y + h / 2);
+        unsafe {
+            let root = ffi::x11::ecore_x_window_root_get(win);
+            let mut rate = None;
+            let mut num = 0;
+            let crtcs = ffi::x11::ecore_x_randr_crtcs_get(root, &mut num);
+            if !crtcs.is_null() {
+                std::slice::raw::buf_as_slice(crtcs as *const _, num as uint, |crtcs| {
+                    for &crtc in crtcs.iter() {
+                        let (mut cx, mut cy, mut cw, mut ch) = (0, 0, 0, 0);
+                        ffi::x11::ecore_x_randr_crtc_geometry_get(root, crtc, &mut cx, &mut cy, &mut cw, &mut ch);
+                        if center_x < cx || center_x >= cx + cw || center_y < cy || center_y >= cy + ch {
+                            continue;
+                        }
+                        let mode = ffi::x11::ecore_x_randr_crtc_mode_get(root, crtc);
+                        if mode != 0 {
+                            let crtc_rate = ffi::x11::ecore_x_randr_crtc_refresh_rate_get(root, crtc, mode);
+                            if crtc_rate > 0.0 { rate = Some(crtc_rate as f64) }
+                        }
+                        break;
+                    }
+                });
+                libc::free(crtcs as *mut libc::c_void);
+            }
+            rate.or_else(|| {
+                let primary = ffi::x11::ecore_x_randr_screen_primary_output_current_refresh_rate_get(root);
+                if primary > 0 { Some(primary as f64) } else { None }
+            })
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    pub fn refresh_rate(&self) -> Option<f64> {
+        None
+    }
+
+    /// The time between frames to aim for, in seconds. This is the period
+    /// of the screen's refresh rate if it is known, and ecore's animator
+    /// frametime otherwise.
+    pub fn frame_time(&self) -> f64 {
+        match self.refresh_rate() {
+            Some(rate) => 1.0 / rate,
+            None => ecore::animator_frametime(),
+        }
+    }
+
+    pub fn show(&self) {
+        unsafe {
+            // The window and its image are left hidden by `with_visible(false)`
+            ffi::evas_object_show(self.object);
+            ffi::ecore_evas_show(self.ee);
+        }
+    }
+
+    pub fn hide(&self) {
+        unsafe { ffi::ecore_evas_hide(self.ee) };
+    }
+
+    pub fn activate(&self) {
+        unsafe { ffi::ecore_evas_activate(self.ee) };
+    }
+
+    pub fn get_position(&self) -> (i32, i32) {
+        let (mut x, mut y) = (0, 0);
+        unsafe { ffi::ecore_evas_geometry_get(self.ee as *const _, &mut x, &mut y, ptr::mut_null(), ptr::mut_null()) }
+        (x as i32, y as i32)
+    }
+
+    pub fn get_size(&self) -> (i32, i32) {
+        let (mut w, mut h) = (0, 0);
+        unsafe { ffi::ecore_evas_geometry_get(self.ee as *const _, ptr::mut_null(), ptr::mut_null(), &mut w, &mut h) }
+        (w as i32, h as i32)
+    }
+
+    /// Set the size of the canvas output, in pixels. This is the resolution
+    /// the canvas is rendered at, while the viewport (see `set_viewport`) is
+    /// the area of the canvas, in canvas coordinates, that is stretched over
+    /// it. Rendering at a larger output size than the viewport supersamples
+    /// the canvas, and at a smaller one downscales it.
+    ///
+    /// Ecore_Evas sets both the output size and the viewport to the size of
+    /// the window whenever the window is resized, so a different size has to
+    /// be set again from a resize callback.
+    pub fn set_output_size(&self, w: i32, h: i32) {
+        unsafe { ffi::evas_output_size_set(self.canvas, w as libc::c_int, h as libc::c_int) };
+    }
+
+    pub fn get_output_size(&self) -> (i32, i32) {
+        let (mut w, mut h) = (0, 0);
+        unsafe { ffi::evas_output_size_get(self.canvas as *const _, &mut w, &mut h) }
+        (w as i32, h as i32)
+    }
+
+    /// Set the area of the canvas that is rendered to the output, in canvas
+    /// coordinates. See `set_output_size`.
+    pub fn set_viewport(&self, x: i32, y: i32, w: i32, h: i32) {
+        unsafe {
+            ffi::evas_output_viewport_set(self.canvas, x as ffi::Evas_Coord, y as ffi::Evas_Coord,
+                                          w as ffi::Evas_Coord, h as ffi::Evas_Coord)
+        };
+    }
+
+    pub fn get_viewport(&self) -> Rect {
+        let (mut x, mut y, mut w, mut h) = (0, 0, 0, 0);
+        unsafe { ffi::evas_output_viewport_get(self.canvas as *const _, &mut x, &mut y, &mut w, &mut h) }
+        Rect::new(x as i32, y as i32, w as i32, h as i32)
+    }
+
+    pub fn get_size_min(&self) -> (i32, i32) {
+        let (mut w, mut h) = (0, 0);
+        unsafe { ffi::ecore_evas_size_min_get(self.ee as *const _, &mut w, &mut h) }
+        (w as i32, h as i32)
+    }
+
+    pub fn get_size_max(&self) -> (i32, i32) {
+        let (mut w, mut h) = (0, 0);
+        unsafe { ffi::ecore_evas_size_max_get(self.ee as *const _, &mut w, &mut h) }
         (w as i32, h as i32)
     }
 
@@ -479,6 +1743,153 @@ impl<'a> Window<'a> {
         unsafe { ffi::ecore_evas_size_step_set(self.ee, w as libc::c_int, h as libc::c_int) };
     }
 
+    /// Apply a set of size hints in one go. Unset hints are left unchanged.
+    /// The minimum and maximum sizes are applied in whichever order keeps
+    /// the maximum at least as large as the minimum at all times, as some
+    /// window managers reject inconsistent hints.
+    pub fn set_size_hints(&self, hints: SizeHints) {
+        hints.base.map(|(w, h)| self.set_size_base(w, h));
+        hints.step.map(|(w, h)| self.set_size_step(w, h));
+        match (hints.min, hints.max) {
+            (Some((min_w, min_h)), Some((max_w, max_h))) => {
+                let (cur_max_w, cur_max_h) = self.get_size_max();
+                if min_w > cur_max_w || min_h > cur_max_h {
+                    self.set_size_max(max_w, max_h);
+                    self.set_size_min(min_w, min_h);
+                } else {
+                    self.set_size_min(min_w, min_h);
+                    self.set_size_max(max_w, max_h);
+                }
+            },
+            (Some((min_w, min_h)), None) => self.set_size_min(min_w, min_h),
+            (None, Some((max_w, max_h))) => self.set_size_max(max_w, max_h),
+            (None, None) => {},
+        }
+        hints.aspect.map(|aspect| self.set_aspect_ratio(aspect));
+    }
+
+    pub fn size_hints(&self) -> SizeHints {
+        SizeHints {
+            min: Some(self.get_size_min()),
+            max: Some(self.get_size_max()),
+            base: Some(self.get_size_base()),
+            step: Some(self.get_size_step()),
+            aspect: Some(self.get_aspect_ratio()),
+        }
+    }
+
+    /// Resize the window, moving it so that the point given by `anchor`
+    /// stays fixed on screen.
+    pub fn resize_anchored(&self, w: i32, h: i32, anchor: Anchor) {
+        let (x, y) = self.get_position();
+        let (old_w, old_h) = self.get_size();
+        let (dx, dy) = (old_w - w, old_h - h);
+        let x = match anchor {
+            AnchorTopLeft | AnchorLeft | AnchorBottomLeft => x,
+            AnchorTop | AnchorCenter | AnchorBottom => x + dx / 2,
+            AnchorTopRight | AnchorRight | AnchorBottomRight => x + dx,
+        };
+        let y = match anchor {
+            AnchorTopLeft | AnchorTop | AnchorTopRight => y,
+            AnchorLeft | AnchorCenter | AnchorRight => y + dy / 2,
+            AnchorBottomLeft | AnchorBottom | AnchorBottomRight => y + dy,
+        };
+        unsafe {
+            ffi::ecore_evas_move_resize(self.ee, x as libc::c_int, y as libc::c_int,
+                                        w as libc::c_int, h as libc::c_int)
+        };
+    }
+
+    /// Letterbox the window's image: instead of stretching it over the whole
+    /// window, scale it to the largest size with the aspect ratio of
+    /// `content_w` by `content_h` that fits, centered, with black bars
+    /// filling the rest of the window. This is kept up to date as the window
+    /// is resized.
+    ///
+    /// While letterboxed, the pointer coordinates delivered to input
+    /// callbacks are in content space, from (0, 0) at the top left corner of
+    /// the image to (`content_w`, `content_h`) at the bottom right, before
+    /// any content scale or coordinate transform is applied. Pointer events
+    /// over the bars hit the background rather than the image, so they are
+    /// not delivered.
+    pub fn set_letterbox(&self, enabled: bool, content_w: i32, content_h: i32) {
+        if enabled && content_w > 0 && content_h > 0 {
+            self.letterbox.set(Some((content_w, content_h)));
+        } else {
+            self.letterbox.set(None);
+        }
+        self.update_background();
+    }
+
+    /// Fill the canvas with a color behind every object, including the
+    /// window's image, so that nothing undefined is shown before the first
+    /// frame is drawn. The color is premultiplied by alpha (see
+    /// `Color::premultiply`), and also fills the bars of a letterboxed
+    /// image. This is kept up to date as the window is resized.
+    pub fn set_background_color(&self, color: Color) {
+        self.background_color.set(Some(color));
+        self.update_background();
+    }
+
+    /// Stop filling the canvas behind its objects. Letterboxing bars are
+    /// drawn in black again.
+    pub fn unset_background_color(&self) {
+        self.background_color.set(None);
+        self.update_background();
+    }
+
+    /// Create, recolor or delete the background rectangle to match the
+    /// background color and letterboxing.
+    fn update_background(&self) {
+        let background = self.background.get();
+        if self.background_color.get().is_none() && self.letterbox.get().is_none() {
+            if !background.is_null() {
+                unsafe { ffi::evas_object_del(background) };
+                self.background.set(ptr::mut_null());
+            }
+        } else {
+            let color = self.background_color.get().unwrap_or(Color::new(0, 0, 0, 255));
+            unsafe {
+                if background.is_null() {
+                    let background = ffi::evas_object_rectangle_add(self.canvas);
+                    // The lowest layer Evas allows, `EVAS_LAYER_MIN`
+                    ffi::evas_object_layer_set(background, std::i16::MIN as libc::c_short);
+                    ffi::evas_object_show(background);
+                    self.background.set(background);
+                }
+                ffi::evas_object_color_set(self.background.get(), color.r as libc::c_int, color.g as libc::c_int,
+                                           color.b as libc::c_int, color.a as libc::c_int);
+            }
+        }
+        layout_image(self);
+    }
+
+    /// Whether the window's engine can create an OpenGL surface with the
+    /// given parameters. Evas_GL has no way of listing what it supports, so
+    /// this creates and destroys a small surface to find out. Always false
+    /// for engines without OpenGL support.
+    pub fn gl_config_supported(&self, query: &GlConfigQuery) -> bool {
+        if !self.get_engine().is_gl() { return false }
+        let config = GlConfig::from_settings(&GlSettings {
+            color_format: query.color_format.clone(),
+            depth: query.depth.clone(),
+            stencil: query.stencil.clone(),
+            options: GlOptionsNone,
+            multisample: query.multisample.clone(),
+        });
+        unsafe {
+            let gl = ffi::evas_gl_new(self.canvas);
+            if gl.is_null() { return false }
+            let surface = ffi::evas_gl_surface_create(gl, config.ptr, 1, 1);
+            let supported = !surface.is_null();
+            if supported {
+                ffi::evas_gl_surface_destroy(gl, surface);
+            }
+            ffi::evas_gl_free(gl);
+            supported
+        }
+    }
+
     pub fn set_manual_render(&self, on: bool) {
         unsafe { ffi::ecore_evas_manual_render_set(self.ee, ffi::to_eina_bool(on)) };
     }
@@ -489,6 +1900,307 @@ impl<'a> Window<'a> {
 
     pub fn manual_render(&self) {
         unsafe { ffi::ecore_evas_manual_render(self.ee) };
+        self.damage.borrow_mut().clear();
+    }
+
+    /// Present the frame drawn by the last `manual_render`, so that callers
+    /// can present frames the same way regardless of engine. Currently every
+    /// engine presents as part of rendering, so this does nothing:
+    ///
+    /// - The software engines (`SoftwareX11`, `Fb`, `Sdl`, `WaylandShm`,
+    ///   `SoftwareGdi`, `SoftwareDdraw`, `Ews`) copy the rendered regions to
+    ///   the window at the end of each render. `Buffer` renders into memory
+    ///   that is read back with `buffer_pixels`.
+    /// - The GL engines (`OpenGlX11`, `OpenglCocoa`, `OpenglSdl`,
+    ///   `WaylandEgl`, `OpenGlGlew`) and `Direct3d` swap their back buffer at
+    ///   the end of each render. Evas_GL surfaces are drawn into the canvas
+    ///   by that render, and Evas_GL has no separate swap call.
+    pub fn swap_buffers(&self) {}
+
+    /// Copy out the last rendered frame of a window using the `Buffer`
+    /// engine, as premultiplied ARGB32 pixels in row-major order. Returns
+    /// `None` for any other engine.
+    pub fn buffer_pixels(&self) -> Option<Vec<u32>> {
+        if self.get_engine() != Buffer { return None }
+        let (w, h) = self.get_size();
+        unsafe {
+            let pixels = ffi::ecore_evas_buffer_pixels_get(self.ee) as *const u32;
+            if pixels.is_null() { return None }
+            Some(std::slice::raw::buf_as_slice(pixels, (w * h) as uint, |pixels| {
+                Vec::from_slice(pixels)
+            }))
+        }
+    }
+
+    /// Read back the pixels of an area of the canvas, as premultiplied
+    /// ARGB32 pixels in row-major order, each a native-endian `u32` of four
+    /// bytes. For the `Buffer` engine this is a copy of the last rendered
+    /// frame. Other engines render the canvas into a snapshot image, which
+    /// Evas copies back to the CPU with `evas_object_image_data_get`; for the
+    /// GL engines that means reading back a texture. Returns `None` if the
+    /// area is empty, or the engine can't read back pixels.
+    pub fn read_pixels(&self, rect: Rect) -> Option<Vec<u8>> {
+        if rect.w <= 0 || rect.h <= 0 { return None }
+        if self.get_engine() == Buffer {
+            let (w, h) = self.get_size();
+            if rect.x < 0 || rect.y < 0 || rect.x + rect.w > w || rect.y + rect.h > h { return None }
+            let pixels = match self.buffer_pixels() {
+                Some(pixels) => pixels,
+                None => return None,
+            };
+            let mut out = Vec::with_capacity((rect.w * rect.h * 4) as uint);
+            for y in range(rect.y, rect.y + rect.h) {
+                let start = (y * w + rect.x) as uint;
+                for &pixel in pixels.slice(start, start + rect.w as uint).iter() {
+                    out.push_all(unsafe { mem::transmute::<u32, [u8, ..4]>(pixel) });
+                }
+            }
+            return Some(out);
+        }
+        unsafe {
+            // A snapshot image captures everything stacked below it
+            let snapshot = ffi::evas_object_image_filled_add(self.canvas);
+            if snapshot.is_null() { return None }
+            ffi::evas_object_image_snapshot_set(snapshot, ffi::EINA_TRUE);
+            ffi::evas_object_layer_set(snapshot, std::i16::MAX);
+            ffi::evas_object_move(snapshot, rect.x, rect.y);
+            ffi::evas_object_resize(snapshot, rect.w, rect.h);
+            ffi::evas_object_show(snapshot);
+            self.manual_render();
+            let (mut w, mut h) = (0, 0);
+            ffi::evas_object_image_size_get(snapshot as *const _, &mut w, &mut h);
+            let data = ffi::evas_object_image_data_get(snapshot as *const _, ffi::EINA_FALSE) as *const u8;
+            let pixels = if data.is_null() || w != rect.w || h != rect.h {
+                None
+            } else {
+                let stride = ffi::evas_object_image_stride_get(snapshot as *const _) as uint;
+                let mut out = Vec::with_capacity((w * h * 4) as uint);
+                for y in range(0, h as uint) {
+                    std::slice::raw::buf_as_slice(data.offset((y * stride) as int), (w * 4) as uint, |row| {
+                        out.push_all(row);
+                    });
+                }
+                Some(out)
+            };
+            if !data.is_null() {
+                // Hand the data back, so Evas can release its copy
+                ffi::evas_object_image_data_set(snapshot, data as *mut libc::c_void);
+            }
+            ffi::evas_object_del(snapshot);
+            pixels
+        }
+    }
+
+    /// The X pixmap that a window built with `Context::build_pixmap_window`
+    /// renders into. Returns `None` for any other window.
+    pub fn get_x11_pixmap(&self) -> Option<u64> {
+        if self.get_engine() != SoftwareX11 { return None }
+        match unsafe { ffi::ecore_evas_software_x11_pixmap_get(self.ee as *const _) } {
+            0 => None,
+            pixmap => Some(pixmap as u64),
+        }
+    }
+
+    /// Set the size of the window's image and make it fill the window.
+    fn prepare_image(&self, w: i32, h: i32) {
+        unsafe {
+            ffi::evas_object_image_filled_set(self.object, ffi::EINA_TRUE);
+            ffi::evas_object_image_size_set(self.object, w as libc::c_int, h as libc::c_int);
+        }
+    }
+
+    /// Set the pixel layout of the data passed to `set_image_data`. This
+    /// defaults to `Argb8888`.
+    pub fn set_image_colorspace(&mut self, colorspace: Colorspace) {
+        unsafe { ffi::evas_object_image_colorspace_set(self.object, colorspace as ffi::Evas_Colorspace) };
+        self.image_colorspace = colorspace;
+    }
+
+    pub fn get_image_colorspace(&self) -> Colorspace {
+        self.image_colorspace.clone()
+    }
+
+    /// Copy pixels into the image that covers the window, which is scaled to
+    /// fit the window. The data is in row-major order, laid out as described
+    /// by the image colorspace. Fails if the length of the data does not
+    /// match the size given in that colorspace.
+    pub fn set_image_data(&mut self, w: i32, h: i32, data: &[u8]) -> Result<(), ()> {
+        if data.len() != self.image_colorspace.data_size(w, h) { return Err(()) }
+        if self.image_colorspace.is_ycbcr() {
+            // Evas only copies the row table of YCbCr data, so the pixels
+            // have to be kept alive on this side
+            self.attach_image_data(w, h, Vec::from_slice(data));
+            return Ok(());
+        }
+        self.prepare_image(w, h);
+        unsafe {
+            ffi::evas_object_image_data_copy_set(self.object, data.as_ptr() as *mut libc::c_void);
+            ffi::evas_object_image_data_update_add(self.object, 0, 0, w as libc::c_int, h as libc::c_int);
+        }
+        // Evas has its own copy now, so any buffer it was using can go
+        self.image_data = None;
+        Ok(())
+    }
+
+    /// Like `set_image_data`, but hands the buffer to Evas to draw from
+    /// directly instead of copying it. The window keeps the buffer alive
+    /// until the next call to `set_image_data` or
+    /// `set_image_data_zero_copy`, or until the window is destroyed, so it
+    /// must not be modified until then.
+    ///
+    /// The GL engines upload the image to a texture regardless, so for them
+    /// this falls back to copying the data and the buffer is freed
+    /// immediately.
+    pub fn set_image_data_zero_copy(&mut self, w: i32, h: i32, data: Vec<u8>) -> Result<(), ()> {
+        if data.len() != self.image_colorspace.data_size(w, h) { return Err(()) }
+        match self.get_engine() {
+            SoftwareX11 | Buffer | Fb | Sdl | WaylandShm | SoftwareGdi | SoftwareDdraw | Ews => {},
+            _ => return self.set_image_data(w, h, data.as_slice()),
+        }
+        self.attach_image_data(w, h, data);
+        Ok(())
+    }
+
+    /// Make the window's image draw from `data` directly, which must already
+    /// be the right size.
+    fn attach_image_data(&mut self, w: i32, h: i32, data: Vec<u8>) {
+        let rows: Vec<*const u8> = self.image_colorspace.row_offsets(w, h).iter().map(|&offset| {
+            unsafe { data.as_ptr().offset(offset as int) }
+        }).collect();
+        self.prepare_image(w, h);
+        unsafe {
+            let pixels = if self.image_colorspace.is_ycbcr() { rows.as_ptr() as *mut libc::c_void }
+                         else { data.as_ptr() as *mut libc::c_void };
+            ffi::evas_object_image_data_set(self.object, pixels);
+            ffi::evas_object_image_data_update_add(self.object, 0, 0, w as libc::c_int, h as libc::c_int);
+        }
+        // Only release the previous buffer once Evas has stopped using it
+        self.image_data = Some(ImageData { data: data, rows: rows });
+    }
+
+    /// Display a single plane Linux dmabuf in the window's image, without
+    /// copying it. `format` is a DRM fourcc code, and `modifier` the DRM
+    /// format modifier describing the buffer's tiling. The buffer must stay
+    /// unchanged until it is replaced, as the GPU reads from it directly.
+    ///
+    /// This is only supported by the `WaylandEgl` engine, with
+    /// `DRM_FORMAT_ARGB8888` and `DRM_FORMAT_XRGB8888` buffers, and fails
+    /// otherwise.
+    pub fn import_dmabuf(&self, fd: libc::c_int, w: i32, h: i32, stride: u32,
+                         format: u32, modifier: u64) -> Result<(), ()> {
+        use ffi::dmabuf;
+
+        if self.get_engine() != WaylandEgl { return Err(()) }
+        if format != dmabuf::DRM_FORMAT_ARGB8888 && format != dmabuf::DRM_FORMAT_XRGB8888 {
+            return Err(())
+        }
+        // Evas copies the attributes when the surface is set
+        let mut attr = dmabuf::Dmabuf_Attributes {
+            width: w, height: h,
+            format: format,
+            flags: 0,
+            n_planes: 1,
+            fd: [fd, -1, -1, -1],
+            offset: [0, ..dmabuf::MAX_PLANES],
+            stride: [stride, 0, 0, 0],
+            modifier: [modifier, 0, 0, 0],
+        };
+        // Evas copies the whole surface, so the dmabuf layout, which is
+        // smaller, is written into the start of a zeroed one
+        let mut surface: ffi::Evas_Native_Surface = unsafe { mem::zeroed() };
+        unsafe {
+            let dmabuf_surface = &mut surface as *mut _ as *mut dmabuf::Evas_Native_Surface_Wl_Dmabuf;
+            *dmabuf_surface = dmabuf::Evas_Native_Surface_Wl_Dmabuf {
+                version: dmabuf::EVAS_NATIVE_SURFACE_VERSION,
+                type_: dmabuf::EVAS_NATIVE_SURFACE_WL_DMABUF,
+                attr: &mut attr as *mut _ as *mut libc::c_void,
+                resource: ptr::mut_null(),
+                scanout_handler: ptr::mut_null(),
+                scanout_data: ptr::mut_null(),
+            };
+        }
+        self.prepare_image(w, h);
+        unsafe {
+            ffi::evas_object_image_alpha_set(self.object, ffi::to_eina_bool(format == dmabuf::DRM_FORMAT_ARGB8888));
+            ffi::evas_object_image_native_surface_set(self.object, &mut surface);
+            // Evas drops a surface it cannot import
+            if ffi::evas_object_image_native_surface_get(self.object as *const _).is_null() {
+                return Err(())
+            }
+            ffi::evas_object_image_data_update_add(self.object, 0, 0, w as libc::c_int, h as libc::c_int);
+        }
+        Ok(())
+    }
+
+    /// Mark a region of the canvas as needing to be repainted on the next
+    /// render. The region is also accumulated so that it can be inspected
+    /// with `damage_region` until the next call to `manual_render`.
+    pub fn add_damage(&self, rect: Rect) {
+        unsafe { ffi::evas_damage_rectangle_add(self.canvas, rect.x, rect.y, rect.w, rect.h) };
+        self.damage.borrow_mut().add(rect);
+    }
+
+    /// The regions damaged since the last call to `manual_render`.
+    pub fn damage_region(&self) -> DamageRegion {
+        self.damage.borrow().clone()
+    }
+
+    /// Mark a region of the canvas as covered by something else, so that
+    /// Evas can skip rendering it. The region stays obscured across renders
+    /// until it is removed with `clear_obscured`.
+    pub fn add_obscured(&self, rect: Rect) {
+        unsafe { ffi::evas_obscured_rectangle_add(self.canvas, rect.x, rect.y, rect.w, rect.h) };
+    }
+
+    pub fn clear_obscured(&self) {
+        unsafe { ffi::evas_obscured_clear(self.canvas) };
+    }
+
+    /// Stop the canvas from processing events, so that many objects can be
+    /// changed at once without pointer events being delivered to objects in
+    /// between. Changes are still tracked, and are all drawn by the next
+    /// render after `thaw`. Calls nest, and each must be matched by a call
+    /// to `thaw`.
+    pub fn freeze(&self) {
+        unsafe { ffi::evas_event_freeze(self.canvas) };
+    }
+
+    pub fn thaw(&self) {
+        unsafe { ffi::evas_event_thaw(self.canvas) };
+    }
+
+    /// Freeze the canvas until the returned guard is dropped.
+    pub fn freeze_guard<'b>(&'b self) -> CanvasFreeze<'b> {
+        self.freeze();
+        CanvasFreeze { canvas: self.canvas, marker: std::kinds::marker::ContravariantLifetime }
+    }
+
+    /// Set the maximum size of the canvas image cache, in bytes.
+    pub fn set_image_cache(&self, bytes: i32) {
+        unsafe { ffi::evas_image_cache_set(self.canvas, bytes as libc::c_int) };
+    }
+
+    pub fn get_image_cache(&self) -> i32 {
+        unsafe { ffi::evas_image_cache_get(self.canvas as *const _) as i32 }
+    }
+
+    /// Drop all unused images from the image cache.
+    pub fn image_cache_flush(&self) {
+        unsafe { ffi::evas_image_cache_flush(self.canvas) };
+    }
+
+    /// Set the maximum size of the canvas font cache, in bytes.
+    pub fn set_font_cache(&self, bytes: i32) {
+        unsafe { ffi::evas_font_cache_set(self.canvas, bytes as libc::c_int) };
+    }
+
+    pub fn get_font_cache(&self) -> i32 {
+        unsafe { ffi::evas_font_cache_get(self.canvas as *const _) as i32 }
+    }
+
+    /// Drop all unused fonts from the font cache.
+    pub fn font_cache_flush(&self) {
+        unsafe { ffi::evas_font_cache_flush(self.canvas) };
     }
 
     pub fn input_event_register(&self) {
@@ -523,96 +2235,686 @@ impl<'a> Window<'a> {
         (x as i32, y as i32)
     }
 
+    /// Move the pointer to a point in the window. The point is in the same
+    /// units as the pointer coordinates delivered to input callbacks, so it
+    /// is mapped back through the coordinate transform and content scale if
+    /// those are in use.
     pub fn warp_pointer(&self, x: i32, y: i32) {
+        let (x, y) = transform::unmap_coords(self, x, y);
         unsafe { ffi::ecore_evas_pointer_warp(self.ee as *const _, x as libc::c_int, y as libc::c_int) };
     }
+
+    /// Feed a synthetic mouse move event into the canvas, as if it came from
+    /// the windowing system. This is dispatched through the same input
+    /// callbacks as real hardware events.
+    pub fn feed_mouse_move(&self, x: i32, y: i32, timestamp: TimeStamp) {
+        unsafe { ffi::evas_event_feed_mouse_move(self.canvas, x as libc::c_int, y as libc::c_int, timestamp.millis() as libc::c_uint, ptr::null()) };
+    }
+
+    pub fn feed_mouse_down(&self, button: MouseButton, flags: ButtonFlags, timestamp: TimeStamp) {
+        unsafe { ffi::evas_event_feed_mouse_down(self.canvas, button, flags.bits, timestamp.millis() as libc::c_uint, ptr::null()) };
+    }
+
+    pub fn feed_mouse_up(&self, button: MouseButton, flags: ButtonFlags, timestamp: TimeStamp) {
+        unsafe { ffi::evas_event_feed_mouse_up(self.canvas, button, flags.bits, timestamp.millis() as libc::c_uint, ptr::null()) };
+    }
+
+    pub fn feed_mouse_wheel(&self, direction: i32, z: i32, timestamp: TimeStamp) {
+        unsafe {
+            ffi::evas_event_feed_mouse_wheel(self.canvas, direction as libc::c_int, z as libc::c_int,
+                                             timestamp.millis() as libc::c_uint, ptr::null())
+        };
+    }
+
+    pub fn feed_key_down(&self, keyname: &str, key: &str, string: &str, timestamp: TimeStamp) {
+        keyname.with_c_str(|keyname| key.with_c_str(|key| string.with_c_str(|string| unsafe {
+            ffi::evas_event_feed_key_down(self.canvas, keyname, key, string, ptr::null(), timestamp.millis() as libc::c_uint, ptr::null())
+        })));
+    }
+
+    pub fn feed_key_up(&self, keyname: &str, key: &str, string: &str, timestamp: TimeStamp) {
+        keyname.with_c_str(|keyname| key.with_c_str(|key| string.with_c_str(|string| unsafe {
+            ffi::evas_event_feed_key_up(self.canvas, keyname, key, string, ptr::null(), timestamp.millis() as libc::c_uint, ptr::null())
+        })));
+    }
+}
+
+/// A read-only view of an ecore_evas that is not owned by the caller. This
+/// only queries ecore_evas directly, and never touches the state of a
+/// `Window` that may own the same ecore_evas.
+pub struct WindowRef<'a> {
+    context: &'a Context,
+    ee: *mut ffi::Ecore_Evas,
 }
 
+impl<'a> WindowRef<'a> {
+    pub fn get_context<'a>(&'a self) -> &'a Context { self.context }
+
+    /// The current title of the window, which is empty if no title has been
+    /// set.
+    pub fn get_title(&self) -> String {
+        unsafe { ffi::string_from_c_ptr(ffi::ecore_evas_title_get(self.ee as *const _)).unwrap_or(String::new()) }
+    }
+
+    pub fn get_engine_name(&self) -> String {
+        unsafe { ffi::string_from_c_ptr(ffi::ecore_evas_engine_name_get(self.ee as *const _)).unwrap_or(String::new()) }
+    }
+
+    /// The X11 window id, or `None` if the window is not using an X11
+    /// engine.
+    pub fn x11_window(&self) -> Option<u32> {
+        match Engine::parse(self.get_engine_name()) {
+            OpenGlX11 | SoftwareX11 => Some(unsafe { ffi::ecore_evas_window_get(self.ee as *const _) as u32 }),
+            _ => None,
+        }
+    }
+
+    pub fn get_position(&self) -> (i32, i32) {
+        let (mut x, mut y) = (0, 0);
+        unsafe { ffi::ecore_evas_geometry_get(self.ee as *const _, &mut x, &mut y, ptr::mut_null(), ptr::mut_null()) }
+        (x as i32, y as i32)
+    }
+
+    pub fn get_size(&self) -> (i32, i32) {
+        let (mut w, mut h) = (0, 0);
+        unsafe { ffi::ecore_evas_geometry_get(self.ee as *const _, ptr::mut_null(), ptr::mut_null(), &mut w, &mut h) }
+        (w as i32, h as i32)
+    }
+}
+
+/// Keeps a window's canvas frozen while it is alive. See `Window::freeze`.
+pub struct CanvasFreeze<'a> {
+    canvas: *mut ffi::Evas,
+    marker: std::kinds::marker::ContravariantLifetime<'a>,
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for CanvasFreeze<'a> {
+    fn drop(&mut self) {
+        unsafe { ffi::evas_event_thaw(self.canvas) };
+    }
+}
+
+/// A window is torn down in this order:
+///
+/// 1. The pre-free and destroy callbacks are called, while the window is
+///    still whole. Ecore only calls pre-free from `ecore_evas_free`, once the
+///    window has been unbound, and the native window's destroy event comes
+///    after the ecore_evas is gone, so neither would be seen otherwise.
+/// 2. The input method context, which refers to the native window.
+/// 3. Everything registered with ecore or the canvas that refers to the
+///    ecore_evas: the callbacks on the focused object, the frame rate cap's
+///    animator, the idle enterer, the occlusion and socket event handlers
+///    and any pending coalesced mouse move.
+/// 4. The pointer from the ecore_evas back to the window, so that callbacks
+///    run from here on don't see a window that is partly dropped.
+/// 5. The ecore_evas, which frees the canvas along with its objects and any
+///    GL surfaces and contexts created on it.
+/// 6. The GL configuration, which those surfaces may still refer to until
+///    they are gone.
+///
+/// The remaining state is dropped afterwards. Dropping a window borrowed by
+/// a callback does nothing.
 #[unsafe_destructor]
 impl<'a> Drop for Window<'a> {
     fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+        pre_free_callback(self.ee);
+        destroy_callback(self.ee);
+        self.ime.disable();
+        object::release_focus(self);
+        throttle::release(self);
+        visibility::unregister(self);
+        self.extn.unregister();
+        self.coalesce.cancel_job();
+        self.unbind_data_ptr();
         unsafe {
             ffi::ecore_evas_free(self.ee);
         }
+        // Freed here rather than with the other fields, so that the sequence
+        // doesn't depend on the order they are declared in
+        drop(self.gl_config.take());
+        let _: Box<WindowData> = unsafe { mem::transmute(self.data) };
+    }
+}
+
+pub trait StickyChangeCallback {
+    fn call(&self, &Window, bool);
+}
+
+impl<'a> Window<'a> {
+    /// Set a callback that is called with the new state whenever the window
+    /// becomes sticky or stops being sticky. This is called in addition to
+    /// the separate sticky and unsticky callbacks.
+    pub fn set_sticky_change_callback(&mut self, callback: Box<StickyChangeCallback>)
+                                      -> Option<Box<StickyChangeCallback>> {
+        mem::replace(&mut self.sticky_change_callback, Some(callback))
+    }
+
+    pub fn unset_sticky_change_callback(&mut self) -> Option<Box<StickyChangeCallback>> {
+        self.sticky_change_callback.take()
+    }
+}
+
+/// Pass the new sticky state of the window to its sticky change callback.
+fn dispatch_sticky_change(window: &Window) {
+    for callback in window.sticky_change_callback.iter() {
+        let sticky = unsafe { ffi::from_eina_bool(ffi::ecore_evas_sticky_get(window.ee as *const _)) };
+        callback.call(window, sticky);
+    }
+}
+
+pub trait ProfileChangeCallback {
+    fn call(&self, &Window, Option<&str>);
+}
+
+impl<'a> Window<'a> {
+    /// The profile the window manager has applied to the window, if any.
+    pub fn get_profile(&self) -> Option<String> {
+        unsafe { ffi::string_from_c_ptr(ffi::ecore_evas_window_profile_get(self.ee as *const _)) }
+    }
+
+    /// Set a callback that is called with the new profile whenever the
+    /// window manager switches the window to a different profile, such as
+    /// when a device is docked.
+    pub fn set_profile_change_callback(&mut self, callback: Box<ProfileChangeCallback>)
+                                       -> Option<Box<ProfileChangeCallback>> {
+        *self.profile.borrow_mut() = self.get_profile();
+        mem::replace(&mut self.profile_change_callback, Some(callback))
+    }
+
+    pub fn unset_profile_change_callback(&mut self) -> Option<Box<ProfileChangeCallback>> {
+        self.profile_change_callback.take()
+    }
+}
+
+/// Pass the window's profile to its profile change callback, if it differs
+/// from the last one seen. Ecore_Evas reports profile changes as generic
+/// state changes.
+fn dispatch_profile_change(window: &Window) {
+    for callback in window.profile_change_callback.iter() {
+        let profile = window.get_profile();
+        if profile == *window.profile.borrow() { return }
+        *window.profile.borrow_mut() = profile.clone();
+        callback.call(window, profile.as_ref().map(|p| p.as_slice()));
+    }
+}
+
+pub trait DeviceRotationCallback {
+    fn call(&self, &Window, i32);
+}
+
+impl<'a> Window<'a> {
+    /// The rotation of the window's contents, in degrees counterclockwise.
+    pub fn get_rotation(&self) -> i32 {
+        unsafe { ffi::ecore_evas_rotation_get(self.ee as *const _) as i32 }
+    }
+
+    /// Rotate the window's contents, in degrees counterclockwise. The window
+    /// is resized to match, so width and height swap at 90 and 270 degrees.
+    pub fn set_rotation(&self, rotation: i32) {
+        unsafe { ffi::ecore_evas_rotation_with_resize_set(self.ee, rotation as libc::c_int) };
+    }
+
+    /// Set a callback that is called with the new rotation, in degrees,
+    /// whenever the window manager rotates the window, such as when a mobile
+    /// device is turned.
+    ///
+    /// While the callback is set, the window manager waits for the window to
+    /// acknowledge each rotation before showing it. The callback should lay
+    /// its content out for the new rotation, and then call
+    /// `rotation_done`, or the window manager will stall until it times out.
+    pub fn set_device_rotation_callback(&mut self, callback: Box<DeviceRotationCallback>)
+                                        -> Option<Box<DeviceRotationCallback>> {
+        self.rotation.set(self.get_rotation());
+        unsafe { ffi::ecore_evas_wm_rotation_manual_rotation_done_set(self.ee, ffi::EINA_TRUE) };
+        mem::replace(&mut self.device_rotation_callback, Some(callback))
+    }
+
+    pub fn unset_device_rotation_callback(&mut self) -> Option<Box<DeviceRotationCallback>> {
+        unsafe { ffi::ecore_evas_wm_rotation_manual_rotation_done_set(self.ee, ffi::EINA_FALSE) };
+        self.device_rotation_callback.take()
+    }
+
+    /// Tell the window manager that the window has finished laying out its
+    /// content for a rotation delivered to the device rotation callback.
+    pub fn rotation_done(&self) {
+        unsafe { ffi::ecore_evas_wm_rotation_manual_rotation_done(self.ee) };
+    }
+}
+
+/// Pass the window's rotation to its device rotation callback, if it differs
+/// from the last one seen. Like profile changes, rotations requested by the
+/// window manager are reported as generic state changes.
+fn dispatch_device_rotation(window: &Window) {
+    for callback in window.device_rotation_callback.iter() {
+        let rotation = window.get_rotation();
+        if rotation == window.rotation.get() { return }
+        window.rotation.set(rotation);
+        callback.call(window, rotation);
+    }
+}
+
+/// The states of a window that the window manager can toggle
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct WindowState {
+    pub iconified: bool,
+    pub maximized: bool,
+    pub fullscreen: bool,
+    pub sticky: bool,
+}
+
+impl WindowState {
+    fn new() -> WindowState {
+        WindowState { iconified: false, maximized: false, fullscreen: false, sticky: false }
+    }
+}
+
+/// The window states toggled by a state change. Each is the new value of
+/// the state if it changed, and `None` otherwise.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct StateChanged {
+    pub iconified: Option<bool>,
+    pub maximized: Option<bool>,
+    pub fullscreen: Option<bool>,
+    pub sticky: Option<bool>,
+}
+
+impl StateChanged {
+    /// The states that differ between `old` and `new`
+    fn diff(old: &WindowState, new: &WindowState) -> StateChanged {
+        let changed = |old: bool, new: bool| if old != new { Some(new) } else { None };
+        StateChanged {
+            iconified: changed(old.iconified, new.iconified),
+            maximized: changed(old.maximized, new.maximized),
+            fullscreen: changed(old.fullscreen, new.fullscreen),
+            sticky: changed(old.sticky, new.sticky),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.iconified.is_none() && self.maximized.is_none() && self.fullscreen.is_none() && self.sticky.is_none()
+    }
+}
+
+pub trait StateChangedCallback {
+    fn call(&self, &Window, StateChanged);
+}
+
+impl StateChangedCallback for Forwarded<Box<StateChangedCallback>> {
+    fn call(&self, window: &Window, changed: StateChanged) {
+        self.with((), |callback| callback.call(window, changed.clone()))
+    }
+}
+
+impl Forward for Box<StateChangedCallback> {
+    fn forward(callback: Rc<RefCell<Option<Box<StateChangedCallback>>>>) -> Box<StateChangedCallback> {
+        box Forwarded { callback: callback } as Box<StateChangedCallback>
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Whether the window is currently iconified, maximized, fullscreen and
+    /// sticky.
+    pub fn get_state(&self) -> WindowState {
+        WindowState {
+            iconified: self.is_iconified(),
+            maximized: self.is_maximized(),
+            fullscreen: self.is_fullscreen(),
+            sticky: unsafe { ffi::from_eina_bool(ffi::ecore_evas_sticky_get(self.ee as *const _)) },
+        }
+    }
+
+    /// Set a callback that is called with the states that toggled whenever
+    /// the window is iconified or restored, maximized, made fullscreen or
+    /// made sticky. The window's state is compared to the one seen at the
+    /// last state change, so state changes that toggle nothing, such as
+    /// profile changes, are not reported. This is called in addition to the
+    /// state change callback, which like the other window event callbacks
+    /// is only told that the event happened.
+    pub fn set_state_changed_callback(&self, callback: Box<StateChangedCallback>)
+                                      -> Option<Box<StateChangedCallback>> {
+        self.state_changed_callback.replace(Some(callback))
+    }
+
+    pub fn unset_state_changed_callback(&self) -> Option<Box<StateChangedCallback>> {
+        self.state_changed_callback.take()
+    }
+}
+
+/// Update the cached window state, and pass the states that toggled to the
+/// state changed callback.
+fn dispatch_state_changed(window: &Window) {
+    let state = window.get_state();
+    let changed = StateChanged::diff(&window.state.get(), &state);
+    window.state.set(state);
+    if changed.is_empty() { return }
+    window.state_changed_callback.with((), |callback| callback.call(window, changed.clone()));
+}
+
+/// Dispatch the state changes that Ecore_Evas has no dedicated callback for.
+fn dispatch_state_change(window: &Window) {
+    dispatch_state_changed(window);
+    throttle::update_pause(window);
+    dispatch_profile_change(window);
+    dispatch_device_rotation(window);
+}
+
+/// The largest rectangle with the aspect ratio of `content_w` by `content_h`
+/// that fits in a window of `w` by `h`, centered.
+fn letterbox_rect(w: i32, h: i32, content_w: i32, content_h: i32) -> Rect {
+    let scale = (w as f64 / content_w as f64).min(h as f64 / content_h as f64);
+    let (image_w, image_h) = ((content_w as f64 * scale) as i32, (content_h as f64 * scale) as i32);
+    Rect::new((w - image_w) / 2, (h - image_h) / 2, image_w, image_h)
+}
+
+/// The area of the window covered by its image.
+fn image_geometry(window: &Window) -> Rect {
+    let (w, h) = window.get_size();
+    match window.letterbox.get() {
+        Some((content_w, content_h)) => letterbox_rect(w, h, content_w, content_h),
+        None => Rect::new(0, 0, w, h),
+    }
+}
+
+/// Fit the window's image to the window, letterboxing it if requested, and
+/// make the background fill the window.
+fn layout_image(window: &Window) {
+    let (w, h) = window.get_size();
+    let geometry = image_geometry(window);
+    unsafe {
+        ffi::evas_object_move(window.object, geometry.x, geometry.y);
+        ffi::evas_object_resize(window.object, geometry.w, geometry.h);
+        let background = window.background.get();
+        if !background.is_null() {
+            ffi::evas_object_move(background, 0, 0);
+            ffi::evas_object_resize(background, w, h);
+        }
+    }
+}
+
+/// Storage for a callback that can be replaced or removed from within its own
+/// call. The callback is moved out of the slot while it is called, so the
+/// setters never touch a callback that is running.
+struct CallbackSlot<B> {
+    callback: RefCell<Option<B>>,
+    /// The callback being called, if any. It is shared with the stand-in
+    /// handed back for it if the slot is changed during the call.
+    running: RefCell<Option<Rc<RefCell<Option<B>>>>>,
+    /// Whether the slot was changed during the current call
+    changed: Cell<bool>,
+}
+
+impl<B: Forward> CallbackSlot<B> {
+    fn new() -> CallbackSlot<B> {
+        CallbackSlot { callback: RefCell::new(None), running: RefCell::new(None), changed: Cell::new(false) }
+    }
+
+    /// Whether there is a callback, counting one that is being called unless
+    /// it has removed or replaced itself.
+    fn is_some(&self) -> bool {
+        self.callback.borrow().is_some() || (self.running.borrow().is_some() && !self.changed.get())
+    }
+
+    fn is_none(&self) -> bool {
+        !self.is_some()
+    }
+
+    /// Replace the callback, returning the previous one. A callback that is
+    /// being called is returned as a stand-in that calls it, as it can't be
+    /// given away before its call has finished.
+    fn replace(&self, callback: Option<B>) -> Option<B> {
+        let running = if self.changed.get() { None } else { self.running.borrow().clone() };
+        self.changed.set(true);
+        let previous = mem::replace(&mut *self.callback.borrow_mut(), callback);
+        match running {
+            Some(running) => Some(Forward::forward(running)),
+            None => previous,
+        }
+    }
+
+    fn take(&self) -> Option<B> {
+        self.replace(None)
+    }
+
+    /// Call `f` with the callback, if there is one, and put the callback back
+    /// afterwards unless the slot was changed meanwhile. This is done even
+    /// if `f` fails. A callback that causes its own event to be dispatched
+    /// again is not called again.
+    fn with<R>(&self, default: R, f: |&B| -> R) -> R {
+        let callback = match self.callback.borrow_mut().take() {
+            Some(callback) => callback,
+            None => return default,
+        };
+        let running = Rc::new(RefCell::new(Some(callback)));
+        let _call = SlotCall {
+            slot: self,
+            running: running.clone(),
+            outer_running: mem::replace(&mut *self.running.borrow_mut(), Some(running.clone())),
+            outer_changed: self.changed.get(),
+        };
+        self.changed.set(false);
+        let callback = running.borrow();
+        f(callback.as_ref().unwrap())
+    }
+}
+
+/// Restores a `CallbackSlot` once a call of its callback is over, including
+/// when the callback fails
+struct SlotCall<'a, B> {
+    slot: &'a CallbackSlot<B>,
+    running: Rc<RefCell<Option<B>>>,
+    /// The state of the slot's call that was in progress when this one began,
+    /// if any
+    outer_running: Option<Rc<RefCell<Option<B>>>>,
+    outer_changed: bool,
+}
+
+#[unsafe_destructor]
+impl<'a, B> Drop for SlotCall<'a, B> {
+    fn drop(&mut self) {
+        let changed = self.slot.changed.get();
+        *self.slot.running.borrow_mut() = self.outer_running.take();
+        self.slot.changed.set(self.outer_changed);
+        if !changed {
+            *self.slot.callback.borrow_mut() = self.running.borrow_mut().take();
+        }
+    }
+}
+
+/// Callbacks that a `Forwarded` can stand in for
+trait Forward {
+    fn forward(callback: Rc<RefCell<Option<Self>>>) -> Self;
+}
+
+/// Stands in for a callback that was replaced during its own call, and calls
+/// that callback from then on
+struct Forwarded<B> {
+    callback: Rc<RefCell<Option<B>>>,
+}
+
+impl<B> Forwarded<B> {
+    fn with<R>(&self, default: R, f: |&B| -> R) -> R {
+        match *self.callback.borrow() {
+            Some(ref callback) => f(callback),
+            None => default,
+        }
     }
 }
 
 macro_rules! event_callbacks {
     ($(($field:ident,
+        $Variant:ident,
         $extern_set_callback:path,
         $extern_callback: ident,
         $set_callback:ident,
-        $unset_callback:ident)),+
+        $unset_callback:ident,
+        $has_callback:ident,
+        $internal_handler:expr)),+
     ) => {
         pub trait EventCallback {
             fn call(&self, &Window);
         }
 
+        /// Any of the window events that can be delivered to a window
+        #[deriving(Clone, PartialEq, Eq, Show)]
+        pub enum WindowEvent {
+            $($Variant),+
+        }
+
+        /// A single callback that receives every kind of window event
+        pub trait LifecycleHandler {
+            fn call(&self, &Window, WindowEvent);
+        }
+
+        impl EventCallback for Forwarded<Box<EventCallback>> {
+            fn call(&self, window: &Window) {
+                self.with((), |callback| callback.call(window))
+            }
+        }
+
+        impl Forward for Box<EventCallback> {
+            fn forward(callback: Rc<RefCell<Option<Box<EventCallback>>>>) -> Box<EventCallback> {
+                box Forwarded { callback: callback } as Box<EventCallback>
+            }
+        }
+
+        impl LifecycleHandler for Forwarded<Box<LifecycleHandler>> {
+            fn call(&self, window: &Window, event: WindowEvent) {
+                self.with((), |handler| handler.call(window, event.clone()))
+            }
+        }
+
+        impl Forward for Box<LifecycleHandler> {
+            fn forward(handler: Rc<RefCell<Option<Box<LifecycleHandler>>>>) -> Box<LifecycleHandler> {
+                box Forwarded { callback: handler } as Box<LifecycleHandler>
+            }
+        }
+
         struct EventCallbacks {
-            $($field: Option<Box<EventCallback>>,)+
+            $($field: CallbackSlot<Box<EventCallback>>,)+
+            handler: CallbackSlot<Box<LifecycleHandler>>,
         }
 
         impl EventCallbacks {
             fn new() -> EventCallbacks {
                 EventCallbacks {
-                    $($field: None,)+
+                    $($field: CallbackSlot::new(),)+
+                    handler: CallbackSlot::new(),
                 }
             }
         }
 
         $(extern "C" fn $extern_callback(ee: *mut ffi::Ecore_Evas) {
-            println!(stringify!($extern_callback));
-            unsafe {
-                let window = Window::data_ptr_key().with_c_str(|key| {
-                    ffi::ecore_evas_data_get(ee as *const _, key)
-                }) as *const Window;
-                assert!(!window.is_null());
-                match (*window).event_callbacks.$field {
-                    Some(ref callback) => {
-                        println!("{:p}", callback);
-                        callback.call(&*window) // segfault! >_<
-                    },
-                    None => {
-                        $extern_set_callback((*window).ee, None);
-                    }
+            ::guard_callback(stringify!($extern_callback), (), || {
+                // The window is being dropped. Callbacks are only
+                // unregistered by the unsetters, never from here, as the
+                // window may not be in a usable state.
+                let window = match unsafe { bound_window(ee as *const _) } {
+                    Some(window) => window,
+                    None => return,
+                };
+                let internal_handler: Option<fn(&Window)> = $internal_handler;
+                for handler in internal_handler.iter() {
+                    (*handler)(&window);
                 }
-            }
+                let callbacks = &window.event_callbacks;
+                callbacks.$field.with((), |callback| callback.call(&window));
+                callbacks.handler.with((), |handler| handler.call(&window, $Variant));
+            })
         })+
 
         impl<'a> Window<'a> {
-            $(pub fn $set_callback(&mut self, callback: Box<EventCallback>) -> Option<Box<EventCallback>> {
-                println!(stringify!($set_callback));
+            $(pub fn $set_callback(&self, callback: Box<EventCallback>) -> Option<Box<EventCallback>> {
                 unsafe { $extern_set_callback(self.ee, Some($extern_callback)) };
-                mem::replace(&mut self.event_callbacks.$field, Some(callback))
+                self.event_callbacks.$field.replace(Some(callback))
             }
 
-            pub fn $unset_callback(&mut self) -> Option<Box<EventCallback>> {
-                println!(stringify!($unset_callback));
-                unsafe { $extern_set_callback(self.ee, None) };
+            pub fn $unset_callback(&self) -> Option<Box<EventCallback>> {
+                let internal_handler: Option<fn(&Window)> = $internal_handler;
+                if internal_handler.is_none() && self.event_callbacks.handler.is_none() {
+                    unsafe { $extern_set_callback(self.ee, None) };
+                }
                 self.event_callbacks.$field.take()
+            }
+
+            pub fn $has_callback(&self) -> bool {
+                self.event_callbacks.$field.is_some()
             })+
+
+            pub fn has_lifecycle_handler(&self) -> bool {
+                self.event_callbacks.handler.is_some()
+            }
+
+            /// Set a single handler to receive every kind of window event.
+            /// This is called in addition to any callbacks set for specific
+            /// events.
+            pub fn set_lifecycle_handler(&self, handler: Box<LifecycleHandler>) -> Option<Box<LifecycleHandler>> {
+                unsafe {
+                    $($extern_set_callback(self.ee, Some($extern_callback));)+
+                }
+                self.event_callbacks.handler.replace(Some(handler))
+            }
+
+            pub fn unset_lifecycle_handler(&self) -> Option<Box<LifecycleHandler>> {
+                $(let internal_handler: Option<fn(&Window)> = $internal_handler;
+                if internal_handler.is_none() && self.event_callbacks.$field.is_none() {
+                    unsafe { $extern_set_callback(self.ee, None) };
+                })+
+                self.event_callbacks.handler.take()
+            }
+
+            /// Register the events that the window handles internally,
+            /// regardless of whether the user has set a callback for them
+            fn register_internal_callbacks(&self) {
+                $(let internal_handler: Option<fn(&Window)> = $internal_handler;
+                if internal_handler.is_some() {
+                    unsafe { $extern_set_callback(self.ee, Some($extern_callback)) };
+                })+
+            }
         }
     };
 }
 
 event_callbacks! {
-//  vtable field    ffi callback setter                          extern "C" callback      callback setter              callback unsetter
-    (resize,         ffi::ecore_evas_callback_resize_set,         resize_callback,         set_resize_callback,         unset_resize_callback),
-    (move,           ffi::ecore_evas_callback_move_set,           move_callback,           set_move_callback,           unset_move_callback),
-    (show,           ffi::ecore_evas_callback_show_set,           show_callback,           set_show_callback,           unset_show_callback),
-    (hide,           ffi::ecore_evas_callback_hide_set,           hide_callback,           set_hide_callback,           unset_hide_callback),
-    (delete_request, ffi::ecore_evas_callback_delete_request_set, delete_request_callback, set_delete_request_callback, unset_delete_request_callback),
-    (destroy,        ffi::ecore_evas_callback_destroy_set,        destroy_callback,        set_destroy_callback,        unset_destroy_callback),
-    (focus_in,       ffi::ecore_evas_callback_focus_in_set,       focus_in_callback,       set_focus_in_callback,       unset_focus_in_callback),
-    (focus_out,      ffi::ecore_evas_callback_focus_out_set,      focus_out_callback,      set_focus_out_callback,      unset_focus_out_callback),
-    (sticky,         ffi::ecore_evas_callback_sticky_set,         sticky_callback,         set_sticky_callback,         unset_sticky_callback),
-    (unsticky,       ffi::ecore_evas_callback_unsticky_set,       unsticky_callback,       set_unsticky_callback,       unset_unsticky_callback),
-//  (mouse_in,       ffi::ecore_evas_callback_mouse_in_set,       mouse_in_callback,       set_mouse_in_callback,       unset_mouse_in_callback),
-//  (mouse_out,      ffi::ecore_evas_callback_mouse_out_set,      mouse_out_callback,      set_mouse_out_callback,      unset_mouse_out_callback),
-    (pre_render,     ffi::ecore_evas_callback_pre_render_set,     pre_render_callback,     set_pre_render_callback,     unset_pre_render_callback),
-    (post_render,    ffi::ecore_evas_callback_post_render_set,    post_render_callback,    set_post_render_callback,    unset_post_render_callback),
-    (pre_free,       ffi::ecore_evas_callback_pre_free_set,       pre_free_callback,       set_pre_free_callback,       unset_pre_free_callback),
-    (state_change,   ffi::ecore_evas_callback_state_change_set,   state_change_callback,   set_state_change_callback,   unset_state_change_callback)
+//  vtable field     window event variant  ffi callback setter                          extern "C" callback      callback setter              callback unsetter              callback predicate           internal handler
+    (resize,         WindowResize,         ffi::ecore_evas_callback_resize_set,         resize_callback,         set_resize_callback,         unset_resize_callback,         has_resize_callback,         Some(layout_image)),
+    (move,           WindowMove,           ffi::ecore_evas_callback_move_set,           move_callback,           set_move_callback,           unset_move_callback,           has_move_callback,           None),
+    (show,           WindowShow,           ffi::ecore_evas_callback_show_set,           show_callback,           set_show_callback,           unset_show_callback,           has_show_callback,           None),
+    (hide,           WindowHide,           ffi::ecore_evas_callback_hide_set,           hide_callback,           set_hide_callback,           unset_hide_callback,           has_hide_callback,           None),
+    (delete_request, WindowDeleteRequest,  ffi::ecore_evas_callback_delete_request_set, delete_request_callback, set_delete_request_callback, unset_delete_request_callback, has_delete_request_callback, None),
+    (destroy,        WindowDestroy,        ffi::ecore_evas_callback_destroy_set,        destroy_callback,        set_destroy_callback,        unset_destroy_callback,        has_destroy_callback,        None),
+    (focus_in,       WindowFocusIn,        ffi::ecore_evas_callback_focus_in_set,       focus_in_callback,       set_focus_in_callback,       unset_focus_in_callback,       has_focus_in_callback,       None),
+    (focus_out,      WindowFocusOut,       ffi::ecore_evas_callback_focus_out_set,      focus_out_callback,      set_focus_out_callback,      unset_focus_out_callback,      has_focus_out_callback,      Some(repeat::release_keys)),
+    (sticky,         WindowSticky,         ffi::ecore_evas_callback_sticky_set,         sticky_callback,         set_sticky_callback,         unset_sticky_callback,         has_sticky_callback,         Some(dispatch_sticky_change)),
+    (unsticky,       WindowUnsticky,       ffi::ecore_evas_callback_unsticky_set,       unsticky_callback,       set_unsticky_callback,       unset_unsticky_callback,       has_unsticky_callback,       Some(dispatch_sticky_change)),
+//  (mouse_in,       WindowMouseIn,        ffi::ecore_evas_callback_mouse_in_set,       mouse_in_callback,       set_mouse_in_callback,       unset_mouse_in_callback,       has_mouse_in_callback,       None),
+//  (mouse_out,      WindowMouseOut,       ffi::ecore_evas_callback_mouse_out_set,      mouse_out_callback,      set_mouse_out_callback,      unset_mouse_out_callback,      has_mouse_out_callback,      None),
+    (pre_render,     WindowPreRender,      ffi::ecore_evas_callback_pre_render_set,     pre_render_callback,     set_pre_render_callback,     unset_pre_render_callback,     has_pre_render_callback,     None),
+    (post_render,    WindowPostRender,     ffi::ecore_evas_callback_post_render_set,    post_render_callback,    set_post_render_callback,    unset_post_render_callback,    has_post_render_callback,    Some(frame::dispatch_frame)),
+    (pre_free,       WindowPreFree,        ffi::ecore_evas_callback_pre_free_set,       pre_free_callback,       set_pre_free_callback,       unset_pre_free_callback,       has_pre_free_callback,       None),
+    (state_change,   WindowStateChange,    ffi::ecore_evas_callback_state_change_set,   state_change_callback,   set_state_change_callback,   unset_state_change_callback,   has_state_change_callback,   Some(dispatch_state_change))
+}
+
+/// Whether input events of this type are needed for something other than
+/// the input callbacks, and so must stay registered without them
+fn wants_input_event(window: &Window, callback_type: ffi::Evas_Callback_Type) -> bool {
+    ime::wants_event(window, callback_type) || touch::wants_event(window, callback_type)
+        || repeat::wants_event(window, callback_type) || text::wants_event(window, callback_type)
+        || object::wants_focus_event(window, callback_type)
+}
+
+/// What an input callback did with an event
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum EventResponse {
+    /// The event was not handled, and should be processed as usual.
+    EventPropagate,
+    /// The event was handled. It is marked with `EventFlagOnHold`, which
+    /// tells every later handler, including the input handler and other
+    /// Evas objects, that it has already been acted on.
+    EventHandled,
 }
 
 /// Generates a set of event callbacks
@@ -620,25 +2922,76 @@ macro_rules! input_callbacks {
     ($(($field:ident,
         $Evas_Event_Info:ty,
         $EventInfo:ident,
+        $Variant:ident,
         $EVAS_CALLBACK:expr,
         $extern_callback: ident,
         $InputCallback:ident,
         $set_callback:ident,
-        $unset_callback:ident)),+
+        $unset_callback:ident,
+        $has_callback:ident)),+
     ) => {
         $(pub trait $InputCallback {
             fn call(&self, &Window, &$EventInfo);
+
+            /// Handle the event, and say whether it has been handled. This
+            /// is what the window calls; by default it calls `call` and lets
+            /// the event propagate. Callbacks that need to stop events
+            /// implement this instead, with a `call` that does nothing.
+            fn respond(&self, window: &Window, info: &$EventInfo) -> EventResponse {
+                self.call(window, info);
+                EventPropagate
+            }
         })+
 
+        /// Any of the input events that can be delivered to a window
+        pub enum InputEvent {
+            $($Variant($EventInfo)),+
+        }
+
+        /// A single callback that receives every kind of input event
+        pub trait InputHandler {
+            fn call(&self, &Window, InputEvent);
+        }
+
+        $(impl $InputCallback for Forwarded<Box<$InputCallback>> {
+            fn call(&self, window: &Window, info: &$EventInfo) {
+                self.with((), |callback| callback.call(window, info))
+            }
+
+            fn respond(&self, window: &Window, info: &$EventInfo) -> EventResponse {
+                self.with(EventPropagate, |callback| callback.respond(window, info))
+            }
+        }
+
+        impl Forward for Box<$InputCallback> {
+            fn forward(callback: Rc<RefCell<Option<Box<$InputCallback>>>>) -> Box<$InputCallback> {
+                box Forwarded { callback: callback } as Box<$InputCallback>
+            }
+        })+
+
+        impl InputHandler for Forwarded<Box<InputHandler>> {
+            fn call(&self, window: &Window, event: InputEvent) {
+                let mut event = Some(event);
+                self.with((), |handler| handler.call(window, event.take().unwrap()))
+            }
+        }
+
+        impl Forward for Box<InputHandler> {
+            fn forward(handler: Rc<RefCell<Option<Box<InputHandler>>>>) -> Box<InputHandler> {
+                box Forwarded { callback: handler } as Box<InputHandler>
+            }
+        }
+
         /// A vtable of event callback functions
         struct InputCallbacks {
-            $($field: Option<Box<$InputCallback>>),+
+            $($field: CallbackSlot<Box<$InputCallback>>,)+
+            handler: CallbackSlot<Box<InputHandler>>,
         }
 
         impl InputCallbacks {
             /// An empty window event vtable
             fn new() -> InputCallbacks {
-                InputCallbacks { $($field: None),+ }
+                InputCallbacks { $($field: CallbackSlot::new(),)+ handler: CallbackSlot::new() }
             }
         }
 
@@ -648,67 +3001,169 @@ macro_rules! input_callbacks {
             _obj: *mut ffi::Evas_Object,
             event_info: *mut libc::c_void,
         ) {
-            println!(stringify!($extern_callback));
-            unsafe {
-                let window: &Window = mem::transmute(data);
-                match window.input_callbacks.$field {
-                    Some(ref callback) => {
-                        callback.call(window, &$EventInfo {
-                            ptr: event_info as *const _
-                        });
-                    },
-                    None => {
+            ::guard_callback(stringify!($extern_callback), (), || {
+                unsafe {
+                    let window = &Window::from_data_ptr(data as *const _);
+                    let callbacks = &window.input_callbacks;
+                    if callbacks.$field.is_none() && callbacks.handler.is_none()
+                        && !wants_input_event(window, $EVAS_CALLBACK) {
                         ffi::evas_object_event_callback_del(
                             window.object, $EVAS_CALLBACK, Some($extern_callback),
                         );
-                    },
+                        return;
+                    }
+                    if ime::filter_event(window, $EVAS_CALLBACK, event_info) {
+                        return;
+                    }
+                    // Work on a copy, so that converting the coordinates and
+                    // marking repeats doesn't affect other handlers of the event
+                    let mut info: $Evas_Event_Info = *(event_info as *const $Evas_Event_Info);
+                    if repeat::filter(window, $EVAS_CALLBACK, &mut info as *mut _ as *mut libc::c_void) {
+                        return;
+                    }
+                    transform::map_event_coords(window, &mut info);
+                    touch::dispatch(window, $EVAS_CALLBACK, &info as *const _ as *const libc::c_void);
+                    if coalesce::defer(window, $EVAS_CALLBACK, &info as *const _ as *const libc::c_void) {
+                        return;
+                    }
+                    // The focused object sees key events before the key callbacks
+                    let mut response = object::dispatch_focus_key(
+                        window, $EVAS_CALLBACK, &info as *const _ as *const libc::c_void,
+                    );
+                    if response == EventPropagate {
+                        response = callbacks.$field.with(EventPropagate, |callback| {
+                            callback.respond(window, &$EventInfo {
+                                ptr: &info as *const _
+                            })
+                        });
+                    }
+                    if response == EventHandled {
+                        // Mark both the original event, for any later Evas
+                        // callbacks, and the copy, for the input handler
+                        info.event_flags |= ffi::EVAS_EVENT_FLAG_ON_HOLD;
+                        (*(event_info as *mut $Evas_Event_Info)).event_flags |= ffi::EVAS_EVENT_FLAG_ON_HOLD;
+                    }
+                    text::dispatch(window, $EVAS_CALLBACK, &info as *const _ as *const libc::c_void);
+                    callbacks.handler.with((), |handler| {
+                        handler.call(window, $Variant($EventInfo {
+                            ptr: &info as *const _
+                        }))
+                    });
                 }
-            }
+            })
         })+
 
         impl<'a> Window<'a> {
-            $(pub fn $set_callback(&mut self, callback: Box<$InputCallback>) -> Option<Box<$InputCallback>> {
+            $(pub fn $set_callback(&self, callback: Box<$InputCallback>) -> Option<Box<$InputCallback>> {
                 unsafe {
+                    // The extern callback may already be registered for the
+                    // input handler, and must only be called once per event
+                    ffi::evas_object_event_callback_del(
+                        self.object, $EVAS_CALLBACK, Some($extern_callback),
+                    );
                     ffi::evas_object_event_callback_add(
-                        self.object, $EVAS_CALLBACK, Some($extern_callback), self as *mut _ as *const _,
+                        self.object, $EVAS_CALLBACK, Some($extern_callback), self.data_ptr(),
                     );
                 }
-                mem::replace(&mut self.input_callbacks.$field, Some(callback))
+                self.input_callbacks.$field.replace(Some(callback))
+            }
+
+            pub fn $unset_callback(&self) -> Option<Box<$InputCallback>> {
+                if self.input_callbacks.handler.is_none() && !wants_input_event(self, $EVAS_CALLBACK) {
+                    unsafe {
+                        ffi::evas_object_event_callback_del(
+                            self.object, $EVAS_CALLBACK, Some($extern_callback),
+                        );
+                    }
+                }
+                self.input_callbacks.$field.take()
+            }
+
+            pub fn $has_callback(&self) -> bool {
+                self.input_callbacks.$field.is_some()
+            })+
+
+            pub fn has_input_handler(&self) -> bool {
+                self.input_callbacks.handler.is_some()
             }
 
-            pub fn $unset_callback(&mut self) -> Option<Box<$InputCallback>> {
+            /// Set a single handler to receive every kind of input event. This
+            /// is called in addition to any callbacks set for specific events.
+            pub fn set_input_handler(&self, handler: Box<InputHandler>) -> Option<Box<InputHandler>> {
                 unsafe {
-                    ffi::evas_object_event_callback_del(
+                    $(ffi::evas_object_event_callback_del(
                         self.object, $EVAS_CALLBACK, Some($extern_callback),
                     );
+                    ffi::evas_object_event_callback_add(
+                        self.object, $EVAS_CALLBACK, Some($extern_callback), self.data_ptr(),
+                    );)+
                 }
-                self.input_callbacks.$field.take()
-            })+
+                self.input_callbacks.handler.replace(Some(handler))
+            }
+
+            pub fn unset_input_handler(&self) -> Option<Box<InputHandler>> {
+                unsafe {
+                    $(if self.input_callbacks.$field.is_none() && !wants_input_event(self, $EVAS_CALLBACK) {
+                        ffi::evas_object_event_callback_del(
+                            self.object, $EVAS_CALLBACK, Some($extern_callback),
+                        );
+                    })+
+                }
+                self.input_callbacks.handler.take()
+            }
         }
     }
 }
 
 input_callbacks! {
-//  vtable field    ffi event info struct         event info    callback ffi specifier           extern "C" callback   callback trait      callback setter             callback unsetter
-    (mouse_down,    ffi::Evas_Event_Mouse_Down,   MouseDown,    ffi::EVAS_CALLBACK_MOUSE_DOWN,   mouse_down_callback,  MouseDownCallback,  set_mouse_down_callback,    unset_mouse_down_callback),
-    (mouse_up,      ffi::Evas_Event_Mouse_Up,     MouseUp,      ffi::EVAS_CALLBACK_MOUSE_UP,     mouse_up_callback,    MouseUpCallback,    set_mouse_up_callback,      unset_mouse_up_callback),
-    (mouse_in,      ffi::Evas_Event_Mouse_In,     MouseIn,      ffi::EVAS_CALLBACK_MOUSE_IN,     mouse_in_callback,    MouseInCallback,    set_mouse_in_callback,      unset_mouse_in_callback),
-    (mouse_out,     ffi::Evas_Event_Mouse_Out,    MouseOut,     ffi::EVAS_CALLBACK_MOUSE_OUT,    mouse_out_callback,   MouseOutCallback,   set_mouse_out_callback,     unset_mouse_out_callback),
-    (mouse_move,    ffi::Evas_Event_Mouse_Move,   MouseMove,    ffi::EVAS_CALLBACK_MOUSE_MOVE,   mouse_move_callback,  MouseMoveCallback,  set_mouse_move_callback,    unset_mouse_move_callback),
-    (mouse_wheel,   ffi::Evas_Event_Mouse_Wheel,  MouseWheel,   ffi::EVAS_CALLBACK_MOUSE_WHEEL,  mouse_wheel_callback, MouseWheelCallback, set_mouse_wheel_callback,   unset_mouse_wheel_callback),
-    (multi_down,    ffi::Evas_Event_Multi_Down,   MultiDown,    ffi::EVAS_CALLBACK_MULTI_DOWN,   multi_down_callback,  MultiDownCallback,  set_multi_down_callback,    unset_multi_down_callback),
-    (multi_up,      ffi::Evas_Event_Multi_Up,     MultiUp,      ffi::EVAS_CALLBACK_MULTI_UP,     multi_up_callback,    MultiUpCallback,    set_multi_up_callback,      unset_multi_up_callback),
-    (multi_move,    ffi::Evas_Event_Multi_Move,   MultiMove,    ffi::EVAS_CALLBACK_MULTI_MOVE,   multi_move_callback,  MultiMoveCallback,  set_multi_move_callback,    unset_multi_move_callback),
-    (key_down,      ffi::Evas_Event_Key_Down,     KeyDown,      ffi::EVAS_CALLBACK_KEY_DOWN,     key_down_callback,    KeyDownCallback,    set_key_down_callback,      unset_key_down_callback),
-    (key_up,        ffi::Evas_Event_Key_Up,       KeyUp,        ffi::EVAS_CALLBACK_KEY_UP,       key_up_callback,      KeyUpCallback,      set_key_up_callback,        unset_key_up_callback),
-//  (render_post,   ffi::Evas_Event_Render_Post,  RenderPost,   ffi::EVAS_CALLBACK_RENDER_POST,  render_post_callback, RenderPostCallback, set_render_post_callback,   unset_render_post_callback),
-    (hold,          ffi::Evas_Event_Hold,         Hold,         ffi::EVAS_CALLBACK_HOLD,         hold_callback,        HoldCallback,       set_hold_callback,          unset_hold_callback)
+//  vtable field    ffi event info struct         event info    input event variant  callback ffi specifier           extern "C" callback   callback trait      callback setter             callback unsetter            callback predicate
+    (mouse_down,    ffi::Evas_Event_Mouse_Down,   MouseDown,    InputMouseDown,      ffi::EVAS_CALLBACK_MOUSE_DOWN,   mouse_down_callback,  MouseDownCallback,  set_mouse_down_callback,    unset_mouse_down_callback,   has_mouse_down_callback),
+    (mouse_up,      ffi::Evas_Event_Mouse_Up,     MouseUp,      InputMouseUp,        ffi::EVAS_CALLBACK_MOUSE_UP,     mouse_up_callback,    MouseUpCallback,    set_mouse_up_callback,      unset_mouse_up_callback,     has_mouse_up_callback),
+    (mouse_in,      ffi::Evas_Event_Mouse_In,     MouseIn,      InputMouseIn,        ffi::EVAS_CALLBACK_MOUSE_IN,     mouse_in_callback,    MouseInCallback,    set_mouse_in_callback,      unset_mouse_in_callback,     has_mouse_in_callback),
+    (mouse_out,     ffi::Evas_Event_Mouse_Out,    MouseOut,     InputMouseOut,       ffi::EVAS_CALLBACK_MOUSE_OUT,    mouse_out_callback,   MouseOutCallback,   set_mouse_out_callback,     unset_mouse_out_callback,    has_mouse_out_callback),
+    (mouse_move,    ffi::Evas_Event_Mouse_Move,   MouseMove,    InputMouseMove,      ffi::EVAS_CALLBACK_MOUSE_MOVE,   mouse_move_callback,  MouseMoveCallback,  set_mouse_move_callback,    unset_mouse_move_callback,   has_mouse_move_callback),
+    (mouse_wheel,   ffi::Evas_Event_Mouse_Wheel,  MouseWheel,   InputMouseWheel,     ffi::EVAS_CALLBACK_MOUSE_WHEEL,  mouse_wheel_callback, MouseWheelCallback, set_mouse_wheel_callback,   unset_mouse_wheel_callback,  has_mouse_wheel_callback),
+    (multi_down,    ffi::Evas_Event_Multi_Down,   MultiDown,    InputMultiDown,      ffi::EVAS_CALLBACK_MULTI_DOWN,   multi_down_callback,  MultiDownCallback,  set_multi_down_callback,    unset_multi_down_callback,   has_multi_down_callback),
+    (multi_up,      ffi::Evas_Event_Multi_Up,     MultiUp,      InputMultiUp,        ffi::EVAS_CALLBACK_MULTI_UP,     multi_up_callback,    MultiUpCallback,    set_multi_up_callback,      unset_multi_up_callback,     has_multi_up_callback),
+    (multi_move,    ffi::Evas_Event_Multi_Move,   MultiMove,    InputMultiMove,      ffi::EVAS_CALLBACK_MULTI_MOVE,   multi_move_callback,  MultiMoveCallback,  set_multi_move_callback,    unset_multi_move_callback,   has_multi_move_callback),
+    (key_down,      ffi::Evas_Event_Key_Down,     KeyDown,      InputKeyDown,        ffi::EVAS_CALLBACK_KEY_DOWN,     key_down_callback,    KeyDownCallback,    set_key_down_callback,      unset_key_down_callback,     has_key_down_callback),
+    (key_up,        ffi::Evas_Event_Key_Up,       KeyUp,        InputKeyUp,          ffi::EVAS_CALLBACK_KEY_UP,       key_up_callback,      KeyUpCallback,      set_key_up_callback,        unset_key_up_callback,       has_key_up_callback),
+//  (render_post,   ffi::Evas_Event_Render_Post,  RenderPost,   InputRenderPost,     ffi::EVAS_CALLBACK_RENDER_POST,  render_post_callback, RenderPostCallback, set_render_post_callback,   unset_render_post_callback,  has_render_post_callback),
+    (hold,          ffi::Evas_Event_Hold,         Hold,         InputHold,           ffi::EVAS_CALLBACK_HOLD,         hold_callback,        HoldCallback,       set_hold_callback,          unset_hold_callback,         has_hold_callback)
 }
 
 pub type MouseButton = libc::c_int;
-pub type TimeStamp = libc::c_uint;
+
+/// The time an event occurred, in milliseconds since an arbitrary, engine
+/// specific epoch. The underlying counter is 32 bits wide and wraps around
+/// after roughly 49.7 days, so timestamps should only be compared by taking
+/// the difference between them with `since`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct TimeStamp(pub u32);
+
+impl TimeStamp {
+    pub fn millis(&self) -> u32 {
+        let TimeStamp(ms) = *self;
+        ms
+    }
+
+    /// The time elapsed between `earlier` and this timestamp. This accounts
+    /// for the counter wrapping around between the two, but cannot account
+    /// for it wrapping more than once.
+    pub fn since(&self, earlier: TimeStamp) -> Duration {
+        // Unsigned subtraction wraps, which gives the right answer when the
+        // counter has wrapped between the two timestamps
+        Duration::milliseconds((self.millis() - earlier.millis()) as i64)
+    }
+
+    /// The time since the timestamp's epoch.
+    pub fn to_duration(&self) -> Duration {
+        Duration::milliseconds(self.millis() as i64)
+    }
+}
 pub type Coord = ffi::Evas_Coord;
 
+#[deriving(Clone, PartialEq, Show)]
 pub struct Point {
     pub x: libc::c_int,
     pub y: libc::c_int,
@@ -722,6 +3177,7 @@ impl Point {
     }
 }
 
+#[deriving(Clone, PartialEq, Show)]
 pub struct CoordPoint {
     pub x: Coord,
     pub y: Coord,
@@ -735,6 +3191,7 @@ impl CoordPoint {
     }
 }
 
+#[deriving(Clone, PartialEq, Show)]
 pub struct CoordPrecisionPoint {
     pub x: Coord,
     pub y: Coord,
@@ -752,6 +3209,86 @@ impl CoordPrecisionPoint {
     }
 }
 
+/// An axis-aligned rectangle in canvas coordinates.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Rect {
+    pub x: Coord,
+    pub y: Coord,
+    pub w: Coord,
+    pub h: Coord,
+}
+
+impl Rect {
+    pub fn new(x: Coord, y: Coord, w: Coord, h: Coord) -> Rect {
+        Rect { x: x, y: y, w: w, h: h }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.w <= 0 || self.h <= 0
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        !self.is_empty() && !other.is_empty() &&
+            self.x < other.x + other.w && other.x < self.x + self.w &&
+            self.y < other.y + other.h && other.y < self.y + self.h
+    }
+
+    /// The smallest rectangle containing both `self` and `other`.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() { return other.clone() }
+        if other.is_empty() { return self.clone() }
+        let x = cmp::min(self.x, other.x);
+        let y = cmp::min(self.y, other.y);
+        Rect {
+            x: x,
+            y: y,
+            w: cmp::max(self.x + self.w, other.x + other.w) - x,
+            h: cmp::max(self.y + self.h, other.y + other.h) - y,
+        }
+    }
+}
+
+/// A set of damaged rectangles. Overlapping rectangles are merged into their
+/// bounding box as they are added, so the set never contains two rectangles
+/// that intersect.
+#[deriving(Clone, Show)]
+pub struct DamageRegion {
+    rects: Vec<Rect>,
+}
+
+impl DamageRegion {
+    pub fn new() -> DamageRegion {
+        DamageRegion { rects: Vec::new() }
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() { return }
+        let mut rect = rect;
+        // Merging two rectangles can make the result overlap a rectangle that
+        // neither of them touched, so keep going until nothing intersects.
+        loop {
+            match self.rects.iter().position(|r| r.intersects(&rect)) {
+                Some(i) => rect = rect.union(&self.rects.swap_remove(i).unwrap()),
+                None => break,
+            }
+        }
+        self.rects.push(rect);
+    }
+
+    pub fn rects<'a>(&'a self) -> &'a [Rect] {
+        self.rects.as_slice()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+}
+
+#[deriving(Clone, PartialEq, Show)]
 pub struct Position {
     pub output: Point,
     pub canvas: CoordPoint,
@@ -770,6 +3307,7 @@ impl Position {
     }
 }
 
+#[deriving(Clone, PartialEq, Show)]
 pub struct PrecisionPosition {
     pub output: Point,
     pub canvas: CoordPrecisionPoint,
@@ -792,7 +3330,11 @@ bitflags! {
     flags EventFlags: libc::c_uint {
         static EventFlagNone = ffi::EVAS_EVENT_FLAG_NONE,
         static EventFlagOnHold = ffi::EVAS_EVENT_FLAG_ON_HOLD,
-        static EventFlagOnScroll = ffi::EVAS_EVENT_FLAG_ON_SCROLL
+        static EventFlagOnScroll = ffi::EVAS_EVENT_FLAG_ON_SCROLL,
+        /// Not an Evas flag: marks the key downs generated by auto-repeat,
+        /// when the repeat filter is `RepeatMark`. Taken from the top bit,
+        /// which Evas leaves unused.
+        static EventFlagRepeat = 1 << 31
     }
 }
 
@@ -804,6 +3346,12 @@ bitflags! {
     }
 }
 
+/// The modifier keys registered by ecore_evas on every canvas
+pub static MODIFIER_NAMES: &'static [&'static str] = &["Shift", "Control", "Alt", "Meta", "Hyper", "Super"];
+
+/// The lock keys registered by ecore_evas on every canvas
+pub static LOCK_NAMES: &'static [&'static str] = &["Caps_Lock", "Num_Lock", "Scroll_Lock"];
+
 pub struct Modifier {
     ptr: *const ffi::Evas_Modifier,
 }
@@ -816,6 +3364,12 @@ impl Modifier {
             })
         })
     }
+
+    /// The names of the standard modifiers (see `MODIFIER_NAMES`) that are
+    /// set.
+    pub fn names(&self) -> Vec<String> {
+        MODIFIER_NAMES.iter().filter(|name| self.is_set(**name)).map(|name| name.to_string()).collect()
+    }
 }
 
 pub struct Lock {
@@ -830,6 +3384,11 @@ impl Lock {
             })
         })
     }
+
+    /// The names of the standard locks (see `LOCK_NAMES`) that are set.
+    pub fn names(&self) -> Vec<String> {
+        LOCK_NAMES.iter().filter(|name| self.is_set(**name)).map(|name| name.to_string()).collect()
+    }
 }
 
 /// Generates a safe wrapper around an Evas event info struct
@@ -859,7 +3418,7 @@ event_info_wrapper! {
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
         flags:          ButtonFlags = ButtonFlags::from_bits(flags).unwrap(),
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
         // event_src:   *mut Evas_Object = _,
@@ -875,7 +3434,7 @@ event_info_wrapper! {
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
         flags:          ButtonFlags = ButtonFlags::from_bits(flags).unwrap(),
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
         // event_src:   *mut Evas_Object = _,
@@ -890,7 +3449,7 @@ event_info_wrapper! {
         // data:        *mut libc::c_void = _,
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
         // event_src:   *mut Evas_Object = _,
@@ -905,7 +3464,7 @@ event_info_wrapper! {
         // data:        *mut libc::c_void = _,
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
         // event_src:   *mut Evas_Object = _,
@@ -920,7 +3479,7 @@ event_info_wrapper! {
         // data:        *mut libc::c_void = _,
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
         // event_src:   *mut Evas_Object = _,
@@ -936,7 +3495,7 @@ event_info_wrapper! {
         // data:        *mut libc::c_void = _,
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
         // event_src:   *mut Evas_Object = _,
@@ -957,7 +3516,7 @@ event_info_wrapper! {
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
         flags:          ButtonFlags = ButtonFlags::from_bits(flags).unwrap(),
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
     }
@@ -977,7 +3536,7 @@ event_info_wrapper! {
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
         flags:          ButtonFlags = ButtonFlags::from_bits(flags).unwrap(),
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
     }
@@ -995,7 +3554,7 @@ event_info_wrapper! {
         // data:        *mut libc::c_void = _,
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
     }
@@ -1003,14 +3562,14 @@ event_info_wrapper! {
 
 event_info_wrapper! {
     struct KeyDown(ffi::Evas_Event_Key_Down) {
-        keyname:        String = unsafe { str::raw::from_c_str(keyname as *const _) },
+        keyname:        String = unsafe { ffi::string_from_c_ptr(keyname as *const _).unwrap_or(String::new()) },
         // data:        *mut libc::c_void = _,
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
-        key:            String = unsafe { str::raw::from_c_str(key) },
-        string:         String = unsafe { str::raw::from_c_str(string) },
-        compose:        String = unsafe { str::raw::from_c_str(compose) },
-        timestamp:      TimeStamp = timestamp,
+        key:            String = unsafe { ffi::string_from_c_ptr(key).unwrap_or(String::new()) },
+        string:         String = unsafe { ffi::string_from_c_ptr(string).unwrap_or(String::new()) },
+        compose:        String = unsafe { ffi::string_from_c_ptr(compose).unwrap_or(String::new()) },
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap(),
         // dev:         *mut Evas_Device = _,
         keycode:        libc::c_uint = keycode
@@ -1019,14 +3578,14 @@ event_info_wrapper! {
 
 event_info_wrapper! {
     struct KeyUp(ffi::Evas_Event_Key_Up) {
-        keyname:        String = unsafe { str::raw::from_c_str(keyname as *const _) },
+        keyname:        String = unsafe { ffi::string_from_c_ptr(keyname as *const _).unwrap_or(String::new()) },
         // data:        *mut libc::c_void = _,
         modifiers:      Modifier = Modifier { ptr: modifiers as *const _ },
         locks:          Lock = Lock { ptr: locks as *const _ },
-        key:            String = unsafe { str::raw::from_c_str(key) },
-        string:         String = unsafe { str::raw::from_c_str(string) },
-        compose:        String = unsafe { str::raw::from_c_str(compose) },
-        timestamp:      TimeStamp = timestamp,
+        key:            String = unsafe { ffi::string_from_c_ptr(key).unwrap_or(String::new()) },
+        string:         String = unsafe { ffi::string_from_c_ptr(string).unwrap_or(String::new()) },
+        compose:        String = unsafe { ffi::string_from_c_ptr(compose).unwrap_or(String::new()) },
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap(),
         // dev:         *mut Evas_Device = _,
         keycode:        libc::c_uint = keycode
@@ -1043,9 +3602,610 @@ event_info_wrapper! {
     struct Hold(ffi::Evas_Event_Hold) {
         hold:           libc::c_int = hold,
         // data:        *mut libc::c_void = _,
-        timestamp:      TimeStamp = timestamp,
+        timestamp:      TimeStamp = TimeStamp(timestamp as u32),
         event_flags:    EventFlags = EventFlags::from_bits(event_flags).unwrap()
         // dev:         *mut Evas_Device = _,
         // event_src:   *mut Evas_Object = _,
     }
 }
+
+/// Generates a copy of an event info struct that owns all of its data, so it
+/// can outlive the callback the event was delivered to
+macro_rules! owned_event_info {
+    (struct $Owned:ident($EventInfo:ident) {
+        $($field:ident: $Field:ty = $body:expr),+
+    }) => {
+        #[deriving(Clone)]
+        pub struct $Owned {
+            $(pub $field: $Field),+
+        }
+
+        impl $EventInfo {
+            /// Copy all of the event's data out of the underlying Evas struct,
+            /// which is only valid for the duration of the callback.
+            pub fn to_owned(&self) -> $Owned {
+                $Owned {
+                    $($field: {
+                        let $field = self.$field();
+                        $body
+                    }),+
+                }
+            }
+        }
+    }
+}
+
+owned_event_info! {
+    struct MouseDownOwned(MouseDown) {
+        button:         MouseButton = button,
+        output:         Point = output,
+        canvas:         CoordPoint = canvas,
+        flags:          ButtonFlags = flags,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+owned_event_info! {
+    struct MouseUpOwned(MouseUp) {
+        button:         MouseButton = button,
+        output:         Point = output,
+        canvas:         CoordPoint = canvas,
+        flags:          ButtonFlags = flags,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+owned_event_info! {
+    struct MouseInOwned(MouseIn) {
+        buttons:        MouseButton = buttons,
+        output:         Point = output,
+        canvas:         CoordPoint = canvas,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+owned_event_info! {
+    struct MouseOutOwned(MouseOut) {
+        buttons:        MouseButton = buttons,
+        output:         Point = output,
+        canvas:         CoordPoint = canvas,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+owned_event_info! {
+    struct MouseMoveOwned(MouseMove) {
+        buttons:        MouseButton = buttons,
+        cur:            Position = cur,
+        prev:           Position = prev,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+owned_event_info! {
+    struct MouseWheelOwned(MouseWheel) {
+        direction:      libc::c_int = direction,
+        z:              libc::c_int = z,
+        output:         Point = output,
+        canvas:         CoordPoint = canvas,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+owned_event_info! {
+    struct MultiDownOwned(MultiDown) {
+        device:         libc::c_int = device,
+        radius:         libc::c_double = radius,
+        radius_x:       libc::c_double = radius_x,
+        radius_y:       libc::c_double = radius_y,
+        pressure:       libc::c_double = pressure,
+        angle:          libc::c_double = angle,
+        output:         Point = output,
+        canvas:         CoordPrecisionPoint = canvas,
+        flags:          ButtonFlags = flags,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags,
+        stylus:         Option<input::StylusState> = stylus
+    }
+}
+
+owned_event_info! {
+    struct MultiUpOwned(MultiUp) {
+        device:         libc::c_int = device,
+        radius:         libc::c_double = radius,
+        radius_x:       libc::c_double = radius_x,
+        radius_y:       libc::c_double = radius_y,
+        pressure:       libc::c_double = pressure,
+        angle:          libc::c_double = angle,
+        output:         Point = output,
+        canvas:         CoordPrecisionPoint = canvas,
+        flags:          ButtonFlags = flags,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+owned_event_info! {
+    struct MultiMoveOwned(MultiMove) {
+        device:         libc::c_int = device,
+        radius:         libc::c_double = radius,
+        radius_x:       libc::c_double = radius_x,
+        radius_y:       libc::c_double = radius_y,
+        pressure:       libc::c_double = pressure,
+        angle:          libc::c_double = angle,
+        cur:            PrecisionPosition = cur,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags,
+        stylus:         Option<input::StylusState> = stylus
+    }
+}
+
+owned_event_info! {
+    struct KeyDownOwned(KeyDown) {
+        keyname:        String = keyname,
+        key:            String = key,
+        string:         String = string,
+        compose:        String = compose,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags,
+        keycode:        libc::c_uint = keycode,
+        repeat:         bool = repeat
+    }
+}
+
+owned_event_info! {
+    struct KeyUpOwned(KeyUp) {
+        keyname:        String = keyname,
+        key:            String = key,
+        string:         String = string,
+        compose:        String = compose,
+        modifiers:      Vec<String> = modifiers.names(),
+        locks:          Vec<String> = locks.names(),
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags,
+        keycode:        libc::c_uint = keycode
+    }
+}
+
+owned_event_info! {
+    struct HoldOwned(Hold) {
+        hold:           libc::c_int = hold,
+        timestamp:      TimeStamp = timestamp,
+        event_flags:    EventFlags = event_flags
+    }
+}
+
+/// An owned copy of an `InputEvent`
+#[deriving(Clone)]
+pub enum Event {
+    EventMouseDown(MouseDownOwned),
+    EventMouseUp(MouseUpOwned),
+    EventMouseIn(MouseInOwned),
+    EventMouseOut(MouseOutOwned),
+    EventMouseMove(MouseMoveOwned),
+    EventMouseWheel(MouseWheelOwned),
+    EventMultiDown(MultiDownOwned),
+    EventMultiUp(MultiUpOwned),
+    EventMultiMove(MultiMoveOwned),
+    EventKeyDown(KeyDownOwned),
+    EventKeyUp(KeyUpOwned),
+    EventHold(HoldOwned),
+}
+
+impl InputEvent {
+    pub fn to_owned(&self) -> Event {
+        match *self {
+            InputMouseDown(ref info) => EventMouseDown(info.to_owned()),
+            InputMouseUp(ref info) => EventMouseUp(info.to_owned()),
+            InputMouseIn(ref info) => EventMouseIn(info.to_owned()),
+            InputMouseOut(ref info) => EventMouseOut(info.to_owned()),
+            InputMouseMove(ref info) => EventMouseMove(info.to_owned()),
+            InputMouseWheel(ref info) => EventMouseWheel(info.to_owned()),
+            InputMultiDown(ref info) => EventMultiDown(info.to_owned()),
+            InputMultiUp(ref info) => EventMultiUp(info.to_owned()),
+            InputMultiMove(ref info) => EventMultiMove(info.to_owned()),
+            InputKeyDown(ref info) => EventKeyDown(info.to_owned()),
+            InputKeyUp(ref info) => EventKeyUp(info.to_owned()),
+            InputHold(ref info) => EventHold(info.to_owned()),
+        }
+    }
+}
+
+/// An input handler that copies events into a channel
+struct ChannelInputHandler {
+    sender: Sender<Event>,
+}
+
+impl InputHandler for ChannelInputHandler {
+    fn call(&self, _: &Window, event: InputEvent) {
+        // The receiver may have been dropped, in which case nobody is
+        // interested in the event any more
+        let _ = self.sender.send_opt(event.to_owned());
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Install an input handler that sends a copy of every input event to
+    /// the returned channel, so that events can be handled outside of a
+    /// callback. This replaces any existing input handler.
+    pub fn event_channel(&mut self) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        self.set_input_handler(box ChannelInputHandler { sender: sender });
+        receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_engines, engine_options, Engine, NoEnginesAvailable};
+    #[cfg(all(unix, not(target_os = "macos")))]
+    use super::display_in_env;
+    use super::{Buffer, SoftwareX11, Unknown};
+    use super::Color;
+    use super::{ColorFormat, Rgb888, Rgba8888, ChannelRed, ChannelGreen, ChannelBlue, ChannelAlpha};
+    use super::{letterbox_rect, Rect};
+    use super::{init, guard_callback, EventCallback, Window, AnchorTopLeft, InvalidDisplay};
+    use super::{KeyDownCallback, KeyDown, RepeatMark, TimeStamp};
+    use super::{StateChanged, WindowState};
+    use super::{GlConfig, GlSettings, DepthBits24, StencilBits8, GlOptionsDirect, MultisampleHigh};
+    use ffi;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    /// Counts the calls to a window callback
+    struct Counter(Rc<Cell<uint>>);
+
+    impl EventCallback for Counter {
+        fn call(&self, _: &Window) {
+            let Counter(ref count) = *self;
+            count.set(count.get() + 1);
+        }
+    }
+
+    /// Counts its calls, then fails
+    struct Failing(Rc<Cell<uint>>);
+
+    impl EventCallback for Failing {
+        fn call(&self, _: &Window) {
+            let Failing(ref count) = *self;
+            count.set(count.get() + 1);
+            fail!("callback failed");
+        }
+    }
+
+    /// Replaces itself with a `Counter`, keeping what it was replaced with
+    struct Swap {
+        count: Rc<Cell<uint>>,
+        previous: Rc<RefCell<Option<Box<EventCallback>>>>,
+    }
+
+    impl EventCallback for Swap {
+        fn call(&self, window: &Window) {
+            let previous = window.set_resize_callback(box Counter(self.count.clone()));
+            *self.previous.borrow_mut() = previous;
+        }
+    }
+
+    /// Records whether each key down was a repeat
+    struct Repeats(Rc<RefCell<Vec<bool>>>);
+
+    impl KeyDownCallback for Repeats {
+        fn call(&self, _: &Window, info: &KeyDown) {
+            let Repeats(ref repeats) = *self;
+            repeats.borrow_mut().push(info.repeat());
+        }
+    }
+
+    /// Removes the window's move callback
+    struct UnsetMove;
+
+    impl EventCallback for UnsetMove {
+        fn call(&self, window: &Window) {
+            window.unset_move_callback();
+        }
+    }
+
+    #[test]
+    fn no_engines_is_an_error() {
+        match check_engines([]) {
+            Err(NoEnginesAvailable) => {},
+            other => fail!("expected NoEnginesAvailable, got {}", other),
+        }
+    }
+
+    #[test]
+    fn any_engine_is_enough() {
+        assert!(check_engines([Buffer]).is_ok());
+        assert!(check_engines([Unknown("custom".to_string())]).is_ok());
+    }
+
+    #[test]
+    fn engine_and_preference_replace_each_other() {
+        let context = init().unwrap();
+        let builder = context.build_window(0, 0, 100, 100).with_engine_preference([SoftwareX11, Buffer]);
+        assert_eq!(builder.engine, None);
+        assert_eq!(builder.engine_preference, vec![SoftwareX11, Buffer]);
+        let builder = builder.with_engine(Buffer);
+        assert_eq!(builder.engine, Some(Buffer));
+        assert!(builder.engine_preference.is_empty());
+        let builder = builder.with_engine_preference([SoftwareX11]);
+        assert_eq!(builder.engine, None);
+        assert_eq!(builder.engine_preference, vec![SoftwareX11]);
+    }
+
+    #[test]
+    fn unsupported_preferences_fall_back_to_automatic_selection() {
+        let context = init().unwrap();
+        let automatic = context.build_window(0, 0, 100, 100).with_visible(false).create();
+        let preferred = context.build_window(0, 0, 100, 100).with_visible(false)
+                               .with_engine_preference([Unknown("nonexistent".to_string())])
+                               .create();
+        assert_eq!(preferred.ok().map(|window| window.get_engine()),
+                   automatic.ok().map(|window| window.get_engine()));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn empty_display_is_no_display() {
+        assert!(!display_in_env(|_| None));
+        assert!(!display_in_env(|name| if name == "DISPLAY" { Some(String::new()) } else { None }));
+        assert!(display_in_env(|name| if name == "DISPLAY" { Some(":0".to_string()) } else { None }));
+        assert!(display_in_env(|name| {
+            if name == "WAYLAND_DISPLAY" { Some("wayland-0".to_string()) } else { None }
+        }));
+    }
+
+    #[test]
+    fn engine_names_parse() {
+        assert_eq!(Engine::parse("software_x11".to_string()), SoftwareX11);
+        assert_eq!(Engine::parse("buffer".to_string()), Buffer);
+        assert_eq!(Engine::parse("custom".to_string()), Unknown("custom".to_string()));
+    }
+
+    #[test]
+    fn premultiply_scales_by_alpha() {
+        assert_eq!(Color::new(255, 128, 0, 255).premultiply(), Color::new(255, 128, 0, 255));
+        assert_eq!(Color::new(255, 128, 0, 0).premultiply(), Color::new(0, 0, 0, 0));
+        assert_eq!(Color::new(255, 128, 0, 128).premultiply(), Color::new(128, 64, 0, 128));
+        assert_eq!(Color::new(200, 100, 50, 51).premultiply(), Color::new(40, 20, 10, 51));
+    }
+
+    #[test]
+    fn unpremultiply_undoes_premultiply() {
+        for a in range(1u, 256) {
+            for c in range(0u, 256) {
+                let color = Color::new(c as u8, c as u8, c as u8, a as u8);
+                let round_trip = color.premultiply().unpremultiply().premultiply();
+                assert_eq!(round_trip, color.premultiply());
+            }
+        }
+        assert_eq!(Color::new(255, 255, 255, 255).unpremultiply(), Color::new(255, 255, 255, 255));
+        assert_eq!(Color::new(10, 20, 30, 0).unpremultiply(), Color::new(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn argb32_round_trips() {
+        let color = Color::new(0x12, 0x34, 0x56, 0x78);
+        assert_eq!(color.to_argb32(), 0x78123456);
+        assert_eq!(Color::from_argb32(0x78123456), color);
+    }
+
+    #[test]
+    fn color_formats_map_channels() {
+        let formats: [(ColorFormat, uint), ..2] = [(Rgb888, 3), (Rgba8888, 4)];
+        for &(ref format, bytes) in formats.iter() {
+            assert_eq!(format.bytes_per_pixel(), bytes);
+            assert_eq!(format.channel_order().len(), bytes);
+            let color = Color::new(1, 2, 3, if bytes == 4 { 4 } else { 255 });
+            let pixel = color.to_bytes(format.clone());
+            assert_eq!(pixel.len(), bytes);
+            assert_eq!(Color::from_bytes(format.clone(), pixel.as_slice()), Some(color));
+            assert_eq!(Color::from_bytes(format.clone(), [0, ..5]), None);
+        }
+        assert_eq!(Rgb888.channel_order(), [ChannelRed, ChannelGreen, ChannelBlue].as_slice());
+        assert_eq!(Rgba8888.channel_order(), [ChannelRed, ChannelGreen, ChannelBlue, ChannelAlpha].as_slice());
+        assert_eq!(Color::new(1, 2, 3, 4).to_bytes(Rgb888), vec![1, 2, 3]);
+        assert_eq!(Color::new(1, 2, 3, 4).to_bytes(Rgba8888), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn letterbox_fits_and_centers() {
+        // Pillarboxed: bars on the left and right
+        assert_eq!(letterbox_rect(200, 100, 4, 4), Rect::new(50, 0, 100, 100));
+        // Letterboxed: bars above and below
+        assert_eq!(letterbox_rect(160, 160, 16, 9), Rect::new(0, 35, 160, 90));
+        assert_eq!(letterbox_rect(320, 180, 16, 9), Rect::new(0, 0, 320, 180));
+    }
+
+    #[test]
+    fn hidden_window_gets_callbacks() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).with_visible(false).create().unwrap();
+        let count = Rc::new(Cell::new(0u));
+        assert!(window.set_resize_callback(box Counter(count.clone())).is_none());
+        assert!(window.has_resize_callback());
+        window.resize_anchored(200, 150, AnchorTopLeft);
+        assert_eq!(window.get_size(), (200, 150));
+        assert_eq!(count.get(), 1);
+    }
+
+    #[test]
+    fn callback_unsets_another_callback() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        window.set_move_callback(box Counter(Rc::new(Cell::new(0u))));
+        window.set_resize_callback(box UnsetMove);
+        // The callbacks have to keep working wherever the window ends up
+        let windows = vec![window];
+        let window = windows.get(0);
+        window.resize_anchored(200, 150, AnchorTopLeft);
+        assert!(!window.has_move_callback());
+        assert!(window.has_resize_callback());
+        window.resize_anchored(100, 100, AnchorTopLeft);
+        assert_eq!(window.get_size(), (100, 100));
+    }
+
+    #[test]
+    fn guarded_failure_returns_default() {
+        assert_eq!(guard_callback("test", 1i, || 2), 2);
+        assert_eq!(guard_callback("test", 1i, || fail!("boom")), 1);
+    }
+
+    #[test]
+    fn failing_callback_is_contained() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        let failures = Rc::new(Cell::new(0u));
+        window.set_resize_callback(box Failing(failures.clone()));
+        // The failure unwinds out of the extern callback ecore_evas calls,
+        // and has to stop there
+        unsafe { ffi::ecore_evas_resize(window.ee, 200, 150) };
+        assert_eq!(failures.get(), 1);
+        unsafe { ffi::ecore_main_loop_iterate() };
+        // The main loop and the extern callback keep working afterwards
+        unsafe { ffi::ecore_evas_resize(window.ee, 100, 100) };
+        assert_eq!(failures.get(), 2);
+        assert_eq!(window.get_size(), (100, 100));
+        let count = Rc::new(Cell::new(0u));
+        window.set_resize_callback(box Counter(count.clone()));
+        unsafe { ffi::ecore_evas_resize(window.ee, 200, 150) };
+        assert_eq!(count.get(), 1);
+    }
+
+    #[test]
+    fn callback_replaces_itself() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        let count = Rc::new(Cell::new(0u));
+        let previous = Rc::new(RefCell::new(None));
+        window.set_resize_callback(box Swap { count: count.clone(), previous: previous.clone() });
+        window.resize_anchored(200, 150, AnchorTopLeft);
+        assert!(previous.borrow().is_some());
+        assert_eq!(count.get(), 0);
+        window.resize_anchored(100, 100, AnchorTopLeft);
+        assert_eq!(count.get(), 1);
+        // What was handed back still calls the callback that replaced itself
+        let swap = previous.borrow_mut().take().unwrap();
+        swap.call(&window);
+        assert!(previous.borrow().is_some());
+        window.resize_anchored(200, 150, AnchorTopLeft);
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn key_repeats_are_marked_in_the_event() {
+        let context = init().unwrap();
+        let mut window = context.build_buffer_window(100, 100).create().unwrap();
+        let repeats = Rc::new(RefCell::new(Vec::new()));
+        window.set_key_down_callback(box Repeats(repeats.clone()));
+        window.set_key_repeat_filter(RepeatMark);
+        window.feed_key_down("a", "a", "a", TimeStamp(0));
+        window.feed_key_down("a", "a", "a", TimeStamp(1));
+        window.feed_key_up("a", "a", "a", TimeStamp(2));
+        window.feed_key_down("a", "a", "a", TimeStamp(3));
+        assert_eq!(*repeats.borrow(), vec![false, true, false]);
+    }
+
+    #[test]
+    fn gl_config_is_freed() {
+        let _context = init().unwrap();
+        let mut settings = GlSettings::new();
+        settings.color_format = Rgba8888;
+        settings.depth = Some(DepthBits24);
+        settings.stencil = Some(StencilBits8);
+        settings.options = GlOptionsDirect;
+        settings.multisample = Some(MultisampleHigh);
+        drop(GlConfig::from_settings(&settings));
+    }
+
+    #[test]
+    fn window_with_gl_settings_is_freed() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100)
+                            .with_color_format(Rgba8888)
+                            .with_gl_depth(Some(DepthBits24))
+                            .with_gl_stencil(Some(StencilBits8))
+                            .with_gl_multisample(Some(MultisampleHigh))
+                            .create().unwrap();
+        // The buffer engine doesn't use OpenGL
+        assert!(window.gl_config.is_none());
+        drop(window);
+    }
+
+    #[test]
+    fn window_with_callbacks_is_freed() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100)
+                            .with_gl_depth(Some(DepthBits24))
+                            .create().unwrap();
+        let pre_free = Rc::new(Cell::new(0u));
+        let destroy = Rc::new(Cell::new(0u));
+        let resize = Rc::new(Cell::new(0u));
+        window.set_pre_free_callback(box Counter(pre_free.clone()));
+        window.set_destroy_callback(box Counter(destroy.clone()));
+        window.set_resize_callback(box Counter(resize.clone()));
+        window.set_max_fps(Some(60.0));
+        window.set_idle_render(true);
+        drop(window);
+        assert_eq!(pre_free.get(), 1);
+        assert_eq!(destroy.get(), 1);
+        assert_eq!(resize.get(), 0);
+    }
+
+    #[test]
+    fn state_changes_are_diffed() {
+        let old = WindowState::new();
+        let new = WindowState { iconified: true, sticky: true, ..old.clone() };
+        let changed = StateChanged::diff(&old, &new);
+        assert_eq!(changed, StateChanged { iconified: Some(true), maximized: None, fullscreen: None, sticky: Some(true) });
+        assert!(!changed.is_empty());
+        assert_eq!(StateChanged::diff(&new, &old).iconified, Some(false));
+        assert!(StateChanged::diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn display_comes_after_extra_options() {
+        assert_eq!(engine_options(None, None), None);
+        assert_eq!(engine_options(Some("parent=1;".to_string()), None), Some("parent=1;".to_string()));
+        assert_eq!(engine_options(None, Some(":1")), Some("display=:1;".to_string()));
+        assert_eq!(engine_options(Some("display=:0;parent=1".to_string()), Some(":1")),
+                   Some("display=:0;parent=1;display=:1;".to_string()));
+        assert_eq!(engine_options(Some("vsync=1;".to_string()), Some(":1")),
+                   Some("vsync=1;display=:1;".to_string()));
+    }
+
+    #[test]
+    fn display_cannot_pass_options() {
+        let context = init().unwrap();
+        match context.build_buffer_window(100, 100).with_display(":0;parent=1").create() {
+            Err(InvalidDisplay(display)) => assert_eq!(display, ":0;parent=1".to_string()),
+            other => fail!("expected InvalidDisplay, got {}", other),
+        }
+    }
+}
@@ -0,0 +1,1143 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Safe wrappers around Evas canvas objects.
+
+use libc;
+use std::kinds::marker;
+use std::mem;
+use std::ptr;
+
+use ffi;
+use {Color, EventPropagate, EventResponse, ExtnCallback, InputEvent, InputKeyDown, InputKeyUp, KeyDown, KeyUp};
+use {Rect, Window};
+
+/// An object on a window's canvas. The object is deleted when this is
+/// dropped, and cannot outlive the window it was created on.
+///
+/// Objects returned by queries on the canvas, such as `get_clip`, are
+/// borrowed instead: they refer to an object owned by someone else, and are
+/// not deleted when dropped. A borrowed object holds a reference to the
+/// object, so it stays valid even if its owner deletes it meanwhile; Evas
+/// finishes deleting the object once the last reference is dropped.
+pub struct EvasObject<'a> {
+    ptr: *mut ffi::Evas_Object,
+    /// Whether the object is deleted when this is dropped
+    owned: bool,
+    marker: marker::ContravariantLifetime<'a>,
+}
+
+impl<'a> EvasObject<'a> {
+    fn new(ptr: *mut ffi::Evas_Object) -> EvasObject<'a> {
+        assert!(!ptr.is_null());
+        EvasObject { ptr: ptr, owned: true, marker: marker::ContravariantLifetime }
+    }
+
+    /// A handle to an object owned by someone else, if there is one, which
+    /// holds a reference that keeps the object alive until the handle is
+    /// dropped, but never deletes it.
+    fn borrowed(ptr: *mut ffi::Evas_Object) -> Option<EvasObject<'a>> {
+        if ptr.is_null() { return None }
+        unsafe { ffi::evas_object_ref(ptr) };
+        Some(EvasObject { ptr: ptr, owned: false, marker: marker::ContravariantLifetime })
+    }
+
+    /// Whether this refers to the same object as `other`.
+    pub fn is_same(&self, other: &EvasObject) -> bool {
+        self.ptr == other.ptr
+    }
+
+    pub fn move_to(&self, x: i32, y: i32) {
+        unsafe { ffi::evas_object_move(self.ptr, x as ffi::Evas_Coord, y as ffi::Evas_Coord) };
+    }
+
+    pub fn resize(&self, w: i32, h: i32) {
+        unsafe { ffi::evas_object_resize(self.ptr, w as ffi::Evas_Coord, h as ffi::Evas_Coord) };
+    }
+
+    pub fn set_geometry(&self, rect: Rect) {
+        self.move_to(rect.x, rect.y);
+        self.resize(rect.w, rect.h);
+    }
+
+    pub fn get_geometry(&self) -> Rect {
+        let (mut x, mut y, mut w, mut h) = (0, 0, 0, 0);
+        unsafe { ffi::evas_object_geometry_get(self.ptr as *const _, &mut x, &mut y, &mut w, &mut h) };
+        Rect::new(x, y, w, h)
+    }
+
+    pub fn show(&self) {
+        unsafe { ffi::evas_object_show(self.ptr) };
+    }
+
+    pub fn hide(&self) {
+        unsafe { ffi::evas_object_hide(self.ptr) };
+    }
+
+    pub fn is_visible(&self) -> bool {
+        unsafe { ffi::from_eina_bool(ffi::evas_object_visible_get(self.ptr as *const _)) }
+    }
+
+    /// Whether the object ignores pointer events, letting them pass through
+    /// to the objects below it as if it weren't there. Off by default.
+    pub fn set_pass_events(&self, on: bool) {
+        unsafe { ffi::evas_object_pass_events_set(self.ptr, ffi::to_eina_bool(on)) };
+    }
+
+    pub fn get_pass_events(&self) -> bool {
+        unsafe { ffi::from_eina_bool(ffi::evas_object_pass_events_get(self.ptr as *const _)) }
+    }
+
+    /// Whether pointer events the object receives are also delivered to the
+    /// object below it. Off by default.
+    pub fn set_repeat_events(&self, on: bool) {
+        unsafe { ffi::evas_object_repeat_events_set(self.ptr, ffi::to_eina_bool(on)) };
+    }
+
+    pub fn get_repeat_events(&self) -> bool {
+        unsafe { ffi::from_eina_bool(ffi::evas_object_repeat_events_get(self.ptr as *const _)) }
+    }
+
+    /// Whether events the object receives are also delivered to its smart
+    /// parent, if it has one. On by default.
+    pub fn set_propagate_events(&self, on: bool) {
+        unsafe { ffi::evas_object_propagate_events_set(self.ptr, ffi::to_eina_bool(on)) };
+    }
+
+    pub fn get_propagate_events(&self) -> bool {
+        unsafe { ffi::from_eina_bool(ffi::evas_object_propagate_events_get(self.ptr as *const _)) }
+    }
+
+    /// Set the color the object is multiplied by. Evas expects this to be
+    /// premultiplied by alpha (see `Color::premultiply`).
+    pub fn set_color(&self, color: Color) {
+        unsafe {
+            ffi::evas_object_color_set(self.ptr, color.r as libc::c_int, color.g as libc::c_int,
+                                       color.b as libc::c_int, color.a as libc::c_int)
+        };
+    }
+
+    pub fn get_color(&self) -> Color {
+        let (mut r, mut g, mut b, mut a) = (0, 0, 0, 0);
+        unsafe { ffi::evas_object_color_get(self.ptr as *const _, &mut r, &mut g, &mut b, &mut a) };
+        Color::new(r as u8, g as u8, b as u8, a as u8)
+    }
+
+    /// Whether the edges of the object are smoothed. This applies to lines
+    /// and polygons, and is on by default.
+    pub fn set_anti_alias(&self, on: bool) {
+        unsafe { ffi::evas_object_anti_alias_set(self.ptr, ffi::to_eina_bool(on)) };
+    }
+
+    pub fn get_anti_alias(&self) -> bool {
+        unsafe { ffi::from_eina_bool(ffi::evas_object_anti_alias_get(self.ptr as *const _)) }
+    }
+
+    /// Objects on higher layers are always stacked above objects on lower
+    /// layers.
+    pub fn set_layer(&self, layer: i16) {
+        unsafe { ffi::evas_object_layer_set(self.ptr, layer as libc::c_short) };
+    }
+
+    pub fn get_layer(&self) -> i16 {
+        unsafe { ffi::evas_object_layer_get(self.ptr as *const _) as i16 }
+    }
+
+    /// Stack the object above all other objects on its layer.
+    pub fn raise(&self) {
+        unsafe { ffi::evas_object_raise(self.ptr) };
+    }
+
+    /// Stack the object below all other objects on its layer.
+    pub fn lower(&self) {
+        unsafe { ffi::evas_object_lower(self.ptr) };
+    }
+
+    /// Stack the object immediately above `other`. Objects on different
+    /// layers can't be restacked relative to each other, so this does
+    /// nothing unless both are on the same layer.
+    pub fn stack_above(&self, other: &EvasObject<'a>) {
+        unsafe { ffi::evas_object_stack_above(self.ptr, other.ptr) };
+    }
+
+    /// Stack the object immediately below `other`, which must be on the same
+    /// layer.
+    pub fn stack_below(&self, other: &EvasObject<'a>) {
+        unsafe { ffi::evas_object_stack_below(self.ptr, other.ptr) };
+    }
+
+    /// The object stacked immediately above this one, if any. This is
+    /// borrowed (see `EvasObject::borrowed`).
+    pub fn above(&self) -> Option<EvasObject<'a>> {
+        EvasObject::borrowed(unsafe { ffi::evas_object_above_get(self.ptr as *const _) })
+    }
+
+    /// The object stacked immediately below this one, if any. This is
+    /// borrowed (see `EvasObject::borrowed`).
+    pub fn below(&self) -> Option<EvasObject<'a>> {
+        EvasObject::borrowed(unsafe { ffi::evas_object_below_get(self.ptr as *const _) })
+    }
+
+    /// Draw the object transformed by `map`. The map is copied, so it can be
+    /// changed or dropped afterwards without affecting the object.
+    pub fn set_map(&self, map: &EvasMap) {
+        unsafe {
+            ffi::evas_object_map_set(self.ptr, map.ptr as *const _);
+            ffi::evas_object_map_enable_set(self.ptr, ffi::EINA_TRUE);
+        }
+    }
+
+    /// Only draw the parts of the object that lie within `clipper`, which is
+    /// usually a rectangle. The object is also multiplied by the color of
+    /// the clipper, and hidden while the clipper is hidden. If the clipper
+    /// is deleted first, the object is simply unclipped.
+    pub fn set_clip(&self, clipper: &EvasObject<'a>) {
+        unsafe { ffi::evas_object_clip_set(self.ptr, clipper.ptr) };
+    }
+
+    pub fn unset_clip(&self) {
+        unsafe { ffi::evas_object_clip_unset(self.ptr) };
+    }
+
+    /// The object this object is clipped to, if any. This is borrowed (see
+    /// `EvasObject::borrowed`).
+    pub fn get_clip(&self) -> Option<EvasObject<'a>> {
+        EvasObject::borrowed(unsafe { ffi::evas_object_clip_get(self.ptr as *const _) })
+    }
+
+    /// Draw the object untransformed again.
+    pub fn unset_map(&self) {
+        unsafe {
+            ffi::evas_object_map_enable_set(self.ptr, ffi::EINA_FALSE);
+            ffi::evas_object_map_set(self.ptr, ptr::null());
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for EvasObject<'a> {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe { ffi::evas_object_del(self.ptr) };
+        } else {
+            unsafe { ffi::evas_object_unref(self.ptr) };
+        }
+    }
+}
+
+/// A transform of the four corners of an object, for rotating it in 2D or
+/// 3D. A map starts out matching the geometry of the object it was created
+/// from, and each transform is applied on top of the previous ones.
+pub struct EvasMap {
+    ptr: *mut ffi::Evas_Map,
+}
+
+impl EvasMap {
+    /// Create a map of the current geometry of `object`, at depth 0.
+    pub fn from_object(object: &EvasObject) -> EvasMap {
+        unsafe {
+            let ptr = ffi::evas_map_new(4);
+            assert!(!ptr.is_null());
+            ffi::evas_map_util_points_populate_from_object(ptr, object.ptr as *const _);
+            EvasMap { ptr: ptr }
+        }
+    }
+
+    /// Rotate clockwise by `degrees` around the point `(cx, cy)`.
+    pub fn rotate(self, degrees: f64, cx: i32, cy: i32) -> EvasMap {
+        unsafe { ffi::evas_map_util_rotate(self.ptr, degrees, cx as ffi::Evas_Coord, cy as ffi::Evas_Coord) };
+        self
+    }
+
+    /// Rotate by the given angles in degrees around each axis, in the order
+    /// z, y, x, around the point `(cx, cy, cz)`.
+    pub fn rotate_3d(self, dx: f64, dy: f64, dz: f64, cx: i32, cy: i32, cz: i32) -> EvasMap {
+        unsafe {
+            ffi::evas_map_util_3d_rotate(self.ptr, dx, dy, dz, cx as ffi::Evas_Coord,
+                                         cy as ffi::Evas_Coord, cz as ffi::Evas_Coord)
+        };
+        self
+    }
+
+    /// Scale by `zoom_x` and `zoom_y` around the point `(cx, cy)`.
+    pub fn zoom(self, zoom_x: f64, zoom_y: f64, cx: i32, cy: i32) -> EvasMap {
+        unsafe { ffi::evas_map_util_zoom(self.ptr, zoom_x, zoom_y, cx as ffi::Evas_Coord, cy as ffi::Evas_Coord) };
+        self
+    }
+
+    /// Project the map onto the canvas as seen from the point `(px, py)`.
+    /// `z0` is the depth drawn at its original size, and `focal` the
+    /// distance from the viewer to that depth. This should be applied after
+    /// any 3D rotation.
+    pub fn perspective(self, px: i32, py: i32, z0: i32, focal: i32) -> EvasMap {
+        unsafe {
+            ffi::evas_map_util_3d_perspective(self.ptr, px as ffi::Evas_Coord, py as ffi::Evas_Coord,
+                                              z0 as ffi::Evas_Coord, focal as ffi::Evas_Coord)
+        };
+        self
+    }
+
+    /// Whether the map's points would be drawn clockwise, meaning the front
+    /// of the object faces the viewer.
+    pub fn is_clockwise(&self) -> bool {
+        unsafe { ffi::from_eina_bool(ffi::evas_map_util_clockwise_get(self.ptr)) }
+    }
+}
+
+impl Drop for EvasMap {
+    fn drop(&mut self) {
+        unsafe { ffi::evas_map_free(self.ptr) };
+    }
+}
+
+/// Generates a wrapper for a specific kind of object, which dereferences to
+/// the generic `EvasObject`
+macro_rules! object_wrapper {
+    ($(#[$attr:meta])* struct $Object:ident) => {
+        $(#[$attr])*
+        pub struct $Object<'a> {
+            object: EvasObject<'a>,
+        }
+
+        impl<'a> Deref<EvasObject<'a>> for $Object<'a> {
+            fn deref<'b>(&'b self) -> &'b EvasObject<'a> {
+                &self.object
+            }
+        }
+    }
+}
+
+object_wrapper! {
+    #[doc = "A filled rectangle."]
+    struct EvasRectangle
+}
+
+object_wrapper! {
+    #[doc = "A straight line between two points."]
+    struct EvasLine
+}
+
+impl<'a> EvasLine<'a> {
+    /// Set the end points of the line, in canvas coordinates.
+    pub fn set_xy(&self, x1: i32, y1: i32, x2: i32, y2: i32) {
+        unsafe {
+            ffi::evas_object_line_xy_set(self.object.ptr, x1 as ffi::Evas_Coord, y1 as ffi::Evas_Coord,
+                                         x2 as ffi::Evas_Coord, y2 as ffi::Evas_Coord)
+        };
+    }
+
+    pub fn get_xy(&self) -> (i32, i32, i32, i32) {
+        let (mut x1, mut y1, mut x2, mut y2) = (0, 0, 0, 0);
+        unsafe { ffi::evas_object_line_xy_get(self.object.ptr as *const _, &mut x1, &mut y1, &mut x2, &mut y2) };
+        (x1 as i32, y1 as i32, x2 as i32, y2 as i32)
+    }
+}
+
+object_wrapper! {
+    #[doc = "A filled polygon."]
+    struct EvasPolygon
+}
+
+impl<'a> EvasPolygon<'a> {
+    /// Add a vertex to the polygon, in canvas coordinates.
+    pub fn point_add(&self, x: i32, y: i32) {
+        unsafe { ffi::evas_object_polygon_point_add(self.object.ptr, x as ffi::Evas_Coord, y as ffi::Evas_Coord) };
+    }
+
+    pub fn points_clear(&self) {
+        unsafe { ffi::evas_object_polygon_points_clear(self.object.ptr) };
+    }
+}
+
+object_wrapper! {
+    #[doc = "A single line of text."]
+    struct EvasText
+}
+
+impl<'a> EvasText<'a> {
+    pub fn set_text(&self, text: &str) {
+        unsafe { text.with_c_str(|text| ffi::evas_object_text_text_set(self.object.ptr, text)) };
+    }
+
+    pub fn get_text(&self) -> String {
+        unsafe {
+            ffi::string_from_c_ptr(ffi::evas_object_text_text_get(self.object.ptr as *const _)).unwrap_or(String::new())
+        }
+    }
+
+    /// Set the font by name and size. Returns an error if the font could not
+    /// be loaded, in which case no text will be drawn.
+    pub fn set_font(&self, name: &str, size: i32) -> Result<(), ()> {
+        unsafe { name.with_c_str(|name| ffi::evas_object_text_font_set(self.object.ptr, name, size as libc::c_int)) };
+        // Evas has no direct way of reporting a failed load, but a text
+        // object without a font has no vertical metrics.
+        let (ascent, descent) = unsafe {
+            (ffi::evas_object_text_ascent_get(self.object.ptr as *const _),
+             ffi::evas_object_text_descent_get(self.object.ptr as *const _))
+        };
+        if ascent == 0 && descent == 0 { Err(()) } else { Ok(()) }
+    }
+
+    /// Set a font file (or `eet` archive) to look fonts up in before the
+    /// system font paths. This must be called before `set_font` to take
+    /// effect.
+    pub fn set_font_source(&self, source: &str) {
+        unsafe { source.with_c_str(|source| ffi::evas_object_text_font_source_set(self.object.ptr, source)) };
+    }
+}
+
+/// An image drawn from pixel data supplied by the application, or loaded
+/// from a file.
+pub struct EvasImage<'a> {
+    object: EvasObject<'a>,
+    /// The preloaded callback, pointed to by the Evas callback registration
+    preloaded_callback: Option<Box<Box<ImagePreloadedCallback>>>,
+}
+
+impl<'a> Deref<EvasObject<'a>> for EvasImage<'a> {
+    fn deref<'b>(&'b self) -> &'b EvasObject<'a> {
+        &self.object
+    }
+}
+
+/// Called when an image has finished loading in the background
+pub trait ImagePreloadedCallback {
+    fn call(&self);
+}
+
+/// A rotation or flip applied to an image when it is drawn, covering the
+/// eight EXIF orientations. Rotations are clockwise.
+#[repr(u32)]
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum ImageOrient {
+    OrientNone = ffi::EVAS_IMAGE_ORIENT_NONE,
+    Orient90 = ffi::EVAS_IMAGE_ORIENT_90,
+    Orient180 = ffi::EVAS_IMAGE_ORIENT_180,
+    Orient270 = ffi::EVAS_IMAGE_ORIENT_270,
+    /// Mirrored left to right.
+    OrientFlipHorizontal = ffi::EVAS_IMAGE_FLIP_HORIZONTAL,
+    /// Mirrored top to bottom.
+    OrientFlipVertical = ffi::EVAS_IMAGE_FLIP_VERTICAL,
+    /// Mirrored along the diagonal from the top left corner.
+    OrientTranspose = ffi::EVAS_IMAGE_FLIP_TRANSPOSE,
+    /// Mirrored along the diagonal from the top right corner.
+    OrientTransverse = ffi::EVAS_IMAGE_FLIP_TRANSVERSE,
+}
+
+impl ImageOrient {
+    /// The orientation that displays an image with the given value of the
+    /// EXIF `Orientation` tag upright, or `None` if the value is invalid.
+    pub fn from_exif(orientation: u16) -> Option<ImageOrient> {
+        match orientation {
+            1 => Some(OrientNone),
+            2 => Some(OrientFlipHorizontal),
+            3 => Some(Orient180),
+            4 => Some(OrientFlipVertical),
+            5 => Some(OrientTranspose),
+            6 => Some(Orient90),
+            7 => Some(OrientTransverse),
+            8 => Some(Orient270),
+            _ => None,
+        }
+    }
+
+    fn from_evas(orient: ffi::Evas_Image_Orient) -> ImageOrient {
+        match orient {
+            ffi::EVAS_IMAGE_ORIENT_90 => Orient90,
+            ffi::EVAS_IMAGE_ORIENT_180 => Orient180,
+            ffi::EVAS_IMAGE_ORIENT_270 => Orient270,
+            ffi::EVAS_IMAGE_FLIP_HORIZONTAL => OrientFlipHorizontal,
+            ffi::EVAS_IMAGE_FLIP_VERTICAL => OrientFlipVertical,
+            ffi::EVAS_IMAGE_FLIP_TRANSPOSE => OrientTranspose,
+            ffi::EVAS_IMAGE_FLIP_TRANSVERSE => OrientTransverse,
+            _ => OrientNone,
+        }
+    }
+}
+
+impl<'a> EvasImage<'a> {
+    /// Load the image from a file, or from the entry `key` of an `eet`
+    /// archive. Only the header is read at this point; the pixels are
+    /// decoded when the image is first drawn, or in the background with
+    /// `preload`. Fails if the file can't be opened or its format is not
+    /// supported.
+    pub fn set_file(&self, path: &Path, key: Option<&str>) -> Result<(), ()> {
+        unsafe {
+            path.with_c_str(|path| match key {
+                Some(key) => key.with_c_str(|key| ffi::evas_object_image_file_set(self.object.ptr, path, key)),
+                None => ffi::evas_object_image_file_set(self.object.ptr, path, ptr::null()),
+            });
+            if ffi::evas_object_image_load_error_get(self.object.ptr as *const _) == ffi::EVAS_LOAD_ERROR_NONE {
+                Ok(())
+            } else {
+                Err(())
+            }
+        }
+    }
+
+    /// Start decoding the image's file in a background thread, so that
+    /// drawing it doesn't block, or cancel a preload in progress if `cancel`
+    /// is true. The preloaded callback is run once decoding has finished.
+    pub fn preload(&self, cancel: bool) {
+        unsafe { ffi::evas_object_image_preload(self.object.ptr, ffi::to_eina_bool(cancel)) };
+    }
+
+    /// Set the callback run when a preload started with `preload` finishes.
+    pub fn set_preloaded_callback(&mut self, callback: Box<ImagePreloadedCallback>)
+                                  -> Option<Box<ImagePreloadedCallback>> {
+        let old = self.unset_preloaded_callback();
+        let callback = box callback;
+        unsafe {
+            ffi::evas_object_event_callback_add(
+                self.object.ptr, ffi::EVAS_CALLBACK_IMAGE_PRELOADED, Some(image_preloaded_callback),
+                &*callback as *const Box<ImagePreloadedCallback> as *const _,
+            );
+        }
+        self.preloaded_callback = Some(callback);
+        old
+    }
+
+    pub fn unset_preloaded_callback(&mut self) -> Option<Box<ImagePreloadedCallback>> {
+        self.preloaded_callback.take().map(|callback| {
+            unsafe {
+                ffi::evas_object_event_callback_del_full(
+                    self.object.ptr, ffi::EVAS_CALLBACK_IMAGE_PRELOADED, Some(image_preloaded_callback),
+                    &*callback as *const Box<ImagePreloadedCallback> as *const _,
+                );
+            }
+            *callback
+        })
+    }
+
+    /// Set the size of the image's pixel buffer. This discards the current
+    /// contents.
+    pub fn set_image_size(&self, w: i32, h: i32) {
+        unsafe { ffi::evas_object_image_size_set(self.object.ptr, w as libc::c_int, h as libc::c_int) };
+    }
+
+    pub fn get_image_size(&self) -> (i32, i32) {
+        let (mut w, mut h) = (0, 0);
+        unsafe { ffi::evas_object_image_size_get(self.object.ptr as *const _, &mut w, &mut h) };
+        (w as i32, h as i32)
+    }
+
+    /// Set the region of the object that one copy of the image is drawn to,
+    /// relative to the object. The image is tiled to cover the rest of the
+    /// object. By default the image is stretched to fill the whole object.
+    pub fn set_fill(&self, rect: Rect) {
+        unsafe {
+            ffi::evas_object_image_filled_set(self.object.ptr, ffi::EINA_FALSE);
+            ffi::evas_object_image_fill_set(self.object.ptr, rect.x, rect.y, rect.w, rect.h);
+        }
+    }
+
+    pub fn set_alpha(&self, on: bool) {
+        unsafe { ffi::evas_object_image_alpha_set(self.object.ptr, ffi::to_eina_bool(on)) };
+    }
+
+    /// Rotate or flip the image when it is drawn, without changing its
+    /// pixels. At 90 and 270 degrees the image size is swapped.
+    pub fn set_orient(&self, orient: ImageOrient) {
+        unsafe { ffi::evas_object_image_orient_set(self.object.ptr, orient as ffi::Evas_Image_Orient) };
+    }
+
+    pub fn get_orient(&self) -> ImageOrient {
+        ImageOrient::from_evas(unsafe { ffi::evas_object_image_orient_get(self.object.ptr as *const _) })
+    }
+
+    /// Whether the image's file has more than one frame, such as an
+    /// animated GIF. Evas does not advance the frames itself.
+    pub fn is_animated(&self) -> bool {
+        unsafe { ffi::from_eina_bool(ffi::evas_object_image_animated_get(self.object.ptr as *const _)) }
+    }
+
+    /// The number of frames in an animated image, or `None` if the image is
+    /// not animated.
+    pub fn frame_count(&self) -> Option<uint> {
+        if !self.is_animated() { return None }
+        Some(unsafe { ffi::evas_object_image_animated_frame_count_get(self.object.ptr as *const _) } as uint)
+    }
+
+    /// How long to show `span` frames starting at `frame`, in seconds.
+    /// Frames are numbered from 1. Returns `None` if the image is not
+    /// animated or the frames are out of range.
+    pub fn frame_duration(&self, frame: uint, span: uint) -> Option<f64> {
+        match self.frame_count() {
+            Some(count) if frame >= 1 && span >= 1 && frame + span - 1 <= count => {},
+            _ => return None,
+        }
+        Some(unsafe {
+            ffi::evas_object_image_animated_frame_duration_get(self.object.ptr as *const _, frame as libc::c_int,
+                                                               span as libc::c_int) as f64
+        })
+    }
+
+    /// Show the given frame of an animated image. Frames are numbered from
+    /// 1. Fails if the image is not animated or the frame is out of range.
+    pub fn set_frame(&self, frame: uint) -> Result<(), ()> {
+        match self.frame_count() {
+            Some(count) if frame >= 1 && frame <= count => {},
+            _ => return Err(()),
+        }
+        unsafe { ffi::evas_object_image_animated_frame_set(self.object.ptr, frame as libc::c_int) };
+        Ok(())
+    }
+
+    /// Copy premultiplied ARGB32 pixels into the image and mark all of it as
+    /// changed. The data is in row-major order, with each pixel stored as a
+    /// native-endian `u32`. Fails if the length of the data does not match
+    /// the image size.
+    pub fn set_data(&self, data: &[u8]) -> Result<(), ()> {
+        let (w, h) = self.get_image_size();
+        if data.len() != (w * h * 4) as uint { return Err(()) }
+        unsafe {
+            ffi::evas_object_image_data_copy_set(self.object.ptr, data.as_ptr() as *mut libc::c_void);
+            ffi::evas_object_image_data_update_add(self.object.ptr, 0, 0, w as libc::c_int, h as libc::c_int);
+        }
+        Ok(())
+    }
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for EvasImage<'a> {
+    fn drop(&mut self) {
+        self.unset_preloaded_callback();
+    }
+}
+
+extern "C" fn image_preloaded_callback(
+    data: *mut libc::c_void,
+    _e: *mut ffi::Evas,
+    _obj: *mut ffi::Evas_Object,
+    _event_info: *mut libc::c_void,
+) {
+    ::guard_callback("image_preloaded_callback", (), || {
+        let callback = data as *const Box<ImagePreloadedCallback>;
+        unsafe { (*callback).call() };
+    })
+}
+
+/// An image showing the canvas of a socket in another process. The plug is
+/// disconnected when this is dropped.
+pub struct EvasPlug<'a> {
+    object: EvasObject<'a>,
+    /// The disconnect callback, pointed to by the plug's ecore_evas
+    disconnect_callback: Option<Box<PlugDisconnect>>,
+}
+
+struct PlugDisconnect {
+    callback: Box<ExtnCallback>,
+    /// The window the plug is on
+    parent: *const ffi::Ecore_Evas,
+}
+
+impl<'a> Deref<EvasObject<'a>> for EvasPlug<'a> {
+    fn deref<'b>(&'b self) -> &'b EvasObject<'a> {
+        &self.object
+    }
+}
+
+impl<'a> EvasPlug<'a> {
+    fn plug_ee(&self) -> *mut ffi::Ecore_Evas {
+        unsafe { ffi::ecore_evas_object_ecore_evas_get(self.object.ptr) }
+    }
+
+    fn disconnect_key() -> &'static str { "PLUG_DISCONNECT" }
+
+    /// Set the callback run when the socket the plug is connected to goes
+    /// away, such as when the process rendering into it exits.
+    pub fn set_disconnect_callback(&mut self, callback: Box<ExtnCallback>) -> Option<Box<ExtnCallback>> {
+        let parent = unsafe { ffi::ecore_evas_ecore_evas_get(ffi::evas_object_evas_get(self.object.ptr as *const _)) };
+        let disconnect = box PlugDisconnect { callback: callback, parent: parent as *const _ };
+        let ee = self.plug_ee();
+        unsafe {
+            EvasPlug::disconnect_key().with_c_str(|key| {
+                ffi::ecore_evas_data_set(ee, key, &*disconnect as *const PlugDisconnect as *const _)
+            });
+            ffi::ecore_evas_callback_delete_request_set(ee, Some(plug_disconnect_callback));
+        }
+        mem::replace(&mut self.disconnect_callback, Some(disconnect)).map(|old| old.callback)
+    }
+
+    pub fn unset_disconnect_callback(&mut self) -> Option<Box<ExtnCallback>> {
+        let ee = self.plug_ee();
+        unsafe {
+            ffi::ecore_evas_callback_delete_request_set(ee, None);
+            EvasPlug::disconnect_key().with_c_str(|key| ffi::ecore_evas_data_set(ee, key, 0 as *const _));
+        }
+        self.disconnect_callback.take().map(|old| old.callback)
+    }
+}
+
+#[unsafe_destructor]
+impl<'a> Drop for EvasPlug<'a> {
+    fn drop(&mut self) {
+        self.unset_disconnect_callback();
+    }
+}
+
+extern "C" fn plug_disconnect_callback(ee: *mut ffi::Ecore_Evas) {
+    ::guard_callback("plug_disconnect_callback", (), || {
+        unsafe {
+            let disconnect = EvasPlug::disconnect_key().with_c_str(|key| {
+                ffi::ecore_evas_data_get(ee as *const _, key)
+            }) as *const PlugDisconnect;
+            if disconnect.is_null() { return }
+            for window in ::bound_window((*disconnect).parent).iter() {
+                (*disconnect).callback.call(window);
+            }
+        }
+    })
+}
+
+impl<'a> Window<'a> {
+    /// Create a plug on the window's canvas and connect it to the socket
+    /// with the given service name and number (see `Context::build_socket`).
+    /// Fails if no such socket is listening.
+    pub fn new_plug<'b>(&'b self, name: &str, number: i32, system: bool) -> Result<EvasPlug<'b>, ()> {
+        let ptr = unsafe { ffi::ecore_evas_extn_plug_new(self.ee) };
+        if ptr.is_null() { return Err(()) }
+        let plug = EvasPlug { object: EvasObject::new(ptr), disconnect_callback: None };
+        let connected = unsafe {
+            name.with_c_str(|name| {
+                ffi::ecore_evas_extn_plug_connect(ptr, name, number as libc::c_int, ffi::to_eina_bool(system))
+            })
+        };
+        if ffi::from_eina_bool(connected) { Ok(plug) } else { Err(()) }
+    }
+
+    /// The topmost visible object at a point on the canvas that accepts
+    /// events, if any. The window's own image counts as an object. This is
+    /// borrowed (see `EvasObject::borrowed`), so the canvas may have changed
+    /// by the time the handle is used.
+    pub fn object_at<'b>(&'b self, x: i32, y: i32) -> Option<EvasObject<'b>> {
+        EvasObject::borrowed(unsafe {
+            ffi::evas_object_top_at_xy_get(self.canvas as *const _, x as ffi::Evas_Coord, y as ffi::Evas_Coord,
+                                           ffi::EINA_FALSE, ffi::EINA_FALSE)
+        })
+    }
+
+    /// Every visible object that accepts events and overlaps `rect`, from
+    /// the bottom of the stack to the top. These are borrowed (see
+    /// `EvasObject::borrowed`).
+    pub fn objects_in<'b>(&'b self, rect: Rect) -> Vec<EvasObject<'b>> {
+        unsafe {
+            let list = ffi::evas_objects_in_rectangle_get(self.canvas as *const _, rect.x, rect.y, rect.w, rect.h,
+                                                          ffi::EINA_FALSE, ffi::EINA_FALSE);
+            let objects = ffi::eina_list_iter(list as *const _).filter_map(|data| {
+                EvasObject::borrowed(data as *mut _)
+            }).collect();
+            ffi::eina_list_free(list);
+            objects
+        }
+    }
+
+    pub fn new_rectangle<'b>(&'b self) -> EvasRectangle<'b> {
+        EvasRectangle { object: EvasObject::new(unsafe { ffi::evas_object_rectangle_add(self.canvas) }) }
+    }
+
+    pub fn new_line<'b>(&'b self) -> EvasLine<'b> {
+        EvasLine { object: EvasObject::new(unsafe { ffi::evas_object_line_add(self.canvas) }) }
+    }
+
+    pub fn new_polygon<'b>(&'b self) -> EvasPolygon<'b> {
+        EvasPolygon { object: EvasObject::new(unsafe { ffi::evas_object_polygon_add(self.canvas) }) }
+    }
+
+    pub fn new_text<'b>(&'b self) -> EvasText<'b> {
+        EvasText { object: EvasObject::new(unsafe { ffi::evas_object_text_add(self.canvas) }) }
+    }
+
+    pub fn new_image<'b>(&'b self) -> EvasImage<'b> {
+        EvasImage {
+            object: EvasObject::new(unsafe { ffi::evas_object_image_filled_add(self.canvas) }),
+            preloaded_callback: None,
+        }
+    }
+}
+
+// Keyboard focus
+//
+// Focus is kept by Evas, which delivers key events to the focused object,
+// with the window's own image holding focus whenever no other object does.
+// The window's key extern callbacks are added to whichever object has focus,
+// so the key callbacks, input method and key tracking keep seeing every key
+// event, and the events are routed to the focused object from there.
+
+/// Receives the key events of a window while one of its objects has focus,
+/// along with that object. An object handling the event stops it from
+/// reaching the window's key callbacks.
+pub trait FocusKeyCallback {
+    fn call(&self, &Window, &EvasObject, InputEvent) -> EventResponse;
+}
+
+/// The focus key callback associated with a window
+pub struct FocusState {
+    key_callback: Option<Box<FocusKeyCallback>>,
+}
+
+impl FocusState {
+    pub fn new() -> FocusState {
+        FocusState {
+            key_callback: None,
+        }
+    }
+}
+
+fn focusable_key() -> &'static str { "FOCUSABLE" }
+
+fn tab_order_key() -> &'static str { "TAB_ORDER" }
+
+unsafe fn object_data(ptr: *mut ffi::Evas_Object, key: &str) -> *mut libc::c_void {
+    key.with_c_str(|key| ffi::evas_object_data_get(ptr as *const _, key))
+}
+
+unsafe fn set_object_data(ptr: *mut ffi::Evas_Object, key: &str, data: *const libc::c_void) {
+    key.with_c_str(|key| ffi::evas_object_data_set(ptr, key, data));
+}
+
+impl<'a> EvasObject<'a> {
+    /// Allow the object to take focus with `Window::focus_next` and
+    /// `Window::focus_prev`. Only visible objects take focus.
+    pub fn set_focusable(&self, on: bool) {
+        unsafe { set_object_data(self.ptr, focusable_key(), on as uint as *const _) };
+    }
+
+    pub fn is_focusable(&self) -> bool {
+        unsafe { !object_data(self.ptr, focusable_key()).is_null() }
+    }
+
+    /// Set the position of the object in the tab ring. Focus moves through
+    /// objects in increasing tab order, and through objects with the same
+    /// tab order from the bottom of the stack to the top. Defaults to 0.
+    pub fn set_tab_order(&self, order: i32) {
+        unsafe { set_object_data(self.ptr, tab_order_key(), order as int as *const _) };
+    }
+
+    pub fn get_tab_order(&self) -> i32 {
+        unsafe { object_data(self.ptr, tab_order_key()) as int as i32 }
+    }
+}
+
+/// The visible, focusable objects on the canvas, in tab order
+fn tab_ring(window: &Window) -> Vec<*mut ffi::Evas_Object> {
+    let mut ring = Vec::new();
+    unsafe {
+        // This walks every layer, from the bottom of the stack to the top
+        let mut object = ffi::evas_object_bottom_get(window.canvas as *const _);
+        while !object.is_null() {
+            if ffi::from_eina_bool(ffi::evas_object_visible_get(object as *const _))
+                && !object_data(object, focusable_key()).is_null() {
+                ring.push(object);
+            }
+            object = ffi::evas_object_above_get(object as *const _);
+        }
+        // The sort is stable, so stacking order breaks ties
+        ring.sort_by(|&a, &b| {
+            (object_data(a, tab_order_key()) as int).cmp(&(object_data(b, tab_order_key()) as int))
+        });
+    }
+    ring
+}
+
+/// The callbacks the window adds to the focused object: its key extern
+/// callbacks, and one to take focus back if the object is deleted
+fn focus_callbacks() -> [(ffi::Evas_Callback_Type, ffi::Evas_Object_Event_Cb), ..3] {
+    [
+        (ffi::EVAS_CALLBACK_KEY_DOWN, Some(::key_down_callback)),
+        (ffi::EVAS_CALLBACK_KEY_UP, Some(::key_up_callback)),
+        (ffi::EVAS_CALLBACK_DEL, Some(focused_object_deleted)),
+    ]
+}
+
+/// Give Evas focus to `object`, or back to the window's image if it is null,
+/// moving the window's callbacks over from the object that had focus.
+fn set_focus(window: &Window, object: *mut ffi::Evas_Object) {
+    unsafe {
+        release_focus(window);
+        if object.is_null() {
+            ffi::evas_object_focus_set(window.object, ffi::EINA_TRUE);
+            return;
+        }
+        let data = window.data_ptr();
+        for &(callback_type, callback) in focus_callbacks().iter() {
+            ffi::evas_object_event_callback_add(object, callback_type, callback, data);
+        }
+        ffi::evas_object_focus_set(object, ffi::EINA_TRUE);
+    }
+}
+
+/// Remove the window's callbacks from the focused object. This is done
+/// before the window is freed, as deleting the object would otherwise call
+/// back into it.
+pub fn release_focus(window: &Window) {
+    unsafe {
+        let focused = ffi::evas_focus_get(window.canvas as *const _);
+        if focused.is_null() || focused == window.object {
+            return;
+        }
+        for &(callback_type, callback) in focus_callbacks().iter() {
+            ffi::evas_object_event_callback_del(focused, callback_type, callback);
+        }
+    }
+}
+
+/// Evas drops focus from a deleted object without giving it to another, so
+/// give it back to the window's image, which would otherwise stop receiving
+/// key events.
+extern "C" fn focused_object_deleted(data: *mut libc::c_void, _e: *mut ffi::Evas,
+                                     _obj: *mut ffi::Evas_Object, _event_info: *mut libc::c_void) {
+    ::guard_callback("focused_object_deleted", (), || {
+        let window = unsafe { &Window::from_data_ptr(data as *const _) };
+        if unsafe { ffi::evas_focus_get(window.canvas as *const _) }.is_null() {
+            unsafe { ffi::evas_object_focus_set(window.object, ffi::EINA_TRUE) };
+        }
+    })
+}
+
+/// The object with Evas focus, or null if the window's image has it. Focus
+/// returns to the window's image when the focused object is hidden or made
+/// unfocusable.
+fn focused(window: &Window) -> *mut ffi::Evas_Object {
+    let focused = unsafe { ffi::evas_focus_get(window.canvas as *const _) };
+    if focused.is_null() || focused == window.object {
+        return ptr::mut_null();
+    }
+    if !tab_ring(window).contains(&focused) {
+        set_focus(window, ptr::mut_null());
+        return ptr::mut_null();
+    }
+    focused
+}
+
+impl<'a> Window<'a> {
+    /// Move focus to the next object in the tab ring, wrapping around after
+    /// the last, and return it. Returns `None` if no object can take focus.
+    /// The object returned is borrowed (see `EvasObject::borrowed`).
+    pub fn focus_next<'b>(&'b self) -> Option<EvasObject<'b>> {
+        let ring = tab_ring(self);
+        if ring.is_empty() { return None }
+        let current = focused(self);
+        let next = match ring.iter().position(|&o| o == current) {
+            Some(i) => (i + 1) % ring.len(),
+            None => 0,
+        };
+        set_focus(self, *ring.get(next));
+        EvasObject::borrowed(*ring.get(next))
+    }
+
+    /// Move focus to the previous object in the tab ring, wrapping around
+    /// before the first, and return it. Returns `None` if no object can take
+    /// focus. The object returned is borrowed (see `EvasObject::borrowed`).
+    pub fn focus_prev<'b>(&'b self) -> Option<EvasObject<'b>> {
+        let ring = tab_ring(self);
+        if ring.is_empty() { return None }
+        let current = focused(self);
+        let prev = match ring.iter().position(|&o| o == current) {
+            Some(0) | None => ring.len() - 1,
+            Some(i) => i - 1,
+        };
+        set_focus(self, *ring.get(prev));
+        EvasObject::borrowed(*ring.get(prev))
+    }
+
+    /// Give focus to `object`. Fails if the object is hidden or not
+    /// focusable.
+    pub fn focus_object(&self, object: &EvasObject) -> Result<(), ()> {
+        if !tab_ring(self).contains(&object.ptr) { return Err(()) }
+        set_focus(self, object.ptr);
+        Ok(())
+    }
+
+    /// Take focus away from the focused object, so that key events only go
+    /// to the window's key callbacks.
+    pub fn clear_focus(&self) {
+        set_focus(self, ptr::mut_null());
+    }
+
+    /// The object with focus, if any. This is borrowed (see
+    /// `EvasObject::borrowed`).
+    pub fn focused_object<'b>(&'b self) -> Option<EvasObject<'b>> {
+        EvasObject::borrowed(focused(self))
+    }
+
+    /// Set the callback that receives key events while an object has focus.
+    /// It is called before the window's key callbacks.
+    pub fn set_focus_key_callback(&mut self, callback: Box<FocusKeyCallback>) -> Option<Box<FocusKeyCallback>> {
+        unsafe {
+            let data = self.data_ptr();
+            for &(callback_type, callback) in [
+                (ffi::EVAS_CALLBACK_KEY_DOWN, ::key_down_callback),
+                (ffi::EVAS_CALLBACK_KEY_UP, ::key_up_callback),
+            ].iter() {
+                ffi::evas_object_event_callback_del(self.object, callback_type, Some(callback));
+                ffi::evas_object_event_callback_add(self.object, callback_type, Some(callback), data);
+            }
+        }
+        mem::replace(&mut self.focus.key_callback, Some(callback))
+    }
+
+    /// Remove the focus key callback. The extern callbacks unregister
+    /// themselves once nothing needs them.
+    pub fn unset_focus_key_callback(&mut self) -> Option<Box<FocusKeyCallback>> {
+        self.focus.key_callback.take()
+    }
+}
+
+/// Whether input events of this type need to be routed to the focused object
+pub fn wants_focus_event(window: &Window, callback_type: ffi::Evas_Callback_Type) -> bool {
+    window.focus.key_callback.is_some() &&
+        (callback_type == ffi::EVAS_CALLBACK_KEY_DOWN || callback_type == ffi::EVAS_CALLBACK_KEY_UP)
+}
+
+/// Pass a key event to the focus key callback, if an object has focus.
+pub unsafe fn dispatch_focus_key(window: &Window, callback_type: ffi::Evas_Callback_Type,
+                       info: *const libc::c_void) -> EventResponse {
+    if !wants_focus_event(window, callback_type) {
+        return EventPropagate;
+    }
+    let object = match EvasObject::borrowed(focused(window)) {
+        Some(object) => object,
+        None => return EventPropagate,
+    };
+    let event = if callback_type == ffi::EVAS_CALLBACK_KEY_DOWN {
+        InputKeyDown(KeyDown { ptr: info as *const _ })
+    } else {
+        InputKeyUp(KeyUp { ptr: info as *const _ })
+    };
+    match window.focus.key_callback {
+        Some(ref callback) => callback.call(window, &object, event),
+        None => EventPropagate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libc;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use ffi;
+    use {init, EventHandled, EventResponse, InputEvent, Rect, TimeStamp, Window};
+    use super::{EvasObject, FocusKeyCallback, ImageOrient};
+
+    /// The accessors that return borrowed objects
+    #[deriving(Show)]
+    enum Accessor {
+        GetClip,
+        Above,
+        Below,
+        ObjectAt,
+        ObjectsIn,
+        FocusedObject,
+    }
+
+    extern "C" fn note_deleted(data: *mut libc::c_void, _e: *mut ffi::Evas, _obj: *mut ffi::Evas_Object,
+                               _event_info: *mut libc::c_void) {
+        let deleted = data as *const Cell<bool>;
+        unsafe { (*deleted).set(true) };
+    }
+
+    /// Borrow an object through `accessor` and drop its owner. The object has
+    /// to stay usable through the borrowed handle, and only be deleted once
+    /// that is dropped too.
+    fn check_borrowed_outlives_owner(accessor: Accessor) {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        let object = window.new_rectangle();
+        let other = window.new_rectangle();
+        object.set_geometry(Rect::new(10, 10, 20, 20));
+        object.show();
+        object.set_focusable(true);
+        let deleted = Cell::new(false);
+        unsafe {
+            ffi::evas_object_event_callback_add(object.ptr, ffi::EVAS_CALLBACK_DEL, Some(note_deleted),
+                                                &deleted as *const _ as *const _);
+        }
+        let borrowed = match accessor {
+            GetClip => { other.set_clip(&*object); other.get_clip() },
+            Above => { object.stack_above(&*other); other.above() },
+            Below => { object.stack_below(&*other); other.below() },
+            ObjectAt => window.object_at(15, 15),
+            ObjectsIn => window.objects_in(Rect::new(0, 0, 50, 50)).move_iter().find(|o| o.is_same(&*object)),
+            FocusedObject => { window.focus_next(); window.focused_object() },
+        };
+        let borrowed = borrowed.expect(format!("{} found nothing", accessor).as_slice());
+        assert!(borrowed.is_same(&*object), "{} found another object", accessor);
+        drop(object);
+        assert!(!deleted.get(), "{} didn't keep the object alive", accessor);
+        borrowed.set_layer(1);
+        drop(borrowed);
+        assert!(deleted.get(), "{} kept the object alive", accessor);
+    }
+
+    #[test]
+    fn borrowed_objects_outlive_their_owners() {
+        for &accessor in [GetClip, Above, Below, ObjectAt, ObjectsIn, FocusedObject].iter() {
+            check_borrowed_outlives_owner(accessor);
+        }
+    }
+
+    /// Counts the key events that reach the focused object
+    struct FocusKeys(Rc<Cell<uint>>);
+
+    impl FocusKeyCallback for FocusKeys {
+        fn call(&self, _: &Window, _: &EvasObject, _: InputEvent) -> EventResponse {
+            let FocusKeys(ref count) = *self;
+            count.set(count.get() + 1);
+            EventHandled
+        }
+    }
+
+    #[test]
+    fn focus_is_kept_by_evas() {
+        let context = init().unwrap();
+        let mut window = context.build_buffer_window(100, 100).create().unwrap();
+        let count = Rc::new(Cell::new(0u));
+        window.set_focus_key_callback(box FocusKeys(count.clone()));
+        let object = window.new_rectangle();
+        object.show();
+        object.set_focusable(true);
+        assert!(window.focus_next().unwrap().is_same(&*object));
+        assert_eq!(unsafe { ffi::evas_focus_get(window.canvas as *const _) }, object.ptr);
+        window.feed_key_down("a", "a", "a", TimeStamp(0));
+        assert_eq!(count.get(), 1);
+        // Deleting the object gives focus back to the window
+        drop(object);
+        assert_eq!(unsafe { ffi::evas_focus_get(window.canvas as *const _) }, window.object);
+        assert!(window.focused_object().is_none());
+        window.feed_key_down("a", "a", "a", TimeStamp(1));
+        assert_eq!(count.get(), 1);
+    }
+
+    #[test]
+    fn exif_orientations_round_trip() {
+        let mut orients: Vec<ImageOrient> = Vec::new();
+        for exif in range(1u16, 9) {
+            let orient = ImageOrient::from_exif(exif).unwrap();
+            assert_eq!(ImageOrient::from_evas(orient.clone() as ffi::Evas_Image_Orient), orient);
+            assert!(!orients.contains(&orient));
+            orients.push(orient);
+        }
+        assert_eq!(ImageOrient::from_exif(0), None);
+        assert_eq!(ImageOrient::from_exif(9), None);
+    }
+
+    #[test]
+    fn image_keeps_its_orientation() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        let image = window.new_image();
+        for exif in range(1u16, 9) {
+            let orient = ImageOrient::from_exif(exif).unwrap();
+            image.set_orient(orient.clone());
+            assert_eq!(image.get_orient(), orient);
+        }
+    }
+}
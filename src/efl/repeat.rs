@@ -0,0 +1,124 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Detection of the key down events generated by auto-repeat.
+
+use libc;
+use std::cell::RefCell;
+
+use ffi;
+use {EventFlagRepeat, KeyDown, Window};
+
+/// What to do with the key down events generated while a key is held
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum RepeatMode {
+    /// Deliver repeats like any other key down.
+    RepeatDeliver,
+    /// Drop repeats, so a held key is only reported once.
+    RepeatSuppress,
+    /// Deliver repeats, marked with `EventFlagRepeat` so that
+    /// `KeyDown::repeat` reports them.
+    RepeatMark,
+}
+
+/// The key repeat state associated with a window
+pub struct RepeatState {
+    mode: RepeatMode,
+    /// The keycodes of the keys currently held down
+    down: RefCell<Vec<libc::c_uint>>,
+}
+
+impl RepeatState {
+    pub fn new() -> RepeatState {
+        RepeatState {
+            mode: RepeatDeliver,
+            down: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+/// The input events that key repeats are detected from, with their extern
+/// callbacks
+fn key_callbacks() -> [(ffi::Evas_Callback_Type, ffi::Evas_Object_Event_Cb), ..2] {
+    [
+        (ffi::EVAS_CALLBACK_KEY_DOWN, Some(::key_down_callback)),
+        (ffi::EVAS_CALLBACK_KEY_UP, Some(::key_up_callback)),
+    ]
+}
+
+impl<'a> Window<'a> {
+    /// Set what happens to the key down events that auto-repeat generates
+    /// while a key is held. Repeats are detected by tracking which keys are
+    /// down, so a key down for a key that has not been released since its
+    /// last key down is a repeat. Defaults to `RepeatDeliver`.
+    pub fn set_key_repeat_filter(&mut self, mode: RepeatMode) {
+        if mode != RepeatDeliver {
+            unsafe {
+                let data = self.data_ptr();
+                for &(callback_type, callback) in key_callbacks().iter() {
+                    ffi::evas_object_event_callback_del(self.object, callback_type, callback);
+                    ffi::evas_object_event_callback_add(self.object, callback_type, callback, data);
+                }
+            }
+        } else {
+            // The extern callbacks unregister themselves once nothing needs
+            // them
+            self.key_repeat.down.borrow_mut().clear();
+        }
+        self.key_repeat.mode = mode;
+    }
+
+    pub fn key_repeat_filter(&self) -> RepeatMode {
+        self.key_repeat.mode.clone()
+    }
+}
+
+impl KeyDown {
+    /// Whether the key down was generated by auto-repeat. This is only ever
+    /// set with the window's repeat filter set to `RepeatMark`.
+    pub fn repeat(&self) -> bool {
+        self.event_flags().contains(EventFlagRepeat)
+    }
+}
+
+/// Whether input events of this type are needed to detect repeats
+pub fn wants_event(window: &Window, callback_type: ffi::Evas_Callback_Type) -> bool {
+    window.key_repeat.mode != RepeatDeliver && key_callbacks().iter().any(|&(t, _)| t == callback_type)
+}
+
+/// Track the keys held down, and mark a key down as a repeat in its event
+/// flags if its key is already down. Returns `true` if the event should be
+/// dropped.
+pub unsafe fn filter(window: &Window, callback_type: ffi::Evas_Callback_Type, info: *mut libc::c_void) -> bool {
+    let state = &window.key_repeat;
+    if !wants_event(window, callback_type) {
+        return false;
+    }
+    let mut down = state.down.borrow_mut();
+    if callback_type == ffi::EVAS_CALLBACK_KEY_UP {
+        let keycode = (*(info as *const ffi::Evas_Event_Key_Up)).keycode;
+        down.retain(|&k| k != keycode);
+        return false;
+    }
+    let event = &mut *(info as *mut ffi::Evas_Event_Key_Down);
+    if !down.contains(&event.keycode) {
+        down.push(event.keycode);
+        return false;
+    }
+    if state.mode == RepeatMark {
+        event.event_flags |= EventFlagRepeat.bits();
+    }
+    state.mode == RepeatSuppress
+}
+
+/// Forget the keys held down when the window loses focus, as it won't see
+/// them being released.
+pub fn release_keys(window: &Window) {
+    window.key_repeat.down.borrow_mut().clear();
+}
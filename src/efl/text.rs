@@ -0,0 +1,89 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A stream of committed text, for text entry, separate from the raw key
+//! events.
+
+use libc;
+use std::mem;
+
+use ffi;
+use {Modifier, Window};
+
+/// Receives text typed into the window
+pub trait TextInputCallback {
+    fn call(&self, &Window, &str);
+}
+
+/// The modifiers that turn a key press into a shortcut rather than text
+static SHORTCUT_MODIFIERS: &'static [&'static str] = &["Control", "Alt", "Meta", "Hyper", "Super"];
+
+impl<'a> Window<'a> {
+    /// Set a callback that receives only the text the user types, once it
+    /// is final. Dead keys and other compose sequences are delivered as the
+    /// character they produce, text from the input method (see
+    /// `ime_set_enabled`) is delivered as it is committed, and control
+    /// characters and key presses with a shortcut modifier held are left to
+    /// the key callbacks. Key downs handled by a key down callback (see
+    /// `KeyDownCallback::respond`) produce no text. This is called in
+    /// addition to any input callbacks.
+    pub fn set_text_input_callback(&mut self, callback: Box<TextInputCallback>) -> Option<Box<TextInputCallback>> {
+        unsafe {
+            let data = self.data_ptr();
+            ffi::evas_object_event_callback_del(self.object, ffi::EVAS_CALLBACK_KEY_DOWN, Some(::key_down_callback));
+            ffi::evas_object_event_callback_add(self.object, ffi::EVAS_CALLBACK_KEY_DOWN, Some(::key_down_callback), data);
+        }
+        mem::replace(&mut self.text_input_callback, Some(callback))
+    }
+
+    /// Remove the text input callback. The extern callback unregisters
+    /// itself once nothing needs it.
+    pub fn unset_text_input_callback(&mut self) -> Option<Box<TextInputCallback>> {
+        self.text_input_callback.take()
+    }
+}
+
+/// Whether input events of this type are needed for text input
+pub fn wants_event(window: &Window, callback_type: ffi::Evas_Callback_Type) -> bool {
+    window.text_input_callback.is_some() && callback_type == ffi::EVAS_CALLBACK_KEY_DOWN
+}
+
+/// Pass the text produced by a key down, if any, to the text input callback.
+pub unsafe fn dispatch(window: &Window, callback_type: ffi::Evas_Callback_Type, info: *const libc::c_void) {
+    if !wants_event(window, callback_type) {
+        return;
+    }
+    let info = &*(info as *const ffi::Evas_Event_Key_Down);
+    if info.event_flags & ffi::EVAS_EVENT_FLAG_ON_HOLD != 0 {
+        return;
+    }
+    let modifiers = Modifier { ptr: info.modifiers as *const _ };
+    if SHORTCUT_MODIFIERS.iter().any(|name| modifiers.is_set(*name)) {
+        return;
+    }
+    // `string` is only set once a compose sequence is complete; `compose`
+    // holds the sequence in progress, which is not text yet
+    let string = match ffi::string_from_c_ptr(info.string) {
+        Some(string) => string,
+        None => return,
+    };
+    deliver(window, string.as_slice());
+}
+
+/// Pass committed text to the text input callback, without the control
+/// characters that some keys produce, such as for Return and BackSpace.
+pub fn deliver(window: &Window, text: &str) {
+    let text: String = text.chars().filter(|&c| c >= ' ' && c != '\x7f').collect();
+    if text.is_empty() {
+        return;
+    }
+    for callback in window.text_input_callback.iter() {
+        callback.call(window, text.as_slice());
+    }
+}
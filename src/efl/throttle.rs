@@ -0,0 +1,426 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Control over when a window renders: capping its frame rate, only
+//! rendering when something has changed, and pausing while it can't be seen.
+
+use libc;
+use std::cell::{Cell, RefCell};
+use std::ptr;
+
+use ecore;
+use ecore::{IdleCallback, IdleEnterer};
+use ffi;
+use {bound_window, Window};
+
+/// The render policies associated with a window
+pub struct ThrottleState {
+    max_fps: Cell<Option<f64>>,
+    /// The animator that renders the window
+    animator: Cell<*mut ffi::Ecore_Animator>,
+    /// The time since the last render, carried over between animator ticks
+    accumulated: Cell<f64>,
+    /// The loop time of the last animator tick
+    last_tick: Cell<Option<f64>>,
+    /// The animator frametime from before the cap lowered it, and the
+    /// frametime it was lowered to
+    frametime: Cell<Option<(f64, f64)>>,
+    /// The idle enterer that renders the window, if idle rendering is on
+    idle_render: RefCell<Option<IdleEnterer>>,
+    /// Whether a render has been requested with `request_render`
+    render_requested: Cell<bool>,
+    /// Whether rendering pauses while the window is iconified
+    pause_on_iconify: Cell<bool>,
+    /// Whether rendering pauses while the window is fully covered
+    pause_when_obscured: Cell<bool>,
+    /// Whether rendering is paused
+    paused: Cell<bool>,
+}
+
+impl ThrottleState {
+    pub fn new() -> ThrottleState {
+        ThrottleState {
+            max_fps: Cell::new(None),
+            animator: Cell::new(ptr::mut_null()),
+            accumulated: Cell::new(0.0),
+            last_tick: Cell::new(None),
+            frametime: Cell::new(None),
+            idle_render: RefCell::new(None),
+            render_requested: Cell::new(false),
+            pause_on_iconify: Cell::new(false),
+            pause_when_obscured: Cell::new(false),
+            paused: Cell::new(false),
+        }
+    }
+
+    fn stop(&self) {
+        let animator = self.animator.get();
+        if !animator.is_null() {
+            unsafe { ffi::ecore_animator_del(animator) };
+            self.animator.set(ptr::mut_null());
+        }
+    }
+
+    /// Lower the animator frametime to `frametime` if it is longer,
+    /// remembering what it was so that it can be restored
+    fn lower_frametime(&self, frametime: f64) {
+        let current = ecore::animator_frametime();
+        if current <= frametime { return }
+        let original = self.frametime.get().map_or(current, |(original, _)| original);
+        ecore::set_animator_frametime(frametime);
+        self.frametime.set(Some((original, frametime)));
+    }
+
+    /// Put back the animator frametime from before it was lowered, unless
+    /// something else has changed it since
+    fn restore_frametime(&self) {
+        for &(original, lowered) in self.frametime.get().iter() {
+            if ecore::animator_frametime() == lowered {
+                ecore::set_animator_frametime(original);
+            }
+        }
+        self.frametime.set(None);
+    }
+}
+
+impl Drop for ThrottleState {
+    fn drop(&mut self) {
+        self.stop();
+        self.restore_frametime();
+    }
+}
+
+/// Remove the animator and the idle enterer, which refer to the window's
+/// ecore_evas, before it is freed.
+pub fn release(window: &Window) {
+    window.throttle.stop();
+    *window.throttle.idle_render.borrow_mut() = None;
+}
+
+impl<'a> Window<'a> {
+    /// Cap the rate at which the window renders to `fps` frames per second,
+    /// or remove the cap with `None`. While capped, the window is switched
+    /// to manual rendering and rendered from an ecore animator, which skips
+    /// ticks until a whole frame interval has passed since the last render.
+    ///
+    /// The animator can't tick faster than ecore's animator frametime, so if
+    /// that is longer than the interval of the cap it is lowered to match.
+    /// The frametime is shared by every animator in the process. It is
+    /// restored when the cap is removed or the window is dropped, unless it
+    /// has been changed again in the meantime.
+    pub fn set_max_fps(&self, fps: Option<f64>) {
+        let state = &self.throttle;
+        state.stop();
+        state.max_fps.set(fps);
+        match fps {
+            Some(fps) if fps > 0.0 => {
+                state.lower_frametime(1.0 / fps);
+                state.accumulated.set(0.0);
+                state.last_tick.set(None);
+                let animator = unsafe { ffi::ecore_animator_add(Some(animator_tick), self.ee as *const _) };
+                if state.paused.get() {
+                    unsafe { ffi::ecore_animator_freeze(animator) };
+                }
+                state.animator.set(animator);
+            },
+            _ => {
+                state.max_fps.set(None);
+                state.restore_frametime();
+            },
+        }
+        self.update_manual_render();
+    }
+
+    pub fn get_max_fps(&self) -> Option<f64> {
+        self.throttle.max_fps.get()
+    }
+
+    /// Only render the window when something has changed. While enabled,
+    /// the window is switched to manual rendering, and rendered as the main
+    /// loop goes idle if damage has been added with `add_damage` or a render
+    /// has been requested with `request_render` since the last render.
+    /// Changes made to canvas objects don't count by themselves, so they
+    /// need to be accompanied by one of these.
+    pub fn set_idle_render(&self, on: bool) {
+        {
+            let mut idle_render = self.throttle.idle_render.borrow_mut();
+            if on == idle_render.is_some() { return }
+            if on {
+                *idle_render = Some(IdleEnterer::add(box IdleRender { ee: self.ee as *const _ } as Box<IdleCallback>));
+            } else {
+                *idle_render = None;
+            }
+        }
+        self.update_manual_render();
+    }
+
+    pub fn is_idle_render(&self) -> bool {
+        self.throttle.idle_render.borrow().is_some()
+    }
+
+    /// Ask for the window to be rendered the next time the main loop goes
+    /// idle, such as from an animator. This only matters with idle rendering
+    /// on (see `set_idle_render`).
+    pub fn request_render(&self) {
+        self.throttle.render_requested.set(true);
+    }
+
+    /// Stop rendering the window while it is iconified, and resume when it
+    /// is restored. While paused the window is switched to manual rendering,
+    /// the frame rate cap's animator is frozen and idle rendering is held
+    /// back; a render is requested on resuming so that the window is brought
+    /// up to date. Explicit calls to `manual_render` still render.
+    pub fn set_pause_on_iconify(&self, on: bool) {
+        self.throttle.pause_on_iconify.set(on);
+        update_pause(self);
+    }
+
+    pub fn is_pause_on_iconify(&self) -> bool {
+        self.throttle.pause_on_iconify.get()
+    }
+
+    /// Stop rendering the window while it is fully covered by other windows
+    /// (see `is_obscured`), and resume when any of it is uncovered, in the
+    /// same way as `set_pause_on_iconify`. Engines that can't report
+    /// occlusion never pause.
+    pub fn set_pause_when_obscured(&self, on: bool) {
+        self.throttle.pause_when_obscured.set(on);
+        update_pause(self);
+    }
+
+    pub fn is_pause_when_obscured(&self) -> bool {
+        self.throttle.pause_when_obscured.get()
+    }
+
+    /// Whether rendering is paused, such as while the window is iconified
+    /// (see `set_pause_on_iconify`) or obscured (see
+    /// `set_pause_when_obscured`).
+    pub fn is_render_paused(&self) -> bool {
+        self.throttle.paused.get()
+    }
+
+    /// Render manually if anything in this module drives rendering, or it
+    /// is paused, and let ecore_evas render otherwise. The frame rate cap,
+    /// idle rendering and pausing all go through here rather than setting
+    /// manual rendering themselves, so that turning one of them off doesn't
+    /// undo the others.
+    fn update_manual_render(&self) {
+        let state = &self.throttle;
+        let manual = state.paused.get() || state.max_fps.get().is_some() || state.idle_render.borrow().is_some();
+        self.set_manual_render(manual);
+    }
+}
+
+/// Pause or resume rendering to match the window's state. Called on every
+/// state change, and whenever the window's occlusion changes.
+pub fn update_pause(window: &Window) {
+    let state = &window.throttle;
+    let paused = (state.pause_on_iconify.get() && window.state.get().iconified) ||
+                 (state.pause_when_obscured.get() && window.is_obscured());
+    if paused == state.paused.get() { return }
+    state.paused.set(paused);
+    let animator = state.animator.get();
+    if !animator.is_null() {
+        unsafe {
+            if paused { ffi::ecore_animator_freeze(animator) } else { ffi::ecore_animator_thaw(animator) }
+        }
+    }
+    if !paused {
+        state.render_requested.set(true);
+        state.last_tick.set(None);
+    }
+    window.update_manual_render();
+}
+
+/// Renders a window as the main loop goes idle, if it needs it
+struct IdleRender {
+    ee: *const ffi::Ecore_Evas,
+}
+
+impl IdleCallback for IdleRender {
+    fn call(&self) -> bool {
+        for window in unsafe { bound_window(self.ee) }.iter() {
+            let state = &window.throttle;
+            if state.paused.get() { continue }
+            if state.render_requested.get() || !window.damage.borrow().is_empty() {
+                state.render_requested.set(false);
+                window.manual_render();
+            }
+        }
+        true
+    }
+}
+
+extern "C" fn animator_tick(data: *mut libc::c_void) -> ffi::Eina_Bool {
+    ::guard_callback("animator_tick", ffi::EINA_TRUE, || {
+        let window = match unsafe { bound_window(data as *const _) } {
+            Some(window) => window,
+            None => return ffi::EINA_TRUE,
+        };
+        let state = &window.throttle;
+        let fps = match state.max_fps.get() {
+            Some(fps) => fps,
+            None => return ffi::EINA_TRUE,
+        };
+        let interval = 1.0 / fps;
+        let now = ecore::loop_time();
+        let elapsed = state.last_tick.get().map_or(interval, |last| now - last);
+        state.last_tick.set(Some(now));
+        let (render, accumulated) = accumulate(state.accumulated.get(), elapsed, interval);
+        state.accumulated.set(accumulated);
+        if render {
+            window.manual_render();
+        }
+        ffi::EINA_TRUE
+    })
+}
+
+/// Add the `elapsed` time since the last animator tick to the time
+/// `accumulated` towards the next frame of a window capped to frames of
+/// `interval` seconds. Returns whether to render on this tick, and the time
+/// to carry over to the next.
+fn accumulate(accumulated: f64, elapsed: f64, interval: f64) -> (bool, f64) {
+    let accumulated = accumulated + elapsed;
+    if accumulated < interval {
+        return (false, accumulated);
+    }
+    // Carry over the remainder to keep the average rate, but drop any whole
+    // frames missed, so that a stall isn't followed by a burst of renders
+    (true, (accumulated - interval) % interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use ecore;
+    use ecore::IdleCallback;
+    use ffi;
+    use {init, Window, WindowState};
+    use super::{accumulate, update_pause, IdleRender};
+
+    /// Set whether the window's cached state says it is iconified, as a
+    /// state change would, and update the pause to match
+    fn set_iconified(window: &Window, iconified: bool) {
+        window.state.set(WindowState { iconified: iconified, ..window.state.get() });
+        update_pause(window);
+    }
+
+    /// Tick every animator once, and say whether the window's ran
+    fn animator_ticks(window: &Window) -> bool {
+        window.throttle.last_tick.set(None);
+        unsafe { ffi::ecore_animator_custom_tick() };
+        window.throttle.last_tick.get().is_some()
+    }
+
+    /// The number of renders from animator ticks at `rate` Hz for `seconds`,
+    /// under a cap of `fps`
+    fn renders(rate: f64, fps: f64, seconds: uint) -> uint {
+        let mut accumulated = 0.0;
+        let mut renders = 0;
+        for _ in range(0, (rate * seconds as f64) as uint) {
+            let (render, carried) = accumulate(accumulated, 1.0 / rate, 1.0 / fps);
+            accumulated = carried;
+            if render { renders += 1 }
+        }
+        renders
+    }
+
+    #[test]
+    fn cap_limits_render_rate() {
+        let one = renders(144.0, 60.0, 1);
+        assert!(one >= 59 && one <= 60, "{} renders in a second", one);
+        let ten = renders(144.0, 60.0, 10);
+        assert!(ten >= 599 && ten <= 600, "{} renders in ten seconds", ten);
+        // Ticks slower than the cap render every time
+        assert_eq!(renders(30.0, 60.0, 1), 30);
+    }
+
+    #[test]
+    fn stall_does_not_burst() {
+        let (render, carried) = accumulate(0.0, 1.0, 1.0 / 60.0);
+        assert!(render);
+        assert!(carried < 1.0 / 60.0);
+        let (render, _) = accumulate(carried, 0.0, 1.0 / 60.0);
+        assert!(!render);
+    }
+
+    #[test]
+    fn cap_restores_frametime() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        let original = ecore::animator_frametime();
+        ecore::set_animator_frametime(1.0 / 30.0);
+        window.set_max_fps(Some(60.0));
+        assert_eq!(ecore::animator_frametime(), 1.0 / 60.0);
+        window.set_max_fps(Some(120.0));
+        assert_eq!(ecore::animator_frametime(), 1.0 / 120.0);
+        window.set_max_fps(None);
+        assert_eq!(ecore::animator_frametime(), 1.0 / 30.0);
+        ecore::set_animator_frametime(original);
+    }
+
+    #[test]
+    fn pause_freezes_the_cap_animator() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        unsafe { ffi::ecore_animator_source_set(ffi::ECORE_ANIMATOR_SOURCE_CUSTOM) };
+        window.set_max_fps(Some(60.0));
+        window.set_pause_on_iconify(true);
+        assert!(animator_ticks(&window));
+        set_iconified(&window, true);
+        assert!(window.is_render_paused());
+        assert!(!animator_ticks(&window));
+        set_iconified(&window, false);
+        assert!(!window.is_render_paused());
+        assert!(animator_ticks(&window));
+        unsafe { ffi::ecore_animator_source_set(ffi::ECORE_ANIMATOR_SOURCE_TIMER) };
+    }
+
+    #[test]
+    fn pause_switches_to_manual_render() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        window.set_pause_on_iconify(true);
+        assert!(!window.is_manual_render());
+        set_iconified(&window, true);
+        assert!(window.is_manual_render());
+        assert!(!window.throttle.render_requested.get());
+        set_iconified(&window, false);
+        assert!(!window.is_manual_render());
+        // The window is brought up to date on resuming
+        assert!(window.throttle.render_requested.get());
+    }
+
+    #[test]
+    fn idle_render_skips_paused_windows() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        let idle_render = IdleRender { ee: window.ee as *const _ };
+        window.set_idle_render(true);
+        window.set_pause_on_iconify(true);
+        set_iconified(&window, true);
+        window.request_render();
+        idle_render.call();
+        assert!(window.throttle.render_requested.get());
+        set_iconified(&window, false);
+        idle_render.call();
+        assert!(!window.throttle.render_requested.get());
+    }
+
+    #[test]
+    fn cap_and_idle_render_share_manual_render() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        window.set_max_fps(Some(60.0));
+        window.set_idle_render(true);
+        window.set_max_fps(None);
+        assert!(window.is_manual_render());
+        window.set_idle_render(false);
+        assert!(!window.is_manual_render());
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Aggregation of the per-finger multi-touch events into a single stream.
+
+use libc;
+use std::cell::RefCell;
+use std::mem;
+
+use ffi;
+use Window;
+
+/// A finger touching the window
+#[deriving(Clone, PartialEq, Show)]
+pub struct TouchPoint {
+    /// The device id of the touch. This is 0 for the first finger down,
+    /// which Evas reports as mouse events, and the multi-touch device id for
+    /// the others.
+    pub id: i32,
+    /// The position of the touch on the canvas, at sub-pixel precision
+    /// where available.
+    pub x: f64,
+    pub y: f64,
+    /// The pressure of the touch, or 1 if the device doesn't report it.
+    pub pressure: f64,
+    /// The radius of the touch, or 0 if the device doesn't report it.
+    pub radius: f64,
+}
+
+/// A change to one of the touch points
+#[deriving(Clone, PartialEq, Show)]
+pub enum TouchEvent {
+    TouchBegan(TouchPoint),
+    TouchMoved(TouchPoint),
+    TouchEnded(TouchPoint),
+}
+
+/// Receives every change to the touch points of a window, along with all of
+/// the points touching the window after the change.
+pub trait TouchHandler {
+    fn call(&self, &Window, TouchEvent, &[TouchPoint]);
+}
+
+/// The touch points and touch handler associated with a window
+pub struct TouchState {
+    handler: Option<Box<TouchHandler>>,
+    /// The points currently touching the window, in the order they began
+    points: RefCell<Vec<TouchPoint>>,
+}
+
+impl TouchState {
+    pub fn new() -> TouchState {
+        TouchState {
+            handler: None,
+            points: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+/// The input events that touches are assembled from, with their extern
+/// callbacks
+fn touch_callbacks() -> [(ffi::Evas_Callback_Type, ffi::Evas_Object_Event_Cb), ..6] {
+    [
+        (ffi::EVAS_CALLBACK_MOUSE_DOWN, Some(::mouse_down_callback)),
+        (ffi::EVAS_CALLBACK_MOUSE_UP, Some(::mouse_up_callback)),
+        (ffi::EVAS_CALLBACK_MOUSE_MOVE, Some(::mouse_move_callback)),
+        (ffi::EVAS_CALLBACK_MULTI_DOWN, Some(::multi_down_callback)),
+        (ffi::EVAS_CALLBACK_MULTI_UP, Some(::multi_up_callback)),
+        (ffi::EVAS_CALLBACK_MULTI_MOVE, Some(::multi_move_callback)),
+    ]
+}
+
+impl<'a> Window<'a> {
+    /// Set a handler that receives the window's touches as a single stream
+    /// of events, assembled from the mouse and multi-touch events. Evas
+    /// reports the first finger down as mouse events, so presses of the
+    /// left mouse button are also reported as touch point 0. This is
+    /// called in addition to any input callbacks.
+    pub fn set_touch_handler(&mut self, handler: Box<TouchHandler>) -> Option<Box<TouchHandler>> {
+        unsafe {
+            let data = self.data_ptr();
+            for &(callback_type, callback) in touch_callbacks().iter() {
+                ffi::evas_object_event_callback_del(self.object, callback_type, callback);
+                ffi::evas_object_event_callback_add(self.object, callback_type, callback, data);
+            }
+        }
+        mem::replace(&mut self.touch.handler, Some(handler))
+    }
+
+    /// Remove the touch handler, and forget the current touch points. The
+    /// extern callbacks unregister themselves once nothing needs them.
+    pub fn unset_touch_handler(&mut self) -> Option<Box<TouchHandler>> {
+        self.touch.points.borrow_mut().clear();
+        self.touch.handler.take()
+    }
+
+    /// The points currently touching the window.
+    pub fn touch_points(&self) -> Vec<TouchPoint> {
+        self.touch.points.borrow().clone()
+    }
+}
+
+/// Whether input events of this type need to be seen by the touch handler
+pub fn wants_event(window: &Window, callback_type: ffi::Evas_Callback_Type) -> bool {
+    window.touch.handler.is_some() && touch_callbacks().iter().any(|&(t, _)| t == callback_type)
+}
+
+/// Update the window's touch points with an input event, and pass the
+/// change to the touch handler.
+pub unsafe fn dispatch(window: &Window, callback_type: ffi::Evas_Callback_Type, info: *const libc::c_void) {
+    if !wants_event(window, callback_type) {
+        return;
+    }
+    let point = |id: libc::c_int, point: ffi::Evas_Coord_Precision_Point,
+                 pressure: libc::c_double, radius: libc::c_double| TouchPoint {
+        id: id as i32, x: point.xsub as f64, y: point.ysub as f64,
+        pressure: pressure as f64, radius: radius as f64,
+    };
+    let mouse_point = |point: ffi::Evas_Coord_Point| TouchPoint {
+        id: 0, x: point.x as f64, y: point.y as f64, pressure: 1.0, radius: 0.0,
+    };
+    let event = if callback_type == ffi::EVAS_CALLBACK_MOUSE_DOWN {
+        let info = &*(info as *const ffi::Evas_Event_Mouse_Down);
+        if info.button != 1 { return }
+        TouchBegan(mouse_point(info.canvas))
+    } else if callback_type == ffi::EVAS_CALLBACK_MOUSE_UP {
+        let info = &*(info as *const ffi::Evas_Event_Mouse_Up);
+        if info.button != 1 { return }
+        TouchEnded(mouse_point(info.canvas))
+    } else if callback_type == ffi::EVAS_CALLBACK_MOUSE_MOVE {
+        let info = &*(info as *const ffi::Evas_Event_Mouse_Move);
+        TouchMoved(mouse_point(info.cur.canvas))
+    } else if callback_type == ffi::EVAS_CALLBACK_MULTI_DOWN {
+        let info = &*(info as *const ffi::Evas_Event_Multi_Down);
+        TouchBegan(point(info.device, info.canvas, info.pressure, info.radius))
+    } else if callback_type == ffi::EVAS_CALLBACK_MULTI_UP {
+        let info = &*(info as *const ffi::Evas_Event_Multi_Up);
+        TouchEnded(point(info.device, info.canvas, info.pressure, info.radius))
+    } else {
+        let info = &*(info as *const ffi::Evas_Event_Multi_Move);
+        TouchMoved(point(info.device, info.cur.canvas, info.pressure, info.radius))
+    };
+
+    let points = {
+        let mut points = window.touch.points.borrow_mut();
+        match event {
+            TouchBegan(ref point) => {
+                points.retain(|p| p.id != point.id);
+                points.push(point.clone());
+            },
+            TouchMoved(ref point) => {
+                match points.mut_iter().find(|p| p.id == point.id) {
+                    Some(p) => *p = point.clone(),
+                    // Mouse moves without a button held are not touches
+                    None => return,
+                }
+            },
+            TouchEnded(ref point) => {
+                if !points.iter().any(|p| p.id == point.id) { return }
+                points.retain(|p| p.id != point.id);
+            },
+        }
+        points.clone()
+    };
+    for handler in window.touch.handler.iter() {
+        handler.call(window, event.clone(), points.as_slice());
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversion of the pointer coordinates delivered in input events.
+
+use libc;
+use std::mem;
+
+use ffi;
+use Window;
+
+/// A mapping from window coordinates into the user's own coordinate space,
+/// applied to the pointer coordinates delivered to input callbacks.
+pub trait CoordinateTransform {
+    /// Map a point in the window into the user's space.
+    fn call(&self, x: i32, y: i32) -> (i32, i32);
+    /// Map a point in the user's space back into the window. This is used
+    /// for coordinates the user passes in, such as to `warp_pointer`.
+    fn inverse(&self, x: i32, y: i32) -> (i32, i32);
+}
+
+/// An Evas event info struct, whose pointer coordinates can be rewritten
+/// before it is handed to the user
+pub trait EventCoords {
+    fn map_coords(&mut self, f: |f64, f64| -> (f64, f64));
+}
+
+fn map_point(point: &mut ffi::Evas_Point, f: |f64, f64| -> (f64, f64)) {
+    let (x, y) = f(point.x as f64, point.y as f64);
+    point.x = x.round() as libc::c_int;
+    point.y = y.round() as libc::c_int;
+}
+
+fn map_coord_point(point: &mut ffi::Evas_Coord_Point, f: |f64, f64| -> (f64, f64)) {
+    let (x, y) = f(point.x as f64, point.y as f64);
+    point.x = x.round() as ffi::Evas_Coord;
+    point.y = y.round() as ffi::Evas_Coord;
+}
+
+fn map_precision_point(point: &mut ffi::Evas_Coord_Precision_Point, f: |f64, f64| -> (f64, f64)) {
+    let (x, y) = f(point.xsub as f64, point.ysub as f64);
+    point.xsub = x as libc::c_double;
+    point.ysub = y as libc::c_double;
+    point.x = x.floor() as ffi::Evas_Coord;
+    point.y = y.floor() as ffi::Evas_Coord;
+}
+
+fn map_position(position: &mut ffi::Evas_Position, f: |f64, f64| -> (f64, f64)) {
+    map_point(&mut position.output, |x, y| f(x, y));
+    map_coord_point(&mut position.canvas, |x, y| f(x, y));
+}
+
+fn map_precision_position(position: &mut ffi::Evas_Precision_Position, f: |f64, f64| -> (f64, f64)) {
+    map_point(&mut position.output, |x, y| f(x, y));
+    map_precision_point(&mut position.canvas, |x, y| f(x, y));
+}
+
+macro_rules! event_coords {
+    ($($Evas_Event_Info:ty { $($field:ident: $map:ident),+ }),+) => {
+        $(impl EventCoords for $Evas_Event_Info {
+            fn map_coords(&mut self, f: |f64, f64| -> (f64, f64)) {
+                $($map(&mut self.$field, |x, y| f(x, y));)+
+            }
+        })+
+    }
+}
+
+event_coords! {
+    ffi::Evas_Event_Mouse_Down  { output: map_point, canvas: map_coord_point },
+    ffi::Evas_Event_Mouse_Up    { output: map_point, canvas: map_coord_point },
+    ffi::Evas_Event_Mouse_In    { output: map_point, canvas: map_coord_point },
+    ffi::Evas_Event_Mouse_Out   { output: map_point, canvas: map_coord_point },
+    ffi::Evas_Event_Mouse_Move  { cur: map_position, prev: map_position },
+    ffi::Evas_Event_Mouse_Wheel { output: map_point, canvas: map_coord_point },
+    ffi::Evas_Event_Multi_Down  { output: map_point, canvas: map_precision_point },
+    ffi::Evas_Event_Multi_Up    { output: map_point, canvas: map_precision_point },
+    ffi::Evas_Event_Multi_Move  { cur: map_precision_position }
+}
+
+// Events without pointer coordinates
+
+impl EventCoords for ffi::Evas_Event_Key_Down {
+    fn map_coords(&mut self, _f: |f64, f64| -> (f64, f64)) {}
+}
+
+impl EventCoords for ffi::Evas_Event_Key_Up {
+    fn map_coords(&mut self, _f: |f64, f64| -> (f64, f64)) {}
+}
+
+impl EventCoords for ffi::Evas_Event_Hold {
+    fn map_coords(&mut self, _f: |f64, f64| -> (f64, f64)) {}
+}
+
+/// Convert the pointer coordinates of an input event into the units the
+/// window's input callbacks expect. Letterboxing is undone first, then the
+/// content scale is applied, then the coordinate transform.
+pub fn map_event_coords<T: EventCoords>(window: &Window, info: &mut T) {
+    for &(content_w, content_h) in window.letterbox.get().iter() {
+        let image = ::image_geometry(window);
+        if image.w > 0 && image.h > 0 {
+            let (sx, sy) = (content_w as f64 / image.w as f64, content_h as f64 / image.h as f64);
+            info.map_coords(|x, y| ((x - image.x as f64) * sx, (y - image.y as f64) * sy));
+        }
+    }
+    if window.logical_coords.get() {
+        let scale = window.content_scale.get();
+        info.map_coords(|x, y| (x / scale, y / scale));
+    }
+    for transform in window.coordinate_transform.iter() {
+        info.map_coords(|x, y| {
+            // Keep the fractional part of precise coordinates
+            let (tx, ty) = transform.call(x.floor() as i32, y.floor() as i32);
+            (tx as f64 + x - x.floor(), ty as f64 + y - y.floor())
+        });
+    }
+}
+
+/// The inverse of `map_event_coords`, for coordinates passed in by the user.
+pub fn unmap_coords(window: &Window, x: i32, y: i32) -> (i32, i32) {
+    let (x, y) = match window.coordinate_transform {
+        Some(ref transform) => transform.inverse(x, y),
+        None => (x, y),
+    };
+    let (x, y) = if window.logical_coords.get() {
+        let scale = window.content_scale.get();
+        ((x as f64 * scale).round() as i32, (y as f64 * scale).round() as i32)
+    } else {
+        (x, y)
+    };
+    match window.letterbox.get() {
+        Some((content_w, content_h)) => {
+            let image = ::image_geometry(window);
+            (image.x + (x as f64 * image.w as f64 / content_w as f64).round() as i32,
+             image.y + (y as f64 * image.h as f64 / content_h as f64).round() as i32)
+        },
+        None => (x, y),
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Set the number of device pixels per logical pixel. Rendering and the
+    /// image data of the window are always in device pixels, so this only
+    /// affects `logical_size`, and the pointer coordinates delivered to
+    /// input callbacks if `set_logical_coords` is enabled. Defaults to 1.
+    pub fn set_content_scale(&self, scale: f64) {
+        assert!(scale > 0.0);
+        self.content_scale.set(scale);
+    }
+
+    pub fn content_scale(&self) -> f64 {
+        self.content_scale.get()
+    }
+
+    /// The size of the window in logical pixels.
+    pub fn logical_size(&self) -> (f64, f64) {
+        let (w, h) = self.get_size();
+        let scale = self.content_scale.get();
+        (w as f64 / scale, h as f64 / scale)
+    }
+
+    /// Whether input callbacks receive pointer coordinates in logical pixels
+    /// rather than device pixels. Disabled by default.
+    pub fn set_logical_coords(&self, on: bool) {
+        self.logical_coords.set(on);
+    }
+
+    pub fn logical_coords(&self) -> bool {
+        self.logical_coords.get()
+    }
+
+    /// Set a transform to apply to the pointer coordinates delivered to input
+    /// callbacks, after any content scaling. Both the `output` and `canvas`
+    /// coordinates of every event are transformed.
+    pub fn set_coordinate_transform(&mut self, transform: Box<CoordinateTransform>)
+                                    -> Option<Box<CoordinateTransform>> {
+        mem::replace(&mut self.coordinate_transform, Some(transform))
+    }
+
+    pub fn unset_coordinate_transform(&mut self) -> Option<Box<CoordinateTransform>> {
+        self.coordinate_transform.take()
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2014 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tracking whether a window is fully covered by other windows. This is only
+//! implemented for the X11 engines, from the X server's visibility events.
+
+use std::cell::{Cell, RefCell};
+
+use ecore::EventHandler;
+use Window;
+
+/// The occlusion state associated with a window
+pub struct VisibilityState {
+    obscured: Cell<bool>,
+    /// The handler for the visibility events of the window, if its engine
+    /// reports them
+    handler: RefCell<Option<EventHandler>>,
+}
+
+impl VisibilityState {
+    pub fn new() -> VisibilityState {
+        VisibilityState {
+            obscured: Cell::new(false),
+            handler: RefCell::new(None),
+        }
+    }
+}
+
+impl<'a> Window<'a> {
+    /// Whether the window is fully covered by other windows, so that nothing
+    /// it renders can be seen. Only the X11 engines report this; with other
+    /// engines a window is never obscured, so it is always treated as
+    /// visible. The same goes for X11 under a compositing window manager,
+    /// which redirects windows offscreen, so the X server reports every one
+    /// of them as unobscured. Visibility events are followed from when the
+    /// window is created, as that is when it is bound to its ecore_evas (see
+    /// `bound_window`), and the window reads as unobscured until the first
+    /// one arrives.
+    pub fn is_obscured(&self) -> bool {
+        self.visibility.obscured.get()
+    }
+}
+
+/// Start tracking the occlusion of a new window.
+pub fn register(window: &Window) {
+    *window.visibility.handler.borrow_mut() = imp::handler(window);
+}
+
+/// Stop tracking the occlusion of a window, before its ecore_evas is freed.
+pub fn unregister(window: &Window) {
+    *window.visibility.handler.borrow_mut() = None;
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod imp {
+    use libc;
+
+    use ecore::{EventHandler, EventHandlerCallback};
+    use ffi;
+    use ffi::x11;
+    use throttle;
+    use {bound_window, Window};
+
+    /// Updates the occlusion of a window from its visibility events
+    struct VisibilityChange {
+        ee: *const ffi::Ecore_Evas,
+    }
+
+    impl EventHandlerCallback for VisibilityChange {
+        fn call(&self, event: *mut libc::c_void) -> bool {
+            for window in unsafe { bound_window(self.ee) }.iter() {
+                let event = unsafe { &*(event as *const x11::Ecore_X_Event_Window_Visibility_Change) };
+                if Some(event.win as u32) != window.x11_window() { continue }
+                let obscured = event.fully_obscured != 0;
+                if obscured != window.visibility.obscured.get() {
+                    window.visibility.obscured.set(obscured);
+                    throttle::update_pause(window);
+                }
+            }
+            true
+        }
+    }
+
+    pub fn handler(window: &Window) -> Option<EventHandler> {
+        if window.x11_window().is_none() { return None }
+        let callback = box VisibilityChange { ee: window.ee as *const _ };
+        Some(EventHandler::add(x11::ECORE_X_EVENT_WINDOW_VISIBILITY_CHANGE, callback as Box<EventHandlerCallback>))
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+mod imp {
+    use ecore::EventHandler;
+    use Window;
+
+    pub fn handler(_window: &Window) -> Option<EventHandler> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use init;
+    use throttle;
+
+    #[test]
+    fn obscured_window_pauses() {
+        let context = init().unwrap();
+        let window = context.build_buffer_window(100, 100).create().unwrap();
+        window.set_pause_when_obscured(true);
+        // The buffer engine can't report occlusion, so it never pauses by
+        // itself
+        assert!(!window.is_obscured());
+        assert!(!window.is_render_paused());
+        window.visibility.obscured.set(true);
+        throttle::update_pause(&window);
+        assert!(window.is_render_paused());
+        assert!(window.is_manual_render());
+        window.visibility.obscured.set(false);
+        throttle::update_pause(&window);
+        assert!(!window.is_render_paused());
+        assert!(!window.is_manual_render());
+        // Only obscured windows pause
+        window.set_pause_when_obscured(false);
+        window.visibility.obscured.set(true);
+        throttle::update_pause(&window);
+        assert!(!window.is_render_paused());
+    }
+}
@@ -16,7 +16,7 @@ struct OnKeyDown;
 impl efl::KeyDownCallback for OnKeyDown {
     fn call(&self, window: &efl::Window, info: &efl::KeyDown) {
         println!("KEY: {}", info.keyname());
-        window.set_title(format!("key pressed(time: {})", info.timestamp()).as_slice());
+        window.set_title(format!("key pressed(time: {})", info.timestamp().millis()).as_slice());
     }
 }
 
@@ -11,3 +11,5 @@
 #include <Evas.h>
 #include <Evas_GL.h>
 #include <Ecore_Evas.h>
+#include <Ecore_IMF.h>
+#include <Ecore_IMF_Evas.h>